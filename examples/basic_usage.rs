@@ -19,7 +19,7 @@
 
 use inquire::Select;
 use log::info;
-use m18_protocol::{OutputFormat, Result, M18};
+use m18_protocol::{ChargerProfile, OutputFormat, Register, Result, M18};
 use std::time::Duration;
 
 /// Interactive serial port selection using inquire
@@ -38,12 +38,7 @@ fn select_port() -> Result<String> {
 
     let selection = Select::new("Select a serial port:", port_names)
         .prompt()
-        .map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Selection cancelled: {}", e),
-            )
-        })?;
+        .map_err(|e| std::io::Error::other(format!("Selection cancelled: {}", e)))?;
 
     // Extract just the port name (before " - ")
     let port_name = selection.split(" - ").next().unwrap().to_string();
@@ -58,7 +53,7 @@ fn main() -> Result<()> {
     let port_name = std::env::args()
         .nth(1)
         .map(Ok)
-        .unwrap_or_else(|| select_port())?;
+        .unwrap_or_else(select_port)?;
 
     info!("Connecting to M18 battery on {}...", port_name);
     let mut m18 = M18::new(&port_name)?;
@@ -70,7 +65,7 @@ fn main() -> Result<()> {
     info!("=== Basic Battery Operations ===");
 
     // Reset and check connection
-    if m18.reset()? {
+    if m18.reset()?.is_synced() {
         info!("✓ Battery connection established");
     } else {
         info!("✗ Failed to establish battery connection");
@@ -79,12 +74,16 @@ fn main() -> Result<()> {
 
     // Read a few key registers
     info!("=== Reading Key Registers ===");
-    let key_registers = vec![2, 4, 12]; // Serial, Manufacture date, Cell voltages
+    let key_registers = [
+        Register::SerialNumber,
+        Register::ManufactureDate,
+        Register::CellVoltages,
+    ];
     m18.print_registers(&key_registers, OutputFormat::Label, true)?;
 
     // Test charger simulation for 5 seconds
     info!("=== Charger Simulation Test ===");
-    m18.simulate_for(Duration::from_secs(5))?;
+    m18.simulate_for(Duration::from_secs(5), &ChargerProfile::standard())?;
 
     // // Write a test message to battery memory
     // info!("=== Writing Test Message ===");
@@ -92,7 +91,7 @@ fn main() -> Result<()> {
 
     // Read the message back
     info!("Reading message back...");
-    m18.print_registers(&[7], OutputFormat::Label, true)?; // Register 7 is the note field
+    m18.print_registers(&[Register::Note], OutputFormat::Label, true)?;
 
     info!("=== Basic Usage Complete ===");
 
@@ -35,12 +35,7 @@ fn select_port() -> Result<String> {
 
     let selection = Select::new("Select a serial port:", port_names)
         .prompt()
-        .map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Selection cancelled: {}", e),
-            )
-        })?;
+        .map_err(|e| std::io::Error::other(format!("Selection cancelled: {}", e)))?;
 
     // Extract just the port name (before " - ")
     let port_name = selection.split(" - ").next().unwrap().to_string();
@@ -55,7 +50,7 @@ fn main() -> Result<()> {
     let port_name = std::env::args()
         .nth(1)
         .map(Ok)
-        .unwrap_or_else(|| select_port())?;
+        .unwrap_or_else(select_port)?;
 
     info!("Connecting to M18 battery on {}...", port_name);
     let mut m18 = M18::new(&port_name)?;
@@ -87,10 +82,10 @@ fn main() -> Result<()> {
             info!("Description: {}", report.battery_description);
             info!("Pack Voltage: {:.2}V", report.pack_voltage);
             info!("Cell Imbalance: {}mV", report.cell_imbalance);
-            info!(
-                "Total Discharge: {:.2}Ah",
-                report.usage_stats.total_discharge_ah
-            );
+            match report.usage_stats.total_discharge_ah {
+                Some(ah) => info!("Total Discharge: {:.2}Ah", ah),
+                None => info!("Total Discharge: unknown"),
+            }
 
             // Export to JSON (requires serde feature)
             if let Ok(json) = serde_json::to_string_pretty(&report) {
@@ -0,0 +1,39 @@
+//! Virtual Battery Example
+//!
+//! This example serves a software M18 battery on a pseudo-terminal so the
+//! other examples can be run without any hardware attached:
+//! - Creates a virtual battery with a sample memory image
+//! - Exposes it on a new pseudo-terminal
+//! - Answers requests until the process is stopped (Ctrl+C)
+//!
+//! Usage:
+//!   cargo run --example virtual_battery
+//!
+//! Then, in another terminal, point an example at the printed path:
+//!   cargo run --example health_report -- /dev/pts/3
+//!
+//! Only available on Unix-like systems.
+
+#[cfg(unix)]
+fn main() -> m18_protocol::Result<()> {
+    use log::info;
+    use m18_protocol::emulator::VirtualBattery;
+
+    // Initialize logger with default info level if RUST_LOG is not set
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let battery = VirtualBattery::new();
+    let server = battery.serve_pty()?;
+
+    info!("Virtual battery listening on {}", server.path());
+    info!("Press Ctrl+C to stop");
+
+    loop {
+        std::thread::park();
+    }
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("The virtual battery example requires a Unix-like system.");
+}
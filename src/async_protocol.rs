@@ -0,0 +1,1060 @@
+//! Asynchronous M18 protocol implementation.
+//!
+//! This module provides [`AsyncM18`], a tokio-based counterpart to the blocking
+//! [`M18`](crate::M18). It performs the same operations but waits with
+//! `tokio::time::sleep` instead of blocking the thread, so it can run inside an
+//! async service. Requires the `async` feature.
+//!
+//! The ACC sequence, reply lengths, retry decisions, echo checks and charger
+//! handshake steps come from the same code as [`M18`](crate::M18), and take
+//! the same [`Timings`] and [`RetryPolicy`]. Set them with
+//! [`AsyncM18::set_timings`], [`AsyncM18::set_retry_policy`] and
+//! [`AsyncM18::set_echo_cancellation`]. The operations built on them are
+//! fewer: there is no memory image capture, scan or
+//! [`ChargeEvent`](crate::charge_session::ChargeEvent) reporting.
+//!
+//! Every operation can be cancelled by dropping its future (for example with
+//! `tokio::time::timeout` or `tokio::select!`). Operations hold an
+//! [`AsyncSession`] while J2 is high, so J2 is returned to idle when the
+//! future is dropped.
+
+use crate::builder::Timings;
+use crate::constants::*;
+use crate::data::RegisterMap;
+use crate::error::{M18Error, Result};
+use crate::exchange::{self, HandshakeStep, Retries, CHARGER_HANDSHAKE};
+use crate::frame::{self, RequestFrame, ResponseFrame};
+use crate::line_control::{ControlLines, LineControl};
+use crate::protocol::{
+    build_health_report, health_report_registers, requested_results, successful_values,
+    PlannedReplies,
+};
+use crate::read_plan::ReadPlan;
+use crate::register::RegisterSelector;
+use crate::retry::{RetryPolicy, RetryStats};
+use crate::session::AsyncSession;
+use crate::transport::is_pseudo_terminal;
+use crate::types::*;
+use chrono::Utc;
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::{sleep, timeout, Instant};
+use tokio_serial::{SerialPort, SerialPortBuilderExt, SerialStream};
+
+/// Run a command on an [`AsyncM18`] or [`AsyncSession`], retrying failures as
+/// its retry policy allows. The async counterpart of `M18::retrying`.
+macro_rules! retrying {
+    ($m18:ident, $command:expr) => {{
+        let mut retry = 0;
+        loop {
+            let error = match $command.await {
+                Ok(value) => {
+                    $m18.retries.succeeded();
+                    break Ok(value);
+                }
+                Err(e) => e,
+            };
+
+            match $m18.retries.failed(error, &mut retry) {
+                Ok(step) => {
+                    sleep(step.backoff).await;
+                    if step.resync && !$m18.resync().await {
+                        break Err(step.error);
+                    }
+                }
+                Err(error) => break Err(error),
+            }
+        }
+    }};
+}
+
+/// Asynchronous byte link to a battery, including J2 line control.
+///
+/// Byte I/O goes through `AsyncRead`/`AsyncWrite`. Line control is synchronous
+/// because it maps to a quick ioctl on real serial ports.
+pub trait AsyncTransport: AsyncRead + AsyncWrite + Unpin + Send {
+    /// Discard any bytes waiting in the receive buffer.
+    fn clear_input(&mut self) -> Result<()>;
+
+    /// Assert (`true`) or release (`false`) the break condition on TX.
+    fn set_break(&mut self, enabled: bool) -> Result<()>;
+
+    /// Drive the DTR line to the given level.
+    fn set_dtr(&mut self, level: bool) -> Result<()>;
+
+    /// Drive the RTS line to the given level.
+    ///
+    /// # Errors
+    /// The default implementation returns an `Unsupported` I/O error.
+    fn set_rts(&mut self, _level: bool) -> Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "RTS is not supported by this transport",
+        )
+        .into())
+    }
+}
+
+/// Control lines of an async transport, for [`LineControl`]
+struct AsyncLines<'a>(&'a mut dyn AsyncTransport);
+
+impl ControlLines for AsyncLines<'_> {
+    fn set_break(&mut self, enabled: bool) -> Result<()> {
+        self.0.set_break(enabled)
+    }
+
+    fn set_dtr(&mut self, level: bool) -> Result<()> {
+        self.0.set_dtr(level)
+    }
+
+    fn set_rts(&mut self, level: bool) -> Result<()> {
+        self.0.set_rts(level)
+    }
+}
+
+/// Async transport backed by a `tokio-serial` stream.
+pub struct AsyncSerialTransport {
+    /// Underlying serial stream
+    port: SerialStream,
+    /// Whether the port supports break and modem-control lines
+    line_control: bool,
+}
+
+impl AsyncSerialTransport {
+    /// Open a serial port configured for M18 communication (4800 baud, 2 stop bits).
+    ///
+    /// Must be called from within a tokio runtime.
+    ///
+    /// # Arguments
+    /// * `port_name` - Serial port name (e.g., "COM3" on Windows, "/dev/ttyUSB0" on Linux)
+    ///
+    /// # Errors
+    /// Returns error if serial port cannot be opened or configured.
+    pub fn open(port_name: &str) -> Result<Self> {
+        let port = tokio_serial::new(port_name, BAUD_RATE)
+            .stop_bits(STOP_BITS)
+            .open_native_async()?;
+        Ok(AsyncSerialTransport {
+            port,
+            line_control: !is_pseudo_terminal(port_name),
+        })
+    }
+
+    /// Wrap an already-opened serial stream.
+    pub fn new(port: SerialStream) -> Self {
+        AsyncSerialTransport {
+            port,
+            line_control: true,
+        }
+    }
+}
+
+impl AsyncRead for AsyncSerialTransport {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.port).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for AsyncSerialTransport {
+    fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        std::pin::Pin::new(&mut self.port).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.port).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.port).poll_shutdown(cx)
+    }
+}
+
+impl AsyncTransport for AsyncSerialTransport {
+    fn clear_input(&mut self) -> Result<()> {
+        self.port.clear(tokio_serial::ClearBuffer::Input)?;
+        Ok(())
+    }
+
+    fn set_break(&mut self, enabled: bool) -> Result<()> {
+        if !self.line_control {
+            return Ok(());
+        }
+        if enabled {
+            self.port.set_break()?;
+        } else {
+            self.port.clear_break()?;
+        }
+        Ok(())
+    }
+
+    fn set_dtr(&mut self, level: bool) -> Result<()> {
+        if !self.line_control {
+            return Ok(());
+        }
+        self.port.write_data_terminal_ready(level)?;
+        Ok(())
+    }
+
+    fn set_rts(&mut self, level: bool) -> Result<()> {
+        if !self.line_control {
+            return Ok(());
+        }
+        self.port.write_request_to_send(level)?;
+        Ok(())
+    }
+}
+
+/// Asynchronous M18 protocol interface.
+///
+/// Mirrors the blocking [`M18`](crate::M18) API with `async` methods.
+///
+/// # Examples
+/// ```no_run
+/// use m18_protocol::AsyncM18;
+///
+/// # async fn run() -> m18_protocol::Result<()> {
+/// let mut m18 = AsyncM18::new("/dev/ttyUSB0")?;
+/// let report = m18.health_report().await?;
+/// println!("Battery voltage: {:.2}V", report.pack_voltage);
+/// # Ok(())
+/// # }
+/// ```
+pub struct AsyncM18 {
+    /// Byte transport connected to the battery
+    port: Box<dyn AsyncTransport>,
+    /// Current accumulator value for command sequencing
+    acc: u8,
+    /// Whether to print transmitted data (for debugging)
+    print_tx: bool,
+    /// Whether to print received data (for debugging)
+    print_rx: bool,
+    /// Memory regions and register definitions
+    register_map: RegisterMap,
+    /// Longest read the read planner may send
+    max_read_length: u8,
+    /// Battery type lookup table
+    battery_lookup: HashMap<u16, BatteryType>,
+    /// How the adapter drives J2
+    line_control: LineControl,
+    /// Sessions currently open on this interface
+    open_sessions: u32,
+    /// Reset, response and keepalive delays
+    timings: Timings,
+    /// Retry policy and retry counters
+    retries: Retries,
+    /// Whether to read back and verify the echo of every write
+    echo_cancellation: bool,
+}
+
+impl AsyncM18 {
+    /// Create a new async M18 interface on the specified serial port.
+    ///
+    /// Must be called from within a tokio runtime.
+    ///
+    /// # Arguments
+    /// * `port_name` - Serial port name (e.g., "COM3" on Windows, "/dev/ttyUSB0" on Linux)
+    ///
+    /// # Errors
+    /// Returns error if serial port cannot be opened or configured.
+    pub fn new(port_name: &str) -> Result<Self> {
+        let transport = AsyncSerialTransport::open(port_name)?;
+        Ok(Self::with_transport(transport))
+    }
+
+    /// Create a new async M18 interface on top of an arbitrary async transport.
+    ///
+    /// # Arguments
+    /// * `transport` - Async byte transport connected to the battery
+    ///
+    /// # Examples
+    /// ```
+    /// use m18_protocol::{emulator::VirtualBattery, AsyncM18};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> m18_protocol::Result<()> {
+    /// let battery = VirtualBattery::new();
+    /// let mut m18 = AsyncM18::with_transport(battery.transport());
+    ///
+    /// let values = m18.read_registers(&[12], false).await?;
+    /// assert_eq!(values.len(), 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_transport(transport: impl AsyncTransport + 'static) -> Self {
+        let mut m18 = AsyncM18 {
+            port: Box::new(transport),
+            acc: INITIAL_ACC,
+            print_tx: false,
+            print_rx: false,
+            register_map: RegisterMap::embedded().clone(),
+            max_read_length: MAX_READ_LENGTH,
+            battery_lookup: create_battery_lookup(),
+            line_control: LineControl::default(),
+            open_sessions: 0,
+            timings: Timings::default(),
+            retries: Retries::default(),
+            echo_cancellation: false,
+        };
+
+        m18.idle();
+        m18
+    }
+
+    /// Set how the adapter drives J2.
+    ///
+    /// See [`M18::set_line_control`](crate::M18::set_line_control).
+    pub fn set_line_control(&mut self, line_control: LineControl) {
+        self.line_control = line_control;
+        self.idle();
+    }
+
+    /// Set the reset, response and keepalive delays.
+    ///
+    /// See [`M18Builder`](crate::M18Builder) for what each delay is for.
+    pub fn set_timings(&mut self, timings: Timings) {
+        self.timings = timings;
+    }
+
+    /// Reset, response and keepalive delays in use.
+    pub fn timings(&self) -> &Timings {
+        &self.timings
+    }
+
+    /// Set how failed commands are retried.
+    ///
+    /// Used by [`AsyncM18::read_all_raw`], [`AsyncM18::read_registers`],
+    /// [`AsyncM18::write_message`] and the charger simulation, as on
+    /// [`M18`](crate::M18).
+    ///
+    /// # Examples
+    /// ```
+    /// use m18_protocol::{emulator::VirtualBattery, AsyncM18, Register, RetryPolicy};
+    /// use std::time::Duration;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> m18_protocol::Result<()> {
+    /// let battery = VirtualBattery::new();
+    /// let mut m18 = AsyncM18::with_transport(battery.transport());
+    /// m18.set_retry_policy(RetryPolicy {
+    ///     backoff: Duration::from_millis(1),
+    ///     ..RetryPolicy::default()
+    /// });
+    ///
+    /// // A lost reply resyncs the link and a damaged one is read again
+    /// battery.drop_replies(1);
+    /// battery.corrupt_replies(1);
+    /// let values = m18
+    ///     .read_registers(&[Register::CellVoltages, Register::Note], false)
+    ///     .await?;
+    /// assert_eq!(values.len(), 2);
+    /// assert_eq!(m18.retry_stats().retries, 2);
+    /// assert_eq!(m18.retry_stats().resyncs, 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retries.policy = policy;
+    }
+
+    /// Current retry policy.
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retries.policy
+    }
+
+    /// Retries made by the last operation that uses the retry policy.
+    pub fn retry_stats(&self) -> RetryStats {
+        self.retries.stats
+    }
+
+    /// Cancel the echo of a single-wire link (default off).
+    ///
+    /// After every write the echo is read back and checked, as
+    /// [`EchoCancellingTransport`](crate::transport::EchoCancellingTransport)
+    /// does for [`M18`](crate::M18).
+    ///
+    /// # Examples
+    /// ```
+    /// use m18_protocol::{emulator::VirtualBattery, AsyncM18, M18Error};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> m18_protocol::Result<()> {
+    /// let battery = VirtualBattery::new();
+    /// battery.set_echo(true);
+    /// let mut m18 = AsyncM18::with_transport(battery.transport());
+    /// m18.set_echo_cancellation(true);
+    /// assert_eq!(m18.read_registers(&[12], false).await?.len(), 1);
+    ///
+    /// // Without the echo the sync reply is taken for the echo, and the next
+    /// // command shows the wiring is faulty
+    /// battery.set_echo(false);
+    /// let mut session = m18.reset().await?;
+    /// assert!(!session.is_synced());
+    /// let result = session.get_snapchat().await;
+    /// assert!(matches!(result, Err(M18Error::WiringFault { .. })));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_echo_cancellation(&mut self, enabled: bool) {
+        self.echo_cancellation = enabled;
+    }
+
+    /// Pull J2 low (`true`) or release it high (`false`)
+    fn set_j2_low(&mut self, low: bool) -> Result<()> {
+        self.line_control
+            .set_j2_low(&mut AsyncLines(self.port.as_mut()), low)
+    }
+
+    /// Enable or disable debug printing for transmitted and received data.
+    ///
+    /// # Arguments
+    /// * `tx` - Enable printing of transmitted data
+    /// * `rx` - Enable printing of received data
+    pub fn set_debug_print(&mut self, tx: bool, rx: bool) {
+        self.print_tx = tx;
+        self.print_rx = rx;
+    }
+
+    /// Reset the connected battery and establish communication.
+    ///
+    /// See [`M18::reset`](crate::M18::reset).
+    ///
+    /// # Returns
+    /// An [`AsyncSession`] that keeps J2 high until it is dropped.
+    /// [`AsyncSession::is_synced`] is false if the battery didn't respond or
+    /// responded incorrectly.
+    ///
+    /// # Errors
+    /// Returns error if J2 cannot be driven or the sync byte cannot be sent.
+    /// J2 is returned to idle first.
+    pub async fn reset(&mut self) -> Result<AsyncSession<'_>> {
+        let mut session = AsyncSession::new(self);
+        session.synced = session.sync().await?;
+        Ok(session)
+    }
+
+    /// Reset the battery for an operation that cannot run without it.
+    ///
+    /// # Errors
+    /// Returns `M18Error::Timeout` if the battery did not answer the sync byte.
+    async fn reset_synced(&mut self) -> Result<AsyncSession<'_>> {
+        let session = self.reset().await?;
+        if !session.is_synced() {
+            return Err(M18Error::Timeout);
+        }
+        Ok(session)
+    }
+
+    /// Count a newly opened session
+    pub(crate) fn open_session(&mut self) {
+        self.open_sessions += 1;
+    }
+
+    /// Count a closed session, returning J2 to idle once none is left open
+    pub(crate) fn close_session(&mut self) {
+        self.open_sessions = self.open_sessions.saturating_sub(1);
+        if self.open_sessions == 0 {
+            self.idle();
+        }
+    }
+
+    /// Run the reset sequence, leaving J2 high
+    async fn sync(&mut self) -> Result<bool> {
+        self.acc = INITIAL_ACC;
+
+        // Pulse J2 low for reset
+        self.set_j2_low(true)?;
+        sleep(self.timings.reset_break).await;
+
+        self.set_j2_low(false)?;
+        sleep(self.timings.reset_settle).await;
+
+        // Send sync byte
+        self.send(&[SYNC_BYTE]).await?;
+
+        match self.read_raw(1).await {
+            Ok(response) if response.len() == 1 && response[0] == SYNC_BYTE => {
+                sleep(self.timings.reset_sync_delay).await;
+                Ok(true)
+            }
+            Ok(response) => {
+                if self.print_rx {
+                    debug!("Unexpected response: {:02X?}", response);
+                }
+                Ok(false)
+            }
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// Re-run the reset sequence to get the ACC sequence back in step
+    async fn resync(&mut self) -> bool {
+        let synced = matches!(self.sync().await, Ok(true));
+        self.retries.resynced(synced)
+    }
+
+    /// Update the ACC (accumulator) value for next command
+    fn update_acc(&mut self) {
+        self.acc = exchange::next_acc(self.acc);
+    }
+
+    /// Send raw bytes to the battery
+    async fn send(&mut self, command: &[u8]) -> Result<()> {
+        self.port.clear_input()?;
+
+        if self.print_tx {
+            debug!("Sending:  {}", exchange::hex(command));
+        }
+
+        // Convert to MSB format (reverse bits)
+        let wire = frame::to_wire(command);
+        self.port.write_all(&wire).await?;
+        self.port.flush().await?;
+
+        if self.echo_cancellation {
+            let echoed = self.read_wire(wire.len()).await;
+            exchange::check_echo(&wire, echoed)?;
+        }
+        Ok(())
+    }
+
+    /// Encode and send a request frame
+    async fn send_command(&mut self, request: &RequestFrame) -> Result<()> {
+        self.send(&request.encode()).await
+    }
+
+    /// Read raw bytes from the battery, converted to LSB order
+    async fn read_raw(&mut self, length: usize) -> Result<Vec<u8>> {
+        Ok(frame::to_wire(&self.read_wire(length).await?))
+    }
+
+    /// Read bytes as they arrive on the wire
+    ///
+    /// Fails with `Timeout` if the bytes don't arrive within
+    /// [`Timings::read_timeout`].
+    async fn read_wire(&mut self, length: usize) -> Result<Vec<u8>> {
+        let mut msb_response = vec![0u8; length];
+        match timeout(
+            self.timings.read_timeout,
+            self.port.read_exact(&mut msb_response),
+        )
+        .await
+        {
+            Ok(Ok(_)) => Ok(msb_response),
+            Ok(Err(e)) if e.kind() == io::ErrorKind::TimedOut => Err(M18Error::Timeout),
+            Ok(Err(e)) => Err(e.into()),
+            Err(_) => Err(M18Error::Timeout),
+        }
+    }
+
+    /// Read the reply to `request` and decode it, rejecting NACKs and unknown
+    /// headers
+    async fn read_response(
+        &mut self,
+        request: &RequestFrame,
+        expected_size: usize,
+    ) -> Result<ResponseFrame> {
+        let mut response = self.read_raw(1).await?;
+
+        if response.is_empty() {
+            return Err(M18Error::EmptyResponse);
+        }
+
+        // Check if we need to read more based on first byte
+        let additional_bytes = exchange::remaining_reply_length(response[0], expected_size);
+        if additional_bytes > 0 {
+            response.extend(self.read_raw(additional_bytes).await?);
+        }
+
+        if self.print_rx {
+            debug!("Received: {}", exchange::hex(&response));
+        }
+
+        // Add delay to improve reliability with isolation circuits
+        sleep(self.timings.post_response_delay).await;
+
+        ResponseFrame::decode_reply(&response, request)
+    }
+
+    /// Configure battery charging parameters.
+    ///
+    /// Sends a configuration command to set charging state and current limits.
+    ///
+    /// # Arguments
+    /// * `state` - Charging state (Active or Initialization)
+    /// * `profile` - Charger current limits to announce
+    ///
+    /// # Returns
+    /// Battery response frame (no payload).
+    ///
+    /// # Errors
+    /// Returns `M18Error::ChargerProfileOutOfRange` without sending anything if
+    /// the profile fails [`ChargerProfile::validate`].
+    pub async fn configure(
+        &mut self,
+        state: ChargeState,
+        profile: &ChargerProfile,
+    ) -> Result<ResponseFrame> {
+        profile.validate()?;
+        let payload = profile.encode(state);
+        let request = RequestFrame::new(Command::Configure as u8, self.acc, payload);
+        self.send_command(&request).await?;
+        self.update_acc();
+        self.read_response(&request, 5).await
+    }
+
+    /// Get snapshot data from battery.
+    ///
+    /// # Returns
+    /// Decoded snapshot reply.
+    pub async fn get_snapchat(&mut self) -> Result<SnapshotResponse> {
+        let request = RequestFrame::new(Command::Snapshot as u8, self.acc, vec![]);
+        self.send_command(&request).await?;
+        self.update_acc();
+        SnapshotResponse::decode(&self.read_response(&request, 8).await?)
+    }
+
+    /// Send keepalive message to battery.
+    ///
+    /// # Returns
+    /// Decoded keepalive reply containing current state.
+    pub async fn keepalive(&mut self) -> Result<KeepaliveResponse> {
+        let request = RequestFrame::new(Command::Keepalive as u8, self.acc, vec![]);
+        self.send_command(&request).await?;
+        KeepaliveResponse::decode(&self.read_response(&request, 9).await?)
+    }
+
+    /// Send calibration/interrupt command to battery.
+    ///
+    /// # Returns
+    /// Battery response frame (3-byte payload).
+    pub async fn calibrate(&mut self) -> Result<ResponseFrame> {
+        let request = RequestFrame::new(Command::Calibrate as u8, self.acc, vec![]);
+        self.send_command(&request).await?;
+        self.update_acc();
+        self.read_response(&request, 8).await
+    }
+
+    /// Send custom command to battery.
+    ///
+    /// # Arguments
+    /// * `operation` - Memory operation (Read or Write)
+    /// * `address_high` - High byte of memory address
+    /// * `address_low` - Low byte of memory address
+    /// * `length` - Number of bytes to read/write
+    ///
+    /// # Returns
+    /// Validated battery response frame.
+    pub async fn send_custom_command(
+        &mut self,
+        operation: MemoryOperation,
+        address_high: u8,
+        address_low: u8,
+        length: u8,
+    ) -> Result<ResponseFrame> {
+        let request = RequestFrame::new(
+            operation as u8,
+            0x04,
+            vec![address_high, address_low, length],
+        );
+        self.send_command(&request).await?;
+        self.read_response(&request, length as usize + frame::FRAME_OVERHEAD)
+            .await
+    }
+
+    /// Simulate charger communication for specified duration.
+    ///
+    /// # Arguments
+    /// * `duration` - How long to simulate charging
+    /// * `profile` - Charger parameters to announce (see [`ChargerProfile`])
+    pub async fn simulate_for(
+        &mut self,
+        duration: Duration,
+        profile: &ChargerProfile,
+    ) -> Result<()> {
+        self.simulate_until(duration, profile, std::future::pending())
+            .await
+    }
+
+    /// Simulate charger communication until `duration` elapses or `cancel` completes.
+    ///
+    /// J2 is returned to idle in either case, unless the call is made through
+    /// an open [`AsyncSession`].
+    ///
+    /// # Arguments
+    /// * `duration` - Maximum time to simulate charging
+    /// * `profile` - Charger parameters to announce (see [`ChargerProfile`])
+    /// * `cancel` - Future that ends the simulation early when it completes
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use m18_protocol::{AsyncM18, ChargerProfile};
+    /// use std::time::Duration;
+    ///
+    /// # async fn run() -> m18_protocol::Result<()> {
+    /// let mut m18 = AsyncM18::new("/dev/ttyUSB0")?;
+    /// let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+    /// # drop(stop_tx);
+    /// let profile = ChargerProfile::standard();
+    /// m18.simulate_until(Duration::from_secs(60), &profile, async {
+    ///     let _ = stop_rx.await;
+    /// })
+    /// .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn simulate_until(
+        &mut self,
+        duration: Duration,
+        profile: &ChargerProfile,
+        cancel: impl Future<Output = ()>,
+    ) -> Result<()> {
+        profile.validate()?;
+        info!(
+            "Simulating charger communication for {} seconds...",
+            duration.as_secs()
+        );
+        let start_time = Instant::now();
+
+        self.retries.start();
+        let result = tokio::select! {
+            result = self.run_simulation(duration, profile) => result,
+            _ = cancel => {
+                info!("Simulation cancelled");
+                Ok(())
+            }
+        };
+
+        self.retries.log("Charger simulation");
+        info!(
+            "Duration: {:.2} seconds",
+            start_time.elapsed().as_secs_f64()
+        );
+        result
+    }
+
+    /// Charger handshake followed by keepalives until `duration` elapses.
+    ///
+    /// Commands are retried as the retry policy allows, and the handshake is
+    /// repeated if a retry had to reset the battery.
+    async fn run_simulation(&mut self, duration: Duration, profile: &ChargerProfile) -> Result<()> {
+        let start_time = Instant::now();
+
+        let mut session = self.reset_synced().await?;
+        session.acc = INITIAL_ACC; // Ensure ACC starts at initial value for configure sequence
+        session.charger_handshake(profile).await?;
+
+        let mut resyncs = session.retries.stats.resyncs;
+        while start_time.elapsed() < duration {
+            sleep(session.timings.keepalive_interval).await;
+            let result = match retrying!(session, session.keepalive()) {
+                Ok(keepalive) => {
+                    log_keepalive(&keepalive);
+                    // A reset ends the charge session, so announce the charger again
+                    if session.retries.stats.resyncs != resyncs {
+                        session.charger_handshake(profile).await
+                    } else {
+                        Ok(())
+                    }
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                warn!("Keepalive failed: {}", e);
+                break;
+            }
+            resyncs = session.retries.stats.resyncs;
+        }
+
+        Ok(())
+    }
+
+    /// Announce the charger with the same steps as [`M18`](crate::M18)
+    async fn charger_handshake(&mut self, profile: &ChargerProfile) -> Result<()> {
+        for step in CHARGER_HANDSHAKE {
+            match step {
+                HandshakeStep::Configure(state) => {
+                    retrying!(self, self.configure(state, profile))?;
+                }
+                HandshakeStep::Snapshot(state) => {
+                    let snapshot = retrying!(self, self.get_snapchat())?;
+                    match state {
+                        ChargeState::Initialization => debug!("Snapshot: {:?}", snapshot),
+                        ChargeState::Active => info!(
+                            "Snapshot: temperature {:.0}C, state flags {:#04x}",
+                            snapshot.temperature, snapshot.state_flags
+                        ),
+                    }
+                }
+                HandshakeStep::Keepalive => log_keepalive(&retrying!(self, self.keepalive())?),
+                HandshakeStep::Wait(delay) => sleep(delay).await,
+            }
+        }
+        Ok(())
+    }
+
+    /// Set J2 pin to idle state (low voltage).
+    pub fn idle(&mut self) {
+        let _ = self.set_j2_low(true);
+    }
+
+    /// Set J2 pin to high state (~20V).
+    pub fn high(&mut self) {
+        let _ = self.set_j2_low(false);
+    }
+
+    /// Set J2 pin high for specified duration, then return to idle.
+    ///
+    /// # Arguments
+    /// * `duration` - How long to keep J2 high
+    pub async fn high_for(&mut self, duration: Duration) {
+        self.high();
+        sleep(duration).await;
+        self.idle();
+    }
+
+    /// Write a custom message to battery memory (register 0x0023).
+    ///
+    /// # Arguments
+    /// * `message` - Text to write (max 20 characters)
+    ///
+    /// # Errors
+    /// Returns `M18Error::MessageTooLong` if message exceeds 20 characters, or
+    /// `M18Error::Timeout` if the battery does not answer the reset.
+    pub async fn write_message(&mut self, message: &str) -> Result<()> {
+        if message.len() > 20 {
+            return Err(M18Error::MessageTooLong {
+                length: message.len(),
+            });
+        }
+
+        info!("Writing \"{}\" to memory", message);
+        self.retries.start();
+        let mut session = self.reset_synced().await?;
+
+        let padded_message = format!("{:-<20}", message);
+        for (i, byte) in padded_message.bytes().enumerate() {
+            let request = RequestFrame::new(
+                MemoryOperation::Read as u8,
+                MemoryOperation::Write as u8,
+                vec![0x00, (0x23 + i) as u8, byte],
+            );
+            retrying!(session, async {
+                session.send_command(&request).await?;
+                session.read_response(&request, 2).await
+            })?;
+        }
+
+        session.retries.log("Write");
+        Ok(())
+    }
+
+    /// Read all memory regions and return raw data.
+    ///
+    /// # Returns
+    /// Vector of (address, data) tuples for each successfully read region.
+    ///
+    /// # Errors
+    /// Returns `M18Error::Timeout` if the battery does not answer the reset.
+    pub async fn read_all_raw(&mut self) -> Result<Vec<(u16, Vec<u8>)>> {
+        let mut results = Vec::new();
+        self.retries.start();
+        let mut session = self.reset_synced().await?;
+
+        let regions = session.register_map.regions().to_vec();
+        for region in &regions {
+            let address = region.address();
+            match retrying!(
+                session,
+                session.send_custom_command(
+                    MemoryOperation::Read,
+                    region.address_high,
+                    region.address_low,
+                    region.length,
+                )
+            ) {
+                Ok(response)
+                    if response.header() == frame::READ_RESPONSE_HEADER
+                        && response.payload().len() == region.length as usize =>
+                {
+                    results.push((address, response.payload().to_vec()));
+                }
+                Err(e @ M18Error::WiringFault { .. }) => return Err(e),
+                Ok(_) | Err(_) => {
+                    if session.print_rx {
+                        debug!("Failed to read from 0x{:04X}", address);
+                    }
+                }
+            }
+        }
+
+        session.retries.log("Capture");
+        Ok(results)
+    }
+
+    /// Plan the reads needed for a set of registers.
+    ///
+    /// # Arguments
+    /// * `registers` - Registers to read, as IDs (0-183) or [`Register`](crate::Register)s
+    /// * `force_refresh` - Plan a read of every memory region
+    pub fn plan_reads<R: RegisterSelector>(
+        &self,
+        registers: &[R],
+        force_refresh: bool,
+    ) -> ReadPlan {
+        let ids: Vec<usize> = registers
+            .iter()
+            .filter_map(|selector| selector.resolve(&self.register_map))
+            .collect();
+
+        if force_refresh {
+            ReadPlan::covering_regions(&self.register_map, &ids, self.max_read_length)
+        } else {
+            ReadPlan::new(&self.register_map, &ids, self.max_read_length)
+        }
+    }
+
+    /// Set the longest read the planner may send.
+    ///
+    /// # Arguments
+    /// * `length` - Maximum read length in bytes
+    pub fn set_max_read_length(&mut self, length: u8) {
+        self.max_read_length = length;
+    }
+
+    /// Read specific registers and return parsed values.
+    ///
+    /// # Arguments
+    /// * `registers` - Registers to read, as IDs (0-183) or [`Register`](crate::Register)s
+    /// * `force_refresh` - If true, reads every memory region once and takes the
+    ///   registers from those reads
+    ///
+    /// # Returns
+    /// Vector of (register_id, parsed_value) tuples, in the order requested.
+    /// Registers that are not in the register map or could not be read are
+    /// skipped; use [`AsyncM18::read_registers_detailed`] to see why.
+    pub async fn read_registers<R: RegisterSelector>(
+        &mut self,
+        registers: &[R],
+        force_refresh: bool,
+    ) -> Result<Vec<(usize, RegisterValue)>> {
+        Ok(successful_values(
+            self.read_registers_detailed(registers, force_refresh)
+                .await?,
+        ))
+    }
+
+    /// Read specific registers, reporting the outcome of each one.
+    ///
+    /// # Arguments
+    /// * `registers` - Registers to read, as IDs (0-183) or [`Register`](crate::Register)s
+    /// * `force_refresh` - If true, reads every memory region once and takes the
+    ///   registers from those reads
+    ///
+    /// # Returns
+    /// One (register_id, result) tuple per requested register in the map, in
+    /// the order requested. Failed registers carry the [`ReadFailure`](crate::ReadFailure) that
+    /// stopped them.
+    ///
+    /// # Errors
+    /// Returns error only if the battery cannot be reset or the wiring is
+    /// faulty; failed reads are reported per register.
+    pub async fn read_registers_detailed<R: RegisterSelector>(
+        &mut self,
+        registers: &[R],
+        force_refresh: bool,
+    ) -> Result<Vec<(usize, RegisterResult)>> {
+        let plan = self.plan_reads(registers, force_refresh);
+        if self.print_rx {
+            debug!("Read plan: {}", plan);
+        }
+
+        let mut replies = PlannedReplies::default();
+        self.retries.start();
+        let mut session = self.reset_synced().await?;
+
+        for read in plan.reads() {
+            let response = match retrying!(
+                session,
+                session.send_custom_command(
+                    MemoryOperation::Read,
+                    (read.address >> 8) as u8,
+                    (read.address & 0xFF) as u8,
+                    read.length,
+                )
+            ) {
+                Err(e @ M18Error::WiringFault { .. }) => return Err(e),
+                response => response,
+            };
+            replies.record(read, response, session.print_rx);
+        }
+        session.retries.log("Read");
+        drop(session);
+
+        let results = replies.parse(&self.register_map, &plan, self.print_rx);
+        Ok(requested_results(&self.register_map, registers, &results))
+    }
+
+    /// Read all 184 registers and return parsed values.
+    ///
+    /// # Arguments
+    /// * `force_refresh` - If true, reads every memory region once
+    pub async fn read_all_registers(
+        &mut self,
+        force_refresh: bool,
+    ) -> Result<Vec<(usize, RegisterValue)>> {
+        let ids: Vec<usize> = (0..self.register_map.registers().len()).collect();
+        self.read_registers(&ids, force_refresh).await
+    }
+
+    /// Generate a comprehensive health report.
+    ///
+    /// # Errors
+    /// Returns error if battery communication fails or required data cannot be read.
+    pub async fn health_report(&mut self) -> Result<HealthReport> {
+        info!("Reading battery health registers");
+
+        let results = self
+            .read_registers_detailed(&health_report_registers(), true)
+            .await?;
+        let results: HashMap<usize, RegisterResult> = results.into_iter().collect();
+        build_health_report(
+            &results,
+            &self.register_map,
+            &self.battery_lookup,
+            Utc::now(),
+        )
+    }
+}
+
+impl Drop for AsyncM18 {
+    /// Return J2 to idle so the battery is not left awake
+    fn drop(&mut self) {
+        self.idle();
+    }
+}
+
+/// Log the telemetry carried by a keepalive reply
+fn log_keepalive(keepalive: &KeepaliveResponse) {
+    info!(
+        "Keepalive: pack {:.2}V, requested current {}mA",
+        keepalive.pack_voltage, keepalive.requested_current
+    );
+}
@@ -0,0 +1,899 @@
+//! `m18` command-line tool.
+//!
+//! Reads diagnostics from, and talks to, an M18 battery on a serial port.
+//! Run `m18 --help` for the list of commands.
+
+use clap::{Parser, Subcommand, ValueEnum};
+use m18_protocol::constants::MAX_READ_LENGTH;
+use m18_protocol::frame::{self, RequestFrame};
+use m18_protocol::read_plan::ReadPlan;
+use m18_protocol::scan::{Scan, ScanConfig};
+use m18_protocol::{
+    ChargeEvent, ChargeSession, ChargerProfile, Command, LineControl, M18Builder, M18Error,
+    MemoryImage, OutputFormat, Register, RegisterMap, RegisterSelector, RetryPolicy, M18,
+};
+use serde_json::json;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+/// Command completed successfully
+const EXIT_OK: u8 = 0;
+/// Unexpected failure, such as being unable to write the output
+const EXIT_FAILURE: u8 = 1;
+/// Invalid arguments
+const EXIT_USAGE: u8 = 2;
+/// Serial port could not be opened
+const EXIT_PORT: u8 = 3;
+/// Battery did not respond
+const EXIT_NO_RESPONSE: u8 = 4;
+/// Battery replied with an invalid or unexpected frame
+const EXIT_PROTOCOL: u8 = 5;
+/// Some of the requested data could not be read
+const EXIT_INCOMPLETE: u8 = 6;
+
+/// Reads between saves of the `scan --state` file
+const SCAN_SAVE_INTERVAL: usize = 64;
+
+#[derive(Parser)]
+#[command(name = "m18", version, about = "Milwaukee M18 battery diagnostics")]
+#[command(after_help = "Exit codes:
+  0  success
+  1  unexpected failure (e.g. output could not be written)
+  2  invalid arguments
+  3  serial port could not be opened
+  4  battery did not respond
+  5  battery sent an invalid or unexpected reply
+  6  some of the requested data could not be read")]
+struct Cli {
+    /// Serial port connected to the battery
+    #[arg(short, long, global = true, env = "M18_PORT")]
+    port: Option<String>,
+
+    /// Output format
+    #[arg(short, long, global = true, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Write output to a file instead of stdout
+    #[arg(short, long, global = true)]
+    output: Option<PathBuf>,
+
+    /// Log every frame sent and received
+    #[arg(long, global = true)]
+    debug: bool,
+
+    /// Control lines the adapter uses to drive J2
+    #[arg(long, global = true, value_enum, default_value_t = Wiring::BreakDtr)]
+    wiring: Wiring,
+
+    /// Adapter ties TX and RX together: read back and check every transmitted byte
+    #[arg(long, global = true)]
+    echo: bool,
+
+    /// Times to retry a failed command (0 disables retries and resyncs)
+    #[arg(long, global = true, default_value_t = RetryPolicy::default().max_retries)]
+    retries: u32,
+
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// List available serial ports
+    Ports,
+    /// Print a battery health report
+    Report {
+        /// Build the report from a saved memory image instead of the battery
+        #[arg(long)]
+        image: Option<PathBuf>,
+    },
+    /// Read registers by ID (`12`, `24-27`), address (`0x400A`) or name (`cell_voltages`)
+    Read {
+        /// Register IDs, ID ranges, addresses or names, separated by spaces or commas
+        #[arg(required = true)]
+        registers: Vec<String>,
+        /// Read every memory region before the requested registers
+        #[arg(long)]
+        refresh: bool,
+        /// Parse registers from a saved memory image instead of the battery
+        #[arg(long)]
+        image: Option<PathBuf>,
+        /// Print the reads that would be sent instead of reading
+        #[arg(long, conflicts_with = "image")]
+        plan: bool,
+    },
+    /// Dump every known memory region as raw bytes
+    Dump {
+        /// Also save the capture as a memory image (binary if the name ends in `.bin`, else JSON)
+        #[arg(long)]
+        save: Option<PathBuf>,
+    },
+    /// Write a note (up to 20 ASCII characters) to battery memory
+    WriteNote {
+        /// Note text
+        text: String,
+    },
+    /// Simulate a charger and print keepalive telemetry
+    Simulate {
+        /// Simulation length in seconds
+        #[arg(short, long, default_value_t = 10)]
+        duration: u64,
+        /// Charger profile to announce
+        #[arg(long, value_enum, default_value_t = Profile::Standard)]
+        profile: Profile,
+    },
+    /// Probe a range of addresses and print which ones can be read
+    Scan {
+        /// First address, e.g. `0x4000`
+        #[arg(long, value_parser = parse_address, default_value = "0x0000")]
+        start: u16,
+        /// Last address (inclusive)
+        #[arg(long, value_parser = parse_address, default_value = "0xFFFF")]
+        end: u16,
+        /// Distance between probed addresses
+        #[arg(long, default_value_t = ScanConfig::default().step)]
+        step: u16,
+        /// Read lengths to try at every address, e.g. `1,2,4`
+        #[arg(long, value_delimiter = ',', default_value = "1")]
+        lengths: Vec<u8>,
+        /// Pause between reads in milliseconds
+        #[arg(long, default_value_t = ScanConfig::default().delay.as_millis() as u64)]
+        delay_ms: u64,
+        /// Let the battery rest with J2 idle after this many reads (0 never rests)
+        #[arg(long, default_value_t = ScanConfig::default().rest_every.unwrap_or(0))]
+        rest_every: u32,
+        /// Length of each rest in seconds
+        #[arg(long, default_value_t = ScanConfig::default().rest.as_secs())]
+        rest_secs: u64,
+        /// Progress file: saved as the scan runs, and resumed from if it exists
+        #[arg(long)]
+        state: Option<PathBuf>,
+        /// Save the bytes found as a memory image (binary if the name ends in `.bin`, else JSON)
+        #[arg(long)]
+        save: Option<PathBuf>,
+    },
+    /// Compare two saved memory images and show which registers changed
+    Diff {
+        /// Earlier image
+        old: PathBuf,
+        /// Later image
+        new: PathBuf,
+    },
+    /// Send a raw request: header, ACC and payload in hex (length and checksum are added)
+    Raw {
+        /// Request bytes, e.g. `01 04 40 0A 0A` or `0104400A0A`
+        #[arg(required = true)]
+        hex: Vec<String>,
+        /// Expected reply length in bytes (guessed from the command if omitted)
+        #[arg(long, value_parser = parse_response_len)]
+        response_len: Option<usize>,
+    },
+}
+
+/// Output formats accepted by `--format`
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Human-readable text
+    Text,
+    /// JSON
+    Json,
+    /// One raw value per line (`read` only)
+    Raw,
+    /// Rust array syntax (`read` only)
+    Array,
+    /// Values for pasting into a form (`read` only)
+    Form,
+}
+
+/// Charger presets accepted by `--profile`
+#[derive(Clone, Copy, ValueEnum)]
+enum Profile {
+    Standard,
+    Compact,
+    Rapid,
+    Super,
+}
+
+impl From<Profile> for ChargerProfile {
+    fn from(profile: Profile) -> Self {
+        match profile {
+            Profile::Standard => ChargerProfile::standard(),
+            Profile::Compact => ChargerProfile::compact(),
+            Profile::Rapid => ChargerProfile::rapid(),
+            Profile::Super => ChargerProfile::super_charger(),
+        }
+    }
+}
+
+/// J2 wiring accepted by `--wiring`
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Wiring {
+    /// TX break and DTR high pull J2 low (reference level shifter)
+    BreakDtr,
+    /// TX break and DTR low pull J2 low
+    BreakDtrInverted,
+    /// TX break only
+    Break,
+    /// DTR high pulls J2 low
+    Dtr,
+    /// DTR low pulls J2 low
+    DtrInverted,
+    /// RTS high pulls J2 low
+    Rts,
+    /// RTS low pulls J2 low
+    RtsInverted,
+}
+
+impl From<Wiring> for LineControl {
+    fn from(wiring: Wiring) -> Self {
+        match wiring {
+            Wiring::BreakDtr => LineControl::BreakAndDtr { inverted: false },
+            Wiring::BreakDtrInverted => LineControl::BreakAndDtr { inverted: true },
+            Wiring::Break => LineControl::Break,
+            Wiring::Dtr => LineControl::Dtr { inverted: false },
+            Wiring::DtrInverted => LineControl::Dtr { inverted: true },
+            Wiring::Rts => LineControl::Rts { inverted: false },
+            Wiring::RtsInverted => LineControl::Rts { inverted: true },
+        }
+    }
+}
+
+/// `--output` file, created by the first write.
+///
+/// Commands only write once they have a result, so a command that fails
+/// early leaves an existing file untouched.
+struct OutputFile {
+    /// Path given with `--output`
+    path: PathBuf,
+    /// Open file, once something has been written
+    file: Option<BufWriter<File>>,
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let file = match self.file.take() {
+            Some(file) => file,
+            None => BufWriter::new(File::create(&self.path)?),
+        };
+        self.file.insert(file).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Error that ends the program, with its exit code
+struct CliError {
+    /// Process exit code
+    code: u8,
+    /// Message for stderr
+    message: String,
+}
+
+impl CliError {
+    fn new(code: u8, message: impl Into<String>) -> Self {
+        CliError {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn usage(message: impl Into<String>) -> Self {
+        Self::new(EXIT_USAGE, message)
+    }
+}
+
+impl From<M18Error> for CliError {
+    fn from(e: M18Error) -> Self {
+        let code = match &e {
+            M18Error::SerialPort(_) => EXIT_PORT,
+            M18Error::Timeout | M18Error::EmptyResponse => EXIT_NO_RESPONSE,
+            M18Error::InvalidResponse { .. }
+            | M18Error::ShortFrame { .. }
+            | M18Error::Nack { .. }
+            | M18Error::ChecksumMismatch { .. }
+            | M18Error::Parse(_) => EXIT_PROTOCOL,
+            M18Error::MessageTooLong { .. }
+            | M18Error::InvalidScan(_)
+            | M18Error::InvalidRequest(_)
+            | M18Error::RegisterNotFound { .. }
+            | M18Error::ChargerProfileOutOfRange { .. } => EXIT_USAGE,
+            _ => EXIT_FAILURE,
+        };
+        CliError::new(code, e.to_string())
+    }
+}
+
+impl From<io::Error> for CliError {
+    fn from(e: io::Error) -> Self {
+        CliError::new(EXIT_FAILURE, format!("Output error: {}", e))
+    }
+}
+
+impl From<serde_json::Error> for CliError {
+    fn from(e: serde_json::Error) -> Self {
+        CliError::new(EXIT_FAILURE, format!("JSON error: {}", e))
+    }
+}
+
+type CliResult<T = ()> = std::result::Result<T, CliError>;
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let default_level = if cli.debug { "debug" } else { "warn" };
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(default_level))
+        .init();
+
+    match run(&cli) {
+        Ok(code) => ExitCode::from(code),
+        Err(e) => {
+            eprintln!("m18: {}", e.message);
+            ExitCode::from(e.code)
+        }
+    }
+}
+
+/// Run the selected command and return the exit code
+fn run(cli: &Cli) -> CliResult<u8> {
+    let mut out: Box<dyn Write> = match &cli.output {
+        Some(path) => Box::new(OutputFile {
+            path: path.clone(),
+            file: None,
+        }),
+        None => Box::new(io::stdout().lock()),
+    };
+
+    let code = match &cli.command {
+        Commands::Ports => ports(cli, &mut out)?,
+        Commands::Report { image } => report(cli, &mut out, image.as_deref())?,
+        Commands::Read {
+            registers,
+            refresh,
+            image: _,
+            plan: true,
+        } => plan(&mut out, registers, *refresh)?,
+        Commands::Read {
+            registers,
+            refresh,
+            image,
+            plan: false,
+        } => read(cli, &mut out, registers, *refresh, image.as_deref())?,
+        Commands::Dump { save } => dump(cli, &mut out, save.as_deref())?,
+        Commands::WriteNote { text } => write_note(cli, &mut out, text)?,
+        Commands::Simulate { duration, profile } => {
+            simulate(cli, &mut out, Duration::from_secs(*duration), *profile)?
+        }
+        Commands::Scan {
+            start,
+            end,
+            step,
+            lengths,
+            delay_ms,
+            rest_every,
+            rest_secs,
+            state,
+            save,
+        } => {
+            let config = ScanConfig {
+                start: *start,
+                end: *end,
+                step: *step,
+                lengths: lengths.clone(),
+                delay: Duration::from_millis(*delay_ms),
+                rest_every: Some(*rest_every).filter(|&every| every > 0),
+                rest: Duration::from_secs(*rest_secs),
+            };
+            scan(cli, &mut out, config, state.as_deref(), save.as_deref())?
+        }
+        Commands::Diff { old, new } => diff(cli, &mut out, old, new)?,
+        Commands::Raw { hex, response_len } => raw(cli, &mut out, hex, *response_len)?,
+    };
+
+    out.flush()?;
+    Ok(code)
+}
+
+/// Open the port given by `--port`.
+///
+/// Commands reset the battery themselves and fail with a timeout if it does
+/// not answer, so no reset is done here.
+fn connect(cli: &Cli) -> CliResult<M18> {
+    let port = cli
+        .port
+        .as_deref()
+        .ok_or_else(|| CliError::usage("No serial port given (use --port or M18_PORT)"))?;
+    let retry_policy = if cli.retries == 0 {
+        RetryPolicy::none()
+    } else {
+        RetryPolicy {
+            max_retries: cli.retries,
+            ..RetryPolicy::default()
+        }
+    };
+    Ok(M18Builder::new(port)
+        .debug_print(cli.debug, cli.debug)
+        .retry_policy(retry_policy)
+        .line_control(cli.wiring.into())
+        .echo_cancellation(cli.echo)
+        .build()?)
+}
+
+/// Tell the user how many retries the last operation needed
+fn report_retries(m18: &M18) {
+    let stats = m18.retry_stats();
+    if stats.retries > 0 {
+        eprintln!("m18: {}", stats);
+    }
+}
+
+/// Where register data comes from
+enum Source {
+    /// Live battery on `--port`
+    Battery(Box<M18>),
+    /// Saved memory image
+    Image(MemoryImage),
+}
+
+impl Source {
+    /// Load the image at `image` if given, otherwise connect to the battery
+    fn open(cli: &Cli, image: Option<&Path>) -> CliResult<Self> {
+        match image {
+            Some(path) => Ok(Source::Image(MemoryImage::load(path)?)),
+            None => Ok(Source::Battery(Box::new(connect(cli)?))),
+        }
+    }
+}
+
+/// Reject formats that only make sense for `read`
+fn require_text_or_json(cli: &Cli) -> CliResult {
+    match cli.format {
+        Format::Text | Format::Json => Ok(()),
+        _ => Err(CliError::usage(
+            "This command only supports --format text or json",
+        )),
+    }
+}
+
+fn ports(cli: &Cli, out: &mut dyn Write) -> CliResult<u8> {
+    require_text_or_json(cli)?;
+    let ports = M18::list_ports()?;
+
+    if cli.format == Format::Json {
+        let ports: Vec<_> = ports
+            .iter()
+            .map(|p| json!({ "name": p.port_name, "type": format!("{:?}", p.port_type) }))
+            .collect();
+        writeln!(out, "{}", serde_json::to_string_pretty(&ports)?)?;
+    } else {
+        for port in &ports {
+            writeln!(out, "{}\t{:?}", port.port_name, port.port_type)?;
+        }
+    }
+    Ok(EXIT_OK)
+}
+
+fn report(cli: &Cli, out: &mut dyn Write, image: Option<&Path>) -> CliResult<u8> {
+    require_text_or_json(cli)?;
+    let report = match Source::open(cli, image)? {
+        Source::Battery(mut m18) => {
+            let report = m18.health_report()?;
+            report_retries(&m18);
+            report
+        }
+        Source::Image(image) => image.health_report(RegisterMap::embedded())?,
+    };
+
+    if cli.format == Format::Json {
+        writeln!(out, "{}", serde_json::to_string_pretty(&report)?)?;
+    } else {
+        write!(out, "{}", report)?;
+    }
+    Ok(EXIT_OK)
+}
+
+fn read(
+    cli: &Cli,
+    out: &mut dyn Write,
+    registers: &[String],
+    refresh: bool,
+    image: Option<&Path>,
+) -> CliResult<u8> {
+    let ids = parse_register_ids(registers)?;
+    let mut source = Source::open(cli, image)?;
+    let results = match &mut source {
+        Source::Battery(m18) => {
+            let results = m18.read_registers_detailed(&ids, refresh)?;
+            report_retries(m18);
+            results
+                .into_iter()
+                .filter_map(|(id, result)| match result {
+                    Ok(value) => Some((id, value)),
+                    Err(failure) => {
+                        eprintln!("m18: register {}: {}", id, failure);
+                        None
+                    }
+                })
+                .collect()
+        }
+        Source::Image(image) => image.registers(&ids, RegisterMap::embedded()),
+    };
+
+    let format = match cli.format {
+        Format::Text => OutputFormat::Label,
+        Format::Raw => OutputFormat::Raw,
+        Format::Array => OutputFormat::Array,
+        Format::Form => OutputFormat::Form,
+        Format::Json => {
+            let defs = RegisterMap::embedded().registers();
+            let values: Vec<_> = results
+                .iter()
+                .map(|(id, value)| {
+                    let register = &defs[*id];
+                    json!({
+                        "id": id,
+                        "address": register.address,
+                        "label": register.label,
+                        "value": value,
+                    })
+                })
+                .collect();
+            writeln!(out, "{}", serde_json::to_string_pretty(&values)?)?;
+            return Ok(completeness(ids.len(), results.len()));
+        }
+    };
+
+    let lines = match &source {
+        Source::Battery(m18) => m18.format_registers(&results, format),
+        Source::Image(image) => image.format_registers(&results, format, RegisterMap::embedded()),
+    };
+    for line in lines {
+        writeln!(out, "{}", line)?;
+    }
+    Ok(completeness(ids.len(), results.len()))
+}
+
+fn plan(out: &mut dyn Write, registers: &[String], refresh: bool) -> CliResult<u8> {
+    let ids = parse_register_ids(registers)?;
+    let map = RegisterMap::embedded();
+    let plan = if refresh {
+        ReadPlan::covering_regions(map, &ids, MAX_READ_LENGTH)
+    } else {
+        ReadPlan::new(map, &ids, MAX_READ_LENGTH)
+    };
+    write!(out, "{}", plan)?;
+    Ok(EXIT_OK)
+}
+
+/// Resolve register arguments to register IDs
+fn parse_register_ids(args: &[String]) -> CliResult<Vec<usize>> {
+    let map = RegisterMap::embedded();
+    let defs = map.registers();
+    let mut ids = Vec::new();
+
+    for item in args.iter().flat_map(|arg| arg.split(',')) {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+
+        if item.starts_with(|c: char| c.is_ascii_alphabetic())
+            || item.to_ascii_lowercase().starts_with("0x")
+        {
+            let register: Register = item
+                .parse()
+                .map_err(|e: M18Error| CliError::usage(e.to_string()))?;
+            let id = register.resolve(map).ok_or(M18Error::RegisterNotFound {
+                address: register.address(),
+            })?;
+            ids.push(id);
+        } else if let Some((start, end)) = item.split_once('-') {
+            let (start, end) = (parse_id(start, defs.len())?, parse_id(end, defs.len())?);
+            if start > end {
+                return Err(CliError::usage(format!("Empty register range: {}", item)));
+            }
+            ids.extend(start..=end);
+        } else {
+            ids.push(parse_id(item, defs.len())?);
+        }
+    }
+
+    Ok(ids)
+}
+
+/// Parse a single register ID and check it is in range
+fn parse_id(text: &str, count: usize) -> CliResult<usize> {
+    match text.trim().parse::<usize>() {
+        Ok(id) if id < count => Ok(id),
+        _ => Err(CliError::usage(format!(
+            "Invalid register ID: {} (expected 0-{})",
+            text,
+            count - 1
+        ))),
+    }
+}
+
+/// Exit code for a read that returned `read` of `requested` items
+fn completeness(requested: usize, read: usize) -> u8 {
+    if read < requested {
+        eprintln!("m18: only {} of {} items could be read", read, requested);
+        EXIT_INCOMPLETE
+    } else {
+        EXIT_OK
+    }
+}
+
+fn dump(cli: &Cli, out: &mut dyn Write, save: Option<&Path>) -> CliResult<u8> {
+    require_text_or_json(cli)?;
+    let mut m18 = connect(cli)?;
+    let image = m18.capture_image()?;
+    report_retries(&m18);
+
+    if let Some(path) = save {
+        if path.extension().is_some_and(|extension| extension == "bin") {
+            image.save_binary(path)?;
+        } else {
+            image.save_json(path)?;
+        }
+    }
+
+    if cli.format == Format::Json {
+        writeln!(out, "{}", image.to_json()?)?;
+    } else {
+        for region in &image.regions {
+            match &region.data {
+                Some(data) => writeln!(out, "0x{:04X}: {}", region.address, hex(data))?,
+                None => writeln!(out, "0x{:04X}: (failed)", region.address)?,
+            }
+        }
+    }
+    Ok(completeness(
+        image.regions.len(),
+        image.regions.len() - image.failed_regions().count(),
+    ))
+}
+
+fn write_note(cli: &Cli, out: &mut dyn Write, text: &str) -> CliResult<u8> {
+    require_text_or_json(cli)?;
+    let mut m18 = connect(cli)?;
+    m18.write_message(text)?;
+    report_retries(&m18);
+
+    if cli.format == Format::Json {
+        writeln!(out, "{}", json!({ "written": text }))?;
+    } else {
+        writeln!(out, "Note written: {:?}", text)?;
+    }
+    Ok(EXIT_OK)
+}
+
+fn simulate(cli: &Cli, out: &mut dyn Write, duration: Duration, profile: Profile) -> CliResult<u8> {
+    require_text_or_json(cli)?;
+    let profile = ChargerProfile::from(profile);
+    profile.validate()?;
+    let m18 = connect(cli)?;
+
+    let start = Instant::now();
+    let (session, events) = ChargeSession::start(m18, profile)?;
+    let mut result = Ok(EXIT_OK);
+    while let Some(remaining) = duration.checked_sub(start.elapsed()) {
+        let keepalive = match events.recv_timeout(remaining) {
+            Ok(ChargeEvent::Keepalive(keepalive)) => keepalive,
+            Ok(ChargeEvent::LinkLost(e)) => {
+                result = Err(CliError::new(
+                    EXIT_NO_RESPONSE,
+                    format!("Lost the link to the battery: {}", e),
+                ));
+                break;
+            }
+            Ok(_) => continue,
+            Err(_) => break,
+        };
+
+        let elapsed = start.elapsed().as_secs_f64();
+        let written = if cli.format == Format::Json {
+            let line = json!({
+                "elapsed": elapsed,
+                "pack_voltage": keepalive.pack_voltage,
+                "requested_current": keepalive.requested_current,
+                "raw": hex(&keepalive.raw),
+            });
+            writeln!(out, "{}", line)
+        } else {
+            writeln!(
+                out,
+                "{:7.1}s  {:6.2} V  {:5} mA",
+                elapsed, keepalive.pack_voltage, keepalive.requested_current
+            )
+        };
+        if let Err(e) = written.and_then(|_| out.flush()) {
+            result = Err(e.into());
+            break;
+        }
+    }
+    report_retries(&session.stop());
+    result
+}
+
+fn scan(
+    cli: &Cli,
+    out: &mut dyn Write,
+    config: ScanConfig,
+    state: Option<&Path>,
+    save: Option<&Path>,
+) -> CliResult<u8> {
+    require_text_or_json(cli)?;
+    let mut scan = match state {
+        Some(path) if path.exists() => {
+            let scan = Scan::load(path)?;
+            eprintln!(
+                "m18: resuming scan from {} ({} reads done)",
+                path.display(),
+                scan.probes.len()
+            );
+            scan
+        }
+        _ => Scan::new(config)?,
+    };
+    let mut m18 = connect(cli)?;
+
+    let mut save_error = None;
+    let result = m18.scan(&mut scan, |scan| {
+        if let Some(path) = state {
+            if scan.probes.len() % SCAN_SAVE_INTERVAL == 0 {
+                if let Err(e) = scan.save(path) {
+                    save_error.get_or_insert(e);
+                }
+            }
+        }
+    });
+
+    // Keep the progress even if the scan was cut short
+    if let Some(path) = state {
+        scan.save(path)?;
+    }
+    if let Some(path) = save {
+        let image = scan.to_image();
+        if path.extension().is_some_and(|extension| extension == "bin") {
+            image.save_binary(path)?;
+        } else {
+            image.save_json(path)?;
+        }
+    }
+    result?;
+    if let Some(e) = save_error {
+        return Err(e.into());
+    }
+
+    let coverage = scan.coverage();
+    if cli.format == Format::Json {
+        writeln!(out, "{}", serde_json::to_string_pretty(&coverage)?)?;
+    } else {
+        for range in &coverage {
+            writeln!(out, "{}", range)?;
+        }
+    }
+    Ok(EXIT_OK)
+}
+
+fn diff(cli: &Cli, out: &mut dyn Write, old: &Path, new: &Path) -> CliResult<u8> {
+    require_text_or_json(cli)?;
+    let diff = MemoryImage::load(old)?.diff(&MemoryImage::load(new)?, RegisterMap::embedded());
+
+    if cli.format == Format::Json {
+        writeln!(out, "{}", diff.to_json()?)?;
+    } else {
+        write!(out, "{}", diff)?;
+    }
+    Ok(EXIT_OK)
+}
+
+fn raw(
+    cli: &Cli,
+    out: &mut dyn Write,
+    args: &[String],
+    response_len: Option<usize>,
+) -> CliResult<u8> {
+    require_text_or_json(cli)?;
+    let bytes = parse_hex(&args.concat())?;
+    if bytes.len() < 2 {
+        return Err(CliError::usage(
+            "A raw request needs at least a header and ACC byte",
+        ));
+    }
+    let request = RequestFrame::new(bytes[0], bytes[1], bytes[2..].to_vec());
+    let response_len = response_len.unwrap_or_else(|| expected_response_len(&request));
+
+    let mut m18 = connect(cli)?;
+    let mut session = m18.reset()?;
+    if !session.is_synced() {
+        return Err(M18Error::Timeout.into());
+    }
+    let response = session.send_raw(&request, response_len)?;
+
+    if cli.format == Format::Json {
+        let line = json!({
+            "request": hex(&request.encode()),
+            "response": hex(&response.encode()),
+            "header": response.header(),
+            "payload": hex(response.payload()),
+            "nack": response.is_nack(),
+        });
+        writeln!(out, "{}", serde_json::to_string_pretty(&line)?)?;
+    } else {
+        writeln!(out, "Sent:     {}", hex(&request.encode()))?;
+        writeln!(out, "Received: {}", hex(&response.encode()))?;
+    }
+
+    if response.is_nack() {
+        Ok(EXIT_PROTOCOL)
+    } else {
+        Ok(EXIT_OK)
+    }
+}
+
+/// Guess the reply length of a request from its command byte
+fn expected_response_len(request: &RequestFrame) -> usize {
+    const READ: u8 = 0x01;
+    match (request.header, request.acc) {
+        (READ, 0x04) if request.payload.len() == 3 => {
+            request.payload[2] as usize + frame::FRAME_OVERHEAD
+        }
+        (READ, 0x05) => 2,
+        (header, _) if header == u8::from(Command::Snapshot) => 8,
+        (header, _) if header == u8::from(Command::Keepalive) => 9,
+        (header, _) if header == u8::from(Command::Calibrate) => 8,
+        _ => frame::FRAME_OVERHEAD,
+    }
+}
+
+/// Parse an address given in hex (`0x4000`) or decimal
+fn parse_address(text: &str) -> Result<u16, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("invalid address: {}", text))
+}
+
+/// Parse a reply length of at least one byte
+fn parse_response_len(text: &str) -> Result<usize, String> {
+    match text.parse() {
+        Ok(0) => Err("reply length must be at least 1".to_string()),
+        Ok(length) => Ok(length),
+        Err(_) => Err(format!("invalid reply length: {}", text)),
+    }
+}
+
+/// Parse hex digits, ignoring whitespace
+fn parse_hex(text: &str) -> CliResult<Vec<u8>> {
+    let digits: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if !digits.is_ascii() || !digits.len().is_multiple_of(2) {
+        return Err(CliError::usage(
+            "Hex input must be an even number of hex digits",
+        ));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| CliError::usage(format!("Invalid hex: {}", &digits[i..i + 2])))
+        })
+        .collect()
+}
+
+/// Format bytes as space-separated hex
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,255 @@
+//! Configurable construction of [`M18`].
+//!
+//! [`M18::new`] opens a port with the defaults from [`constants`](crate::constants).
+//! Isolation circuits and adapters differ in how long J2 takes to settle and
+//! how soon they can turn the line around, so [`M18Builder`] lets every port
+//! and timing setting be chosen, and can wrap a port that is already open.
+//!
+//! # Examples
+//! ```
+//! use m18_protocol::{emulator::VirtualBattery, M18Builder};
+//! use std::time::Duration;
+//!
+//! let battery = VirtualBattery::new();
+//! let mut m18 = M18Builder::from_transport(battery.transport())
+//!     .reset_break(Duration::from_millis(100))
+//!     .reset_settle(Duration::from_millis(100))
+//!     .post_response_delay(Duration::ZERO)
+//!     .build()?;
+//!
+//! assert!(m18.reset()?.is_synced());
+//! assert_eq!(m18.timings().post_response_delay, Duration::ZERO);
+//! # Ok::<(), m18_protocol::M18Error>(())
+//! ```
+
+use crate::constants::*;
+use crate::data::RegisterMap;
+use crate::error::Result;
+use crate::line_control::LineControl;
+use crate::protocol::M18;
+use crate::retry::RetryPolicy;
+use crate::transport::{EchoCancellingTransport, SerialTransport, Transport};
+use serialport::SerialPort;
+use std::time::Duration;
+
+/// Delays used by the protocol.
+///
+/// The defaults are the values in [`constants`](crate::constants).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timings {
+    /// How long break and DTR are held during a reset
+    pub reset_break: Duration,
+    /// Wait after releasing break before sending the sync byte
+    pub reset_settle: Duration,
+    /// Wait after the battery echoes the sync byte
+    pub reset_sync_delay: Duration,
+    /// Wait after every reply before the next command
+    pub post_response_delay: Duration,
+    /// Interval between keepalives while simulating a charger
+    pub keepalive_interval: Duration,
+    /// How long a read may wait for reply bytes
+    pub read_timeout: Duration,
+}
+
+impl Default for Timings {
+    fn default() -> Self {
+        Timings {
+            reset_break: Duration::from_millis(RESET_BREAK_DURATION_MS),
+            reset_settle: Duration::from_millis(RESET_SETTLE_DURATION_MS),
+            reset_sync_delay: Duration::from_millis(RESET_SYNC_DELAY_MS),
+            post_response_delay: Duration::from_millis(POST_RESPONSE_DELAY_MS),
+            keepalive_interval: Duration::from_millis(KEEPALIVE_INTERVAL_MS),
+            read_timeout: Duration::from_millis(TIMEOUT_MS),
+        }
+    }
+}
+
+/// Where the builder gets its link to the battery
+enum PortSource {
+    /// Serial port to open by name
+    Name(String),
+    /// Serial port opened by the caller
+    Serial(Box<dyn SerialPort>),
+    /// Any other transport
+    Transport(Box<dyn Transport>),
+}
+
+/// Builder for an [`M18`] with custom port and timing options.
+pub struct M18Builder {
+    /// Link to the battery
+    source: PortSource,
+    /// Protocol delays
+    timings: Timings,
+    /// Debug printing of transmitted and received data
+    debug_print: (bool, bool),
+    /// Retry policy for the new interface
+    retry_policy: RetryPolicy,
+    /// Register map for the new interface
+    register_map: Option<RegisterMap>,
+    /// How the adapter drives J2
+    line_control: LineControl,
+    /// Whether to read back and verify the echo of every write
+    echo_cancellation: bool,
+}
+
+impl M18Builder {
+    /// Start building an interface on the named serial port.
+    ///
+    /// The port is opened by [`M18Builder::build`] at 4800 baud with 2 stop bits.
+    ///
+    /// # Arguments
+    /// * `port_name` - Serial port name (e.g., "COM3" on Windows, "/dev/ttyUSB0" on Linux)
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use m18_protocol::M18Builder;
+    /// use std::time::Duration;
+    ///
+    /// let mut m18 = M18Builder::new("/dev/ttyUSB0")
+    ///     .timeout(Duration::from_millis(500))
+    ///     .reset_settle(Duration::from_millis(500))
+    ///     .build()?;
+    /// # Ok::<(), m18_protocol::M18Error>(())
+    /// ```
+    pub fn new(port_name: &str) -> Self {
+        Self::with_source(PortSource::Name(port_name.to_string()))
+    }
+
+    /// Start building an interface on a serial port that is already open.
+    ///
+    /// The port's baud rate and stop bits are left as they are. Its timeout is
+    /// set to the read timeout (see [`M18Builder::timeout`]).
+    ///
+    /// # Arguments
+    /// * `port` - Open serial port
+    pub fn from_serial_port(port: Box<dyn SerialPort>) -> Self {
+        Self::with_source(PortSource::Serial(port))
+    }
+
+    /// Start building an interface on an arbitrary transport.
+    ///
+    /// The read timeout is passed to [`Transport::set_read_timeout`];
+    /// transports that never wait, such as the emulator, ignore it.
+    ///
+    /// # Arguments
+    /// * `transport` - Byte transport connected to the battery
+    pub fn from_transport(transport: impl Transport + 'static) -> Self {
+        Self::with_source(PortSource::Transport(Box::new(transport)))
+    }
+
+    /// Builder with default settings for the given link
+    fn with_source(source: PortSource) -> Self {
+        M18Builder {
+            source,
+            timings: Timings::default(),
+            debug_print: (false, false),
+            retry_policy: RetryPolicy::default(),
+            register_map: None,
+            line_control: LineControl::default(),
+            echo_cancellation: false,
+        }
+    }
+
+    /// Set how long a read may wait for reply bytes (default [`TIMEOUT_MS`]).
+    ///
+    /// Sets [`Timings::read_timeout`], which is applied to the port or
+    /// transport when the interface is built.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timings.read_timeout = timeout;
+        self
+    }
+
+    /// Set how long break and DTR are held during a reset.
+    pub fn reset_break(mut self, duration: Duration) -> Self {
+        self.timings.reset_break = duration;
+        self
+    }
+
+    /// Set the wait after releasing break before the sync byte is sent.
+    pub fn reset_settle(mut self, duration: Duration) -> Self {
+        self.timings.reset_settle = duration;
+        self
+    }
+
+    /// Set the wait after every reply before the next command is sent.
+    pub fn post_response_delay(mut self, delay: Duration) -> Self {
+        self.timings.post_response_delay = delay;
+        self
+    }
+
+    /// Set the interval between keepalives while simulating a charger.
+    pub fn keepalive_interval(mut self, interval: Duration) -> Self {
+        self.timings.keepalive_interval = interval;
+        self
+    }
+
+    /// Replace all protocol delays at once.
+    pub fn timings(mut self, timings: Timings) -> Self {
+        self.timings = timings;
+        self
+    }
+
+    /// Set how the adapter drives J2 (default break and DTR together).
+    pub fn line_control(mut self, line_control: LineControl) -> Self {
+        self.line_control = line_control;
+        self
+    }
+
+    /// Cancel the echo of a single-wire link (default off).
+    ///
+    /// For adapters that tie TX and RX together; see
+    /// [`EchoCancellingTransport`].
+    pub fn echo_cancellation(mut self, enabled: bool) -> Self {
+        self.echo_cancellation = enabled;
+        self
+    }
+
+    /// Enable debug printing of transmitted and received data.
+    ///
+    /// See [`M18::set_debug_print`].
+    pub fn debug_print(mut self, tx: bool, rx: bool) -> Self {
+        self.debug_print = (tx, rx);
+        self
+    }
+
+    /// Set how failed commands are retried.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Use a different register map.
+    ///
+    /// See [`M18::with_register_map`].
+    pub fn register_map(mut self, register_map: RegisterMap) -> Self {
+        self.register_map = Some(register_map);
+        self
+    }
+
+    /// Open the port if needed and create the interface.
+    ///
+    /// The interface is put into the idle state before returning.
+    ///
+    /// # Errors
+    /// Returns error if the serial port cannot be opened or configured.
+    pub fn build(self) -> Result<M18> {
+        let timeout = self.timings.read_timeout;
+        let mut transport: Box<dyn Transport> = match self.source {
+            PortSource::Name(name) => Box::new(SerialTransport::open_with_timeout(&name, timeout)?),
+            PortSource::Serial(port) => Box::new(SerialTransport::new(port)),
+            PortSource::Transport(transport) => transport,
+        };
+        transport.set_read_timeout(timeout)?;
+        if self.echo_cancellation {
+            transport = Box::new(EchoCancellingTransport::new(transport));
+        }
+
+        let mut m18 = M18::with_transport_and_timings(transport, self.timings, self.line_control);
+        m18.set_debug_print(self.debug_print.0, self.debug_print.1);
+        m18.set_retry_policy(self.retry_policy);
+        if let Some(register_map) = self.register_map {
+            m18 = m18.with_register_map(register_map);
+        }
+        Ok(m18)
+    }
+}
@@ -0,0 +1,157 @@
+//! Charger simulation running on a background thread.
+//!
+//! [`M18::simulate_for`](crate::M18::simulate_for) blocks the caller until the
+//! simulation ends. A [`ChargeSession`] runs the same configure, snapshot and
+//! keepalive sequence on its own thread instead, reports its progress as
+//! [`ChargeEvent`]s and runs until [`ChargeSession::stop`] is called or the
+//! link to the battery is lost.
+//!
+//! # Examples
+//! ```
+//! use m18_protocol::{emulator::VirtualBattery, ChargeEvent, ChargeSession, ChargerProfile, M18};
+//!
+//! let battery = VirtualBattery::new();
+//! let m18 = M18::with_transport(battery.transport());
+//!
+//! let (session, events) = ChargeSession::start(m18, ChargerProfile::standard())?;
+//! for event in &events {
+//!     if let ChargeEvent::Keepalive(keepalive) = event {
+//!         println!("Pack voltage: {:.2}V", keepalive.pack_voltage);
+//!         break;
+//!     }
+//! }
+//!
+//! let _m18 = session.stop();
+//! assert!(matches!(events.iter().last(), Some(ChargeEvent::Stopped)));
+//! # Ok::<(), m18_protocol::M18Error>(())
+//! ```
+
+use crate::error::{M18Error, Result};
+use crate::protocol::M18;
+use crate::types::{ChargerProfile, KeepaliveResponse, SnapshotResponse};
+use std::panic;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+
+/// Progress of a charger simulation.
+#[derive(Debug)]
+pub enum ChargeEvent {
+    /// Battery accepted the initialization configure; carries its snapshot
+    Initialized(SnapshotResponse),
+    /// Battery accepted the active configure; carries its snapshot
+    Active(SnapshotResponse),
+    /// Keepalive reply received
+    Keepalive(KeepaliveResponse),
+    /// Communication failed; the session ends after this event
+    LinkLost(M18Error),
+    /// Session ended and J2 was returned to idle; always the last event
+    Stopped,
+}
+
+/// Charger simulation running on a background thread.
+///
+/// The session owns the [`M18`] for as long as it runs. Dropping the session
+/// stops it and discards the connection; use [`ChargeSession::stop`] to get
+/// the connection back.
+pub struct ChargeSession {
+    /// Signals the worker thread to stop
+    stop_tx: Sender<()>,
+    /// Worker thread, returning the connection when it exits
+    handle: Option<JoinHandle<M18>>,
+}
+
+impl ChargeSession {
+    /// Start a simulation that delivers events through a channel.
+    ///
+    /// # Arguments
+    /// * `m18` - Connection to the battery
+    /// * `profile` - Charger parameters to announce
+    ///
+    /// # Returns
+    /// The running session and the receiving end of its event channel.
+    ///
+    /// # Errors
+    /// Returns `M18Error::ChargerProfileOutOfRange` if the profile is invalid.
+    pub fn start(m18: M18, profile: ChargerProfile) -> Result<(Self, Receiver<ChargeEvent>)> {
+        let (event_tx, event_rx) = mpsc::channel();
+        let session = Self::start_with_callback(m18, profile, move |event| {
+            // The receiver may already be gone; the session keeps running regardless
+            let _ = event_tx.send(event);
+        })?;
+        Ok((session, event_rx))
+    }
+
+    /// Start a simulation that delivers events to a callback.
+    ///
+    /// The callback runs on the session thread, so it should return quickly
+    /// to avoid delaying keepalives.
+    ///
+    /// # Arguments
+    /// * `m18` - Connection to the battery
+    /// * `profile` - Charger parameters to announce
+    /// * `on_event` - Called with each event, ending with `ChargeEvent::Stopped`
+    ///
+    /// # Errors
+    /// Returns `M18Error::ChargerProfileOutOfRange` if the profile is invalid.
+    pub fn start_with_callback(
+        mut m18: M18,
+        profile: ChargerProfile,
+        mut on_event: impl FnMut(ChargeEvent) + Send + 'static,
+    ) -> Result<Self> {
+        profile.validate()?;
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+
+        let handle = thread::spawn(move || {
+            let result = m18.run_charger(
+                &profile,
+                |_, delay| matches!(stop_rx.recv_timeout(delay), Err(RecvTimeoutError::Timeout)),
+                &mut on_event,
+            );
+            if let Err(e) = result {
+                on_event(ChargeEvent::LinkLost(e));
+            }
+            on_event(ChargeEvent::Stopped);
+            m18
+        });
+
+        Ok(ChargeSession {
+            stop_tx,
+            handle: Some(handle),
+        })
+    }
+
+    /// Whether the session thread is still running.
+    ///
+    /// Returns `false` once the session has stopped, including after a lost link.
+    pub fn is_running(&self) -> bool {
+        self.handle
+            .as_ref()
+            .is_some_and(|handle| !handle.is_finished())
+    }
+
+    /// Stop the session and wait for the thread to finish.
+    ///
+    /// # Returns
+    /// The connection, with J2 back in the idle state.
+    pub fn stop(mut self) -> M18 {
+        self.join().expect("session thread is joined only once")
+    }
+
+    /// Signal the thread to stop and wait for it
+    fn join(&mut self) -> Option<M18> {
+        let _ = self.stop_tx.send(());
+        let handle = self.handle.take()?;
+        match handle.join() {
+            Ok(m18) => Some(m18),
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+impl Drop for ChargeSession {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            self.join();
+        }
+    }
+}
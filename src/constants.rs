@@ -12,6 +12,21 @@ pub const CUTOFF_CURRENT: u16 = 300;
 /// Maximum current in milliamps
 pub const MAX_CURRENT: u16 = 6000;
 
+/// Lowest cutoff current accepted in a charger profile (mA)
+pub const MIN_CUTOFF_CURRENT: u16 = 100;
+
+/// Highest cutoff current accepted in a charger profile (mA)
+pub const MAX_CUTOFF_CURRENT: u16 = 1000;
+
+/// Lowest maximum charge current accepted in a charger profile (mA)
+pub const MIN_CHARGE_CURRENT: u16 = 500;
+
+/// Highest maximum charge current accepted in a charger profile (mA)
+pub const MAX_CHARGE_CURRENT: u16 = 12000;
+
+/// Longest memory read planned by default, in bytes (the largest known region)
+pub const MAX_READ_LENGTH: u8 = 58;
+
 /// Initial ACC value to use after reset
 pub const INITIAL_ACC: u8 = 4;
 
@@ -36,6 +51,9 @@ pub const RESET_SETTLE_DURATION_MS: u64 = 300;
 /// Delay after successful sync response
 pub const RESET_SYNC_DELAY_MS: u64 = 10;
 
+/// Delay after every response, to improve reliability with isolation circuits
+pub const POST_RESPONSE_DELAY_MS: u64 = 50;
+
 /// Interval between keepalive messages during simulation
 pub const KEEPALIVE_INTERVAL_MS: u64 = 500;
 
@@ -1,15 +1,53 @@
 //! Battery memory map and register definitions.
 //!
-//! This module contains the complete memory map for Milwaukee M18 batteries,
-//! including all known memory regions and register definitions with their
-//! data types and labels.
+//! The memory map for Milwaukee M18 batteries lives in a schema file,
+//! `data/register_map.toml`, listing every known memory region and register
+//! with its data type, label, group, units and battery family. The file is
+//! embedded at build time and parsed into a [`RegisterMap`]. A replacement map
+//! can be loaded at runtime with [`RegisterMap::load`] and handed to
+//! [`M18::with_register_map`](crate::M18::with_register_map), so newly
+//! discovered labels do not need a code change.
+//!
+//! # Examples
+//! ```
+//! use m18_protocol::data::RegisterMap;
+//!
+//! let map = RegisterMap::embedded();
+//! let cells = map.register(12).unwrap();
+//! assert_eq!(cells.label, "Cell voltages (mV)");
+//! assert_eq!(cells.units.as_deref(), Some("mV"));
+//! ```
+
+use crate::error::{M18Error, Result};
+use crate::register::RegisterSelector;
+use crate::types::{BatteryFamily, DataType, MemoryRegion, RegisterDef};
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::LazyLock;
 
-use crate::types::{DataType, MemoryRegion, RegisterDef};
+/// Version of the register map schema understood by this library
+pub const REGISTER_MAP_VERSION: u32 = 1;
+
+/// Source of the embedded register map
+const EMBEDDED_MAP: &str = include_str!("../data/register_map.toml");
+
+/// Parsed embedded register map
+static EMBEDDED: LazyLock<RegisterMap> =
+    LazyLock::new(|| RegisterMap::from_toml(EMBEDDED_MAP).expect("embedded register map is valid"));
 
 /// Memory regions to read from the battery.
 ///
 /// These represent all known readable memory regions in the battery's address space.
 /// Each region is read as a contiguous block during data collection operations.
+/// [`RegisterMap::embedded`] lists the same regions and should be preferred;
+/// this copy is kept for existing code.
+///
+/// # Examples
+/// ```
+/// use m18_protocol::data::{RegisterMap, DATA_MATRIX};
+///
+/// assert_eq!(DATA_MATRIX, RegisterMap::embedded().regions());
+/// ```
 pub const DATA_MATRIX: &[MemoryRegion] = &[
     MemoryRegion {
         address_high: 0x00,
@@ -161,11 +199,6 @@ pub const DATA_MATRIX: &[MemoryRegion] = &[
         address_low: 0x22,
         length: 0x30,
     },
-    MemoryRegion {
-        address_high: 0x91,
-        address_low: 0x52,
-        length: 0x00,
-    },
     MemoryRegion {
         address_high: 0xA0,
         address_low: 0x00,
@@ -180,1118 +213,398 @@ pub const DATA_MATRIX: &[MemoryRegion] = &[
 /// starting from 0.
 ///
 /// # Returns
-/// Vector of RegisterDef entries indexed by register ID (0-183).
+/// Vector of RegisterDef entries indexed by register ID (0-183), taken from
+/// [`RegisterMap::embedded`].
 pub fn create_data_id() -> Vec<RegisterDef> {
-    vec![
-        RegisterDef {
-            address: 0x0000,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Cell type".to_string(),
-        },
-        RegisterDef {
-            address: 0x0002,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown (always 0)".to_string(),
-        },
-        RegisterDef {
-            address: 0x0004,
-            length: 5,
-            data_type: DataType::SerialNumber,
-            label: "Capacity & Serial number (?)".to_string(),
-        },
-        RegisterDef {
-            address: 0x000D,
-            length: 4,
-            data_type: DataType::UInt,
-            label: "Unknown (4th code?)".to_string(),
-        },
-        RegisterDef {
-            address: 0x0011,
-            length: 4,
-            data_type: DataType::Date,
-            label: "Manufacture date".to_string(),
-        },
-        RegisterDef {
-            address: 0x0015,
-            length: 4,
-            data_type: DataType::Date,
-            label: "Date of first charge (Forge)".to_string(),
-        },
-        RegisterDef {
-            address: 0x0019,
-            length: 4,
-            data_type: DataType::Date,
-            label: "Date of last charge (Forge)".to_string(),
-        },
-        RegisterDef {
-            address: 0x0023,
-            length: 20,
-            data_type: DataType::Ascii,
-            label: "Note (ascii string)".to_string(),
-        },
-        RegisterDef {
-            address: 0x0037,
-            length: 4,
-            data_type: DataType::Date,
-            label: "Current date".to_string(),
-        },
-        RegisterDef {
-            address: 0x0069,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown (always 2)".to_string(),
-        },
-        RegisterDef {
-            address: 0x007B,
-            length: 1,
-            data_type: DataType::UInt,
-            label: "Unknown (always 0)".to_string(),
-        },
-        RegisterDef {
-            address: 0x4000,
-            length: 4,
-            data_type: DataType::UInt,
-            label: "Unknown (Forge)".to_string(),
-        },
-        RegisterDef {
-            address: 0x400A,
-            length: 10,
-            data_type: DataType::CellVoltages,
-            label: "Cell voltages (mV)".to_string(),
-        },
-        RegisterDef {
-            address: 0x4014,
-            length: 2,
-            data_type: DataType::AdcTemperature,
-            label: "Temperature (C) (non-Forge)".to_string(),
-        },
-        RegisterDef {
-            address: 0x4016,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown (Forge)".to_string(),
-        },
-        RegisterDef {
-            address: 0x4019,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown (Forge)".to_string(),
-        },
-        RegisterDef {
-            address: 0x401B,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown (Forge)".to_string(),
-        },
-        RegisterDef {
-            address: 0x401D,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown (Forge)".to_string(),
-        },
-        RegisterDef {
-            address: 0x401F,
-            length: 2,
-            data_type: DataType::DecimalTemperature,
-            label: "Temperature (C) (Forge)".to_string(),
-        },
-        RegisterDef {
-            address: 0x6000,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown (Forge)".to_string(),
-        },
-        RegisterDef {
-            address: 0x6002,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown (Forge)".to_string(),
-        },
-        RegisterDef {
-            address: 0x6004,
-            length: 4,
-            data_type: DataType::UInt,
-            label: "Unknown (Forge)".to_string(),
-        },
-        RegisterDef {
-            address: 0x6008,
-            length: 4,
-            data_type: DataType::UInt,
-            label: "Unknown (Forge)".to_string(),
-        },
-        RegisterDef {
-            address: 0x600C,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown (Forge)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9000,
-            length: 4,
-            data_type: DataType::Date,
-            label: "Date of first charge (rounded)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9004,
-            length: 4,
-            data_type: DataType::Date,
-            label: "Date of last tool use (rounded)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9008,
-            length: 4,
-            data_type: DataType::Date,
-            label: "Date of last charge (rounded)".to_string(),
-        },
-        RegisterDef {
-            address: 0x900C,
-            length: 4,
-            data_type: DataType::Date,
-            label: "Unknown date (often zero)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9010,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Days since first charge".to_string(),
-        },
-        RegisterDef {
-            address: 0x9012,
-            length: 4,
-            data_type: DataType::UInt,
-            label: "Total discharge (amp-sec)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9016,
-            length: 4,
-            data_type: DataType::UInt,
-            label: "Total discharge (watt-sec or joules)".to_string(),
-        },
-        RegisterDef {
-            address: 0x901A,
-            length: 4,
-            data_type: DataType::UInt,
-            label: "Total charge count".to_string(),
-        },
-        RegisterDef {
-            address: 0x901E,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Dumb charge count (J2>7.1V for >=0.48s)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9020,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Redlink (UART) charge count".to_string(),
-        },
-        RegisterDef {
-            address: 0x9022,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Completed charge count (?)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9024,
-            length: 4,
-            data_type: DataType::Duration,
-            label: "Total charging time (HH:MM:SS)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9028,
-            length: 4,
-            data_type: DataType::Duration,
-            label: "Time on charger whilst full (HH:MM:SS)".to_string(),
-        },
-        RegisterDef {
-            address: 0x902C,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown (another low-voltage charge counter?)".to_string(),
-        },
-        RegisterDef {
-            address: 0x902E,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge started with a cell < 2.5V".to_string(),
-        },
-        RegisterDef {
-            address: 0x9030,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Discharge to empty".to_string(),
-        },
-        RegisterDef {
-            address: 0x9032,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Num. overheat on tool (must be > 10A)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9034,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Overcurrent?".to_string(),
-        },
-        RegisterDef {
-            address: 0x9036,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Low voltage events)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9038,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Low-voltage bounce? (4 flashing LEDs)".to_string(),
-        },
-        // Discharge histogram entries (10-20A through 200A+)
-        RegisterDef {
-            address: 0x903A,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Discharge @ 10-20A (seconds)".to_string(),
-        },
-        RegisterDef {
-            address: 0x903C,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 20-30A (could be watts)".to_string(),
-        },
-        RegisterDef {
-            address: 0x903E,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 30-40A      ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9040,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 40-50A      ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9042,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 50-60A      ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9044,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 60-70A      ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9046,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 70-80A      ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9048,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 80-90A      ".to_string(),
-        },
-        RegisterDef {
-            address: 0x904A,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 90-100A     ".to_string(),
-        },
-        RegisterDef {
-            address: 0x904C,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 100-110A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x904E,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 110-120A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9050,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 120-130A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9052,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 130-140A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9054,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 140-150A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9056,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 150-160A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9058,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 160-170A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x905A,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 170-180A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x905C,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 180-190A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x905E,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 190-200A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9060,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 200-210A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9062,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Discharge @ 5-10A (seconds)".to_string(),
-        },
-        // Second set of discharge histogram (10-15A through 200A+)
-        RegisterDef {
-            address: 0x9064,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 10-15A (could be watts)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9066,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 15-20A (histo not well understood yet)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9068,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 20-25A      ".to_string(),
-        },
-        RegisterDef {
-            address: 0x906A,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 25-30A      ".to_string(),
-        },
-        RegisterDef {
-            address: 0x906C,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 30-35A      ".to_string(),
-        },
-        RegisterDef {
-            address: 0x906E,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 35-40A      ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9070,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 40-45A      ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9072,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 45-50A      ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9074,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 50-55A      ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9076,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 55-60A      ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9078,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 60-65A      ".to_string(),
-        },
-        RegisterDef {
-            address: 0x907A,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 65-70A      ".to_string(),
-        },
-        RegisterDef {
-            address: 0x907C,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 70-65A      ".to_string(),
-        },
-        RegisterDef {
-            address: 0x907E,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 75-80A      ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9080,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 80-85A      ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9082,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 85-90A      ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9084,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 90-95A      ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9086,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 95-100A     ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9088,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 100-105A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x908A,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 105-110A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x908C,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 110-115A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x908E,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 115-120A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9090,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 120-125A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9092,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 125-130A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9094,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 130-135A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9096,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 135-140A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x9098,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 140-145A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x909A,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 145-150A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x909C,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 150-155A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x909E,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 155-160A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x90A0,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 160-165A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x90A2,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 165-170A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x90A4,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 170-175A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x90A6,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 175-180A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x90A8,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 180-185A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x90AA,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 185-190A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x90AC,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 190-195A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x90AE,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 195-200A    ".to_string(),
-        },
-        RegisterDef {
-            address: 0x90B0,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "          @ 200A+       ".to_string(),
-        },
-        // Charge voltage ranges
-        RegisterDef {
-            address: 0x90B2,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge started < 17V".to_string(),
-        },
-        RegisterDef {
-            address: 0x90B4,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge started 17-18V".to_string(),
-        },
-        RegisterDef {
-            address: 0x90B6,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge started 18-19V".to_string(),
-        },
-        RegisterDef {
-            address: 0x90B8,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge started 19-20V".to_string(),
-        },
-        RegisterDef {
-            address: 0x90BA,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge started 20V+".to_string(),
-        },
-        RegisterDef {
-            address: 0x90BC,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge ended < 17V".to_string(),
-        },
-        RegisterDef {
-            address: 0x90BE,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge ended 17-18V".to_string(),
-        },
-        RegisterDef {
-            address: 0x90C0,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge ended 18-19V".to_string(),
-        },
-        RegisterDef {
-            address: 0x90C2,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge ended 19-20V".to_string(),
-        },
-        RegisterDef {
-            address: 0x90C4,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge ended 20V+".to_string(),
-        },
-        // Temperature ranges for charging
-        RegisterDef {
-            address: 0x90C6,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge start temp -30C to -20C".to_string(),
-        },
-        RegisterDef {
-            address: 0x90C8,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge start temp -20C to -10C".to_string(),
-        },
-        RegisterDef {
-            address: 0x90CA,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge start temp -10C to 0C".to_string(),
-        },
-        RegisterDef {
-            address: 0x90CC,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge start temp 0C to +10C".to_string(),
-        },
-        RegisterDef {
-            address: 0x90CE,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge start temp +10C to +20C".to_string(),
-        },
-        RegisterDef {
-            address: 0x90D0,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge start temp +20C to +30C".to_string(),
-        },
-        RegisterDef {
-            address: 0x90D2,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge start temp +30C to +40C".to_string(),
-        },
-        RegisterDef {
-            address: 0x90D4,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge start temp +40C to +50C".to_string(),
-        },
-        RegisterDef {
-            address: 0x90D6,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge start temp +50C to +60C".to_string(),
-        },
-        RegisterDef {
-            address: 0x90D8,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge start temp +60C to +70C".to_string(),
-        },
-        RegisterDef {
-            address: 0x90DA,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge start temp +70C to +80C".to_string(),
-        },
-        RegisterDef {
-            address: 0x90DC,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge start temp +80C and over".to_string(),
-        },
-        RegisterDef {
-            address: 0x90DE,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge end temp -30C to -20C".to_string(),
-        },
-        RegisterDef {
-            address: 0x90E0,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge end temp -20C to -10C".to_string(),
-        },
-        RegisterDef {
-            address: 0x90E2,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge end temp -10C to 0C".to_string(),
-        },
-        RegisterDef {
-            address: 0x90E4,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge end temp 0C to +10C".to_string(),
-        },
-        RegisterDef {
-            address: 0x90E6,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge end temp +10C to +20C".to_string(),
-        },
-        RegisterDef {
-            address: 0x90E8,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge end temp +30C to +30C".to_string(),
-        },
-        RegisterDef {
-            address: 0x90EA,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge end temp +30C to +40C".to_string(),
-        },
-        RegisterDef {
-            address: 0x90EC,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge end temp +40C to +50C".to_string(),
-        },
-        RegisterDef {
-            address: 0x90EE,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge end temp +50C to +60C".to_string(),
-        },
-        RegisterDef {
-            address: 0x90F0,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge end temp +60C to +70C".to_string(),
-        },
-        RegisterDef {
-            address: 0x90F2,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge end temp +70C to +80C".to_string(),
-        },
-        RegisterDef {
-            address: 0x90F4,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Charge end temp +80C and over".to_string(),
-        },
-        // Charge time histograms
-        RegisterDef {
-            address: 0x90F6,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Dumb charge time (00:00-14:33)".to_string(),
-        },
-        RegisterDef {
-            address: 0x90F8,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Dumb charge time (14:34-29:07)".to_string(),
-        },
-        RegisterDef {
-            address: 0x90FA,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Dumb charge time (29:08-43:41)".to_string(),
-        },
-        RegisterDef {
-            address: 0x90FC,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Dumb charge time (43:42-58:15)".to_string(),
-        },
-        RegisterDef {
-            address: 0x90FE,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Dumb charge time (58:16-1:12:49)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9100,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Dumb charge time (1:12:50-1:27:23)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9102,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Dumb charge time (1:27:24-1:41:57)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9104,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Dumb charge time (1:41:58-1:56:31)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9106,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Dumb charge time (1:56:32-2:11:05)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9108,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Dumb charge time (2:11:06-2:25:39)".to_string(),
-        },
-        RegisterDef {
-            address: 0x910A,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Dumb charge time (2:25:40-2:40:13)".to_string(),
-        },
-        RegisterDef {
-            address: 0x910C,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Dumb charge time (2:40:14-2:54:47)".to_string(),
-        },
-        RegisterDef {
-            address: 0x910E,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Dumb charge time (2:54:48-3:09:21)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9110,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Dumb charge time (3:09:22-3:23:55)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9112,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Redlink charge time (00:00-17:03)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9114,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Redlink charge time (17:04-34:07)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9116,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Redlink charge time (34:08-51:11)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9118,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Redlink charge time (51:12-1:08:15)".to_string(),
-        },
-        RegisterDef {
-            address: 0x911A,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Redlink charge time (1:08:16-1:25:19)".to_string(),
-        },
-        RegisterDef {
-            address: 0x911C,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Redlink charge time (1:25:20-1:42:23)".to_string(),
-        },
-        RegisterDef {
-            address: 0x911E,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Redlink charge time (1:42:24-1:59:27)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9120,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Redlink charge time (1:59:28-2:16:31)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9122,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Redlink charge time (2:16:32-2:33:35)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9124,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Redlink charge time (2:33:36-2:50:39)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9126,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Redlink charge time (2:50:40-3:07:43)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9128,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Redlink charge time (3:07:44-3:24:47)".to_string(),
-        },
-        RegisterDef {
-            address: 0x912A,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Redlink charge time (3:24:48-3:41:51)".to_string(),
-        },
-        RegisterDef {
-            address: 0x912C,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Redlink charge time (3:41:52-3:58:55)".to_string(),
-        },
-        RegisterDef {
-            address: 0x912E,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Completed charge (?)".to_string(),
-        },
-        // Final unknown registers
-        RegisterDef {
-            address: 0x9130,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown".to_string(),
-        },
-        RegisterDef {
-            address: 0x9132,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown".to_string(),
-        },
-        RegisterDef {
-            address: 0x9134,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown".to_string(),
-        },
-        RegisterDef {
-            address: 0x9136,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown".to_string(),
-        },
-        RegisterDef {
-            address: 0x9138,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown".to_string(),
-        },
-        RegisterDef {
-            address: 0x913A,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown".to_string(),
-        },
-        RegisterDef {
-            address: 0x913C,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown".to_string(),
-        },
-        RegisterDef {
-            address: 0x913E,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown".to_string(),
-        },
-        RegisterDef {
-            address: 0x9140,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown".to_string(),
-        },
-        RegisterDef {
-            address: 0x9142,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown histogram (temperature?)".to_string(),
-        },
-        RegisterDef {
-            address: 0x9144,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown histogram".to_string(),
-        },
-        RegisterDef {
-            address: 0x9146,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown histogram".to_string(),
-        },
-        RegisterDef {
-            address: 0x9148,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown histogram".to_string(),
-        },
-        RegisterDef {
-            address: 0x914A,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown histogram".to_string(),
-        },
-        RegisterDef {
-            address: 0x914C,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown histogram".to_string(),
-        },
-        RegisterDef {
-            address: 0x914E,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown histogram".to_string(),
-        },
-        RegisterDef {
-            address: 0x9150,
-            length: 2,
-            data_type: DataType::UInt,
-            label: "Unknown histogram".to_string(),
-        },
-    ]
+    RegisterMap::embedded().registers().to_vec()
+}
+
+/// Validated set of memory regions and register definitions.
+///
+/// Register IDs are positions in the register list. Every register lies
+/// inside a single region and no two registers or regions overlap.
+#[derive(Debug, Clone)]
+pub struct RegisterMap {
+    /// Blocks read in one command each when capturing the whole memory
+    regions: Vec<MemoryRegion>,
+    /// Register definitions indexed by register ID
+    registers: Vec<RegisterDef>,
+}
+
+impl RegisterMap {
+    /// Register map built into the library.
+    pub fn embedded() -> &'static RegisterMap {
+        &EMBEDDED
+    }
+
+    /// Create a register map from regions and register definitions.
+    ///
+    /// # Errors
+    /// Returns `M18Error::InvalidRegisterMap` if a register is empty, has a
+    /// length its data type cannot hold, lies outside every region, or
+    /// overlaps another register, or if a region is empty or two regions
+    /// overlap.
+    pub fn new(regions: Vec<MemoryRegion>, registers: Vec<RegisterDef>) -> Result<Self> {
+        let map = RegisterMap { regions, registers };
+        map.validate()?;
+        Ok(map)
+    }
+
+    /// Parse a register map in the TOML schema used by the embedded map.
+    ///
+    /// # Errors
+    /// Returns `M18Error::InvalidRegisterMap` if the text does not follow the
+    /// schema or the map fails validation.
+    ///
+    /// # Examples
+    /// ```
+    /// use m18_protocol::data::RegisterMap;
+    ///
+    /// let map = RegisterMap::from_toml(r#"
+    ///     version = 1
+    ///
+    ///     [[region]]
+    ///     address = 0x4000
+    ///     length = 12
+    ///
+    ///     [[register]]
+    ///     address = 0x400A
+    ///     length = 2
+    ///     type = "uint"
+    ///     label = "Pack voltage (mV)"
+    ///     units = "mV"
+    /// "#)?;
+    /// assert_eq!(map.registers().len(), 1);
+    ///
+    /// // Registers must lie inside a region
+    /// let outside = RegisterMap::from_toml(r#"
+    ///     version = 1
+    ///
+    ///     [[register]]
+    ///     address = 0x9000
+    ///     length = 2
+    ///     type = "uint"
+    ///     label = "Nowhere"
+    /// "#);
+    /// assert!(outside.is_err());
+    ///
+    /// // Lengths must suit the data type
+    /// let short = RegisterMap::from_toml(r#"
+    ///     version = 1
+    ///
+    ///     [[region]]
+    ///     address = 0x4000
+    ///     length = 12
+    ///
+    ///     [[register]]
+    ///     address = 0x4000
+    ///     length = 2
+    ///     type = "hhmmss"
+    ///     label = "Too short"
+    /// "#);
+    /// assert!(short.is_err());
+    ///
+    /// // Regions must not be empty
+    /// let empty = RegisterMap::from_toml(r#"
+    ///     version = 1
+    ///
+    ///     [[region]]
+    ///     address = 0x4000
+    ///     length = 0
+    /// "#);
+    /// assert!(empty.is_err());
+    /// # Ok::<(), m18_protocol::M18Error>(())
+    /// ```
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let file: MapFile = toml::from_str(text)
+            .map_err(|e| M18Error::InvalidRegisterMap(format!("Invalid TOML: {}", e)))?;
+        file.into_map()
+    }
+
+    /// Parse a register map from JSON with the same structure as the TOML schema.
+    ///
+    /// Addresses may be given as numbers or as hex strings such as `"0x9000"`.
+    ///
+    /// # Errors
+    /// Returns `M18Error::InvalidRegisterMap` if the text does not follow the
+    /// schema or the map fails validation.
+    pub fn from_json(text: &str) -> Result<Self> {
+        let file: MapFile = serde_json::from_str(text)
+            .map_err(|e| M18Error::InvalidRegisterMap(format!("Invalid JSON: {}", e)))?;
+        file.into_map()
+    }
+
+    /// Load a register map from a file.
+    ///
+    /// Files ending in `.json` are parsed as JSON, anything else as TOML.
+    ///
+    /// # Errors
+    /// Returns `M18Error::Io` if the file cannot be read, or
+    /// `M18Error::InvalidRegisterMap` if it is not a valid map.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        if path.extension().is_some_and(|ext| ext == "json") {
+            Self::from_json(&text)
+        } else {
+            Self::from_toml(&text)
+        }
+    }
+
+    /// Memory regions, in the order they are read.
+    pub fn regions(&self) -> &[MemoryRegion] {
+        &self.regions
+    }
+
+    /// Register definitions, indexed by register ID.
+    pub fn registers(&self) -> &[RegisterDef] {
+        &self.registers
+    }
+
+    /// Definition of the register with the given ID.
+    pub fn register(&self, id: usize) -> Option<&RegisterDef> {
+        self.registers.get(id)
+    }
+
+    /// ID of the register starting at `address`.
+    pub fn find_address(&self, address: u16) -> Option<usize> {
+        self.registers
+            .iter()
+            .position(|register| register.address == address)
+    }
+
+    /// ID of the first register whose label matches, ignoring case and
+    /// surrounding whitespace.
+    ///
+    /// # Examples
+    /// ```
+    /// use m18_protocol::{Register, RegisterMap};
+    ///
+    /// let map = RegisterMap::embedded();
+    /// let id = map.find_label("manufacture date").unwrap();
+    /// assert_eq!(map.find_address(Register::ManufactureDate.address()), Some(id));
+    /// ```
+    pub fn find_label(&self, label: &str) -> Option<usize> {
+        let label = label.trim();
+        self.registers
+            .iter()
+            .position(|register| register.label.trim().eq_ignore_ascii_case(label))
+    }
+
+    /// ID of the register picked by `selector`.
+    pub fn resolve(&self, selector: &impl RegisterSelector) -> Option<usize> {
+        selector.resolve(self)
+    }
+
+    /// Check the map for empty, stray and overlapping entries
+    fn validate(&self) -> Result<()> {
+        if let Some(region) = self.regions.iter().find(|region| region.length == 0) {
+            return Err(M18Error::InvalidRegisterMap(format!(
+                "Region 0x{:04X} is empty",
+                region.address()
+            )));
+        }
+
+        let mut regions: Vec<(u32, u32)> = self
+            .regions
+            .iter()
+            .map(|region| {
+                let start = region.address() as u32;
+                (start, start + region.length as u32)
+            })
+            .collect();
+        regions.sort_unstable();
+
+        for pair in regions.windows(2) {
+            if pair[1].0 < pair[0].1 {
+                return Err(M18Error::InvalidRegisterMap(format!(
+                    "Region 0x{:04X} overlaps region 0x{:04X}",
+                    pair[1].0, pair[0].0
+                )));
+            }
+        }
+        if let Some(&(start, end)) = regions.last() {
+            if end > 0x10000 {
+                return Err(M18Error::InvalidRegisterMap(format!(
+                    "Region 0x{:04X} extends past the end of memory",
+                    start
+                )));
+            }
+        }
+
+        let mut spans = Vec::with_capacity(self.registers.len());
+        for (id, register) in self.registers.iter().enumerate() {
+            let start = register.address as u32;
+            let end = start + register.length as u32;
+            if register.length == 0 {
+                return Err(M18Error::InvalidRegisterMap(format!(
+                    "Register {} \"{}\" is empty",
+                    id,
+                    register.label.trim()
+                )));
+            }
+            if let Some(lengths) = valid_lengths(&register.data_type) {
+                if !lengths.contains(&register.length) {
+                    return Err(M18Error::InvalidRegisterMap(format!(
+                        "Register {} \"{}\" is {} bytes long, but {:?} registers must be {:?}",
+                        id,
+                        register.label.trim(),
+                        register.length,
+                        register.data_type,
+                        lengths
+                    )));
+                }
+            }
+            if !regions
+                .iter()
+                .any(|&(low, high)| low <= start && end <= high)
+            {
+                return Err(M18Error::InvalidRegisterMap(format!(
+                    "Register {} \"{}\" at 0x{:04X}+{} is outside every region",
+                    id,
+                    register.label.trim(),
+                    register.address,
+                    register.length
+                )));
+            }
+            spans.push((start, end, id));
+        }
+
+        spans.sort_unstable();
+        for pair in spans.windows(2) {
+            let (_, previous_end, previous) = pair[0];
+            let (start, _, id) = pair[1];
+            if start < previous_end {
+                return Err(M18Error::InvalidRegisterMap(format!(
+                    "Register {} \"{}\" overlaps register {} \"{}\"",
+                    id,
+                    self.registers[id].label.trim(),
+                    previous,
+                    self.registers[previous].label.trim()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Lengths a register of the given type may have, or `None` for any length
+fn valid_lengths(data_type: &DataType) -> Option<&'static [u8]> {
+    match data_type {
+        DataType::UInt => Some(&[1, 2, 4, 8]),
+        DataType::Date | DataType::Duration => Some(&[4]),
+        DataType::SerialNumber => Some(&[5]),
+        DataType::AdcTemperature | DataType::DecimalTemperature => Some(&[2]),
+        DataType::CellVoltages => Some(&[10]),
+        DataType::Ascii => None,
+    }
+}
+
+/// Top level of a register map file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MapFile {
+    version: u32,
+    #[serde(default, rename = "region", alias = "regions")]
+    regions: Vec<RegionEntry>,
+    #[serde(default, rename = "register", alias = "registers")]
+    registers: Vec<RegisterEntry>,
+}
+
+/// `[[region]]` entry
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegionEntry {
+    address: Address,
+    length: u8,
+}
+
+/// `[[register]]` entry
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegisterEntry {
+    address: Address,
+    length: u8,
+    #[serde(rename = "type")]
+    data_type: String,
+    label: String,
+    group: Option<String>,
+    units: Option<String>,
+    family: Option<BatteryFamily>,
+}
+
+/// Address written as a number or a string such as "0x9000"
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Address {
+    Number(u16),
+    Text(String),
+}
+
+impl Address {
+    fn resolve(&self) -> Result<u16> {
+        match self {
+            Address::Number(address) => Ok(*address),
+            Address::Text(text) => {
+                let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+                    Some(hex) => u16::from_str_radix(hex, 16),
+                    None => text.parse(),
+                };
+                parsed.map_err(|_| {
+                    M18Error::InvalidRegisterMap(format!("Invalid address \"{}\"", text))
+                })
+            }
+        }
+    }
+}
+
+impl MapFile {
+    /// Convert the parsed file into a validated map
+    fn into_map(self) -> Result<RegisterMap> {
+        if self.version != REGISTER_MAP_VERSION {
+            return Err(M18Error::InvalidRegisterMap(format!(
+                "Unsupported schema version {} (expected {})",
+                self.version, REGISTER_MAP_VERSION
+            )));
+        }
+
+        let regions = self
+            .regions
+            .iter()
+            .map(|entry| {
+                let [address_high, address_low] = entry.address.resolve()?.to_be_bytes();
+                Ok(MemoryRegion {
+                    address_high,
+                    address_low,
+                    length: entry.length,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let registers = self
+            .registers
+            .into_iter()
+            .enumerate()
+            .map(|(id, entry)| {
+                let data_type: DataType = entry.data_type.parse().map_err(|_| {
+                    M18Error::InvalidRegisterMap(format!(
+                        "Register {} has unknown type \"{}\"",
+                        id, entry.data_type
+                    ))
+                })?;
+                Ok(RegisterDef {
+                    address: entry.address.resolve()?,
+                    length: entry.length,
+                    data_type,
+                    label: entry.label,
+                    group: entry.group,
+                    units: entry.units,
+                    family: entry.family,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        RegisterMap::new(regions, registers)
+    }
 }
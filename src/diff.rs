@@ -0,0 +1,394 @@
+//! Differences between two memory captures.
+//!
+//! Comparing captures taken before and after a charge, a discharge or a
+//! write shows which registers the battery updates. An [`ImageDiff`] lists
+//! every run of changed bytes, the registers the run falls in with their
+//! values before and after, and the changed addresses that no register
+//! covers, which point at fields the register map does not know about yet.
+//!
+//! The diff prints as text and serializes to JSON.
+//!
+//! # Examples
+//! ```
+//! use m18_protocol::diff::{ImageDiff, ValueDelta};
+//! use m18_protocol::{emulator::VirtualBattery, Register, RegisterMap, M18};
+//!
+//! let battery = VirtualBattery::new();
+//! let mut m18 = M18::with_transport(battery.transport());
+//! let before = m18.read_all_raw()?;
+//!
+//! let cells = Register::CellVoltages.address();
+//! battery.set_memory(cells, &[0x0F, 0xA0, 0x0F, 0xA1, 0x0F, 0xA2, 0x0F, 0xA3, 0x0F, 0xA4]);
+//! let after = m18.read_all_raw()?;
+//!
+//! let diff = ImageDiff::new(&before, &after, RegisterMap::embedded());
+//! assert_eq!(diff.ranges.len(), 1);
+//! let change = &diff.ranges[0].registers[0];
+//! assert_eq!(change.address, cells);
+//! assert!(matches!(change.delta, Some(ValueDelta::Cells(_))));
+//! assert_eq!(diff.uncovered().count(), 0);
+//! # Ok::<(), m18_protocol::M18Error>(())
+//! ```
+
+use crate::data::RegisterMap;
+use crate::error::{M18Error, Result};
+use crate::protocol::{format_duration, format_register_value, parse_register_data};
+use crate::types::{OutputFormat, RegisterDef, RegisterValue};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Numeric change of a register value.
+///
+/// Serializes with its kind next to the value, so a change in seconds can be
+/// told apart from a plain integer change.
+///
+/// # Examples
+/// ```
+/// use m18_protocol::diff::ValueDelta;
+///
+/// let json = serde_json::to_string(&ValueDelta::Seconds(60)).unwrap();
+/// assert_eq!(json, r#"{"kind":"seconds","value":60}"#);
+/// let json = serde_json::to_string(&ValueDelta::Int(60)).unwrap();
+/// assert_eq!(json, r#"{"kind":"int","value":60}"#);
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum ValueDelta {
+    /// Change of an integer
+    Int(i64),
+    /// Change of a floating-point value
+    Float(f64),
+    /// Change of a date or duration, in seconds
+    Seconds(i64),
+    /// Change of each cell voltage
+    Cells([i32; 5]),
+}
+
+impl fmt::Display for ValueDelta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueDelta::Int(delta) => write!(f, "{:+}", delta),
+            ValueDelta::Float(delta) => write!(f, "{:+.2}", delta),
+            ValueDelta::Seconds(delta) => {
+                let sign = if *delta < 0 { '-' } else { '+' };
+                let seconds = u32::try_from(delta.unsigned_abs()).unwrap_or(u32::MAX);
+                write!(f, "{}{}", sign, format_duration(seconds))
+            }
+            ValueDelta::Cells(deltas) => {
+                let cells: Vec<String> = deltas.iter().map(|d| format!("{:+}", d)).collect();
+                write!(f, "[{}]", cells.join(", "))
+            }
+        }
+    }
+}
+
+/// Register touched by a changed range.
+#[derive(Debug, Clone, Serialize)]
+pub struct RegisterChange {
+    /// Register ID
+    pub id: usize,
+    /// Register start address
+    pub address: u16,
+    /// Register label
+    pub label: String,
+    /// Unit of the parsed values
+    pub units: Option<String>,
+    /// Value in the old capture, or `None` if it does not parse
+    pub old: Option<RegisterValue>,
+    /// Value in the new capture, or `None` if it does not parse
+    pub new: Option<RegisterValue>,
+    /// Change from the old value to the new one, for numeric values
+    pub delta: Option<ValueDelta>,
+}
+
+impl fmt::Display for RegisterChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:3} 0x{:04X} {}: {} -> {}",
+            self.id,
+            self.address,
+            self.label,
+            value_text(&self.old),
+            value_text(&self.new)
+        )?;
+        match (&self.delta, &self.units) {
+            (Some(delta @ ValueDelta::Seconds(_)), _) | (Some(delta), None) => {
+                write!(f, " ({})", delta)
+            }
+            (Some(delta), Some(units)) => write!(f, " ({} {})", delta, units),
+            (None, _) => Ok(()),
+        }
+    }
+}
+
+/// Run of changed bytes.
+///
+/// Changed bytes inside one register are kept in one range even when bytes
+/// between them did not change, so every register appears in one range only.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangedRange {
+    /// First address of the range
+    pub start: u16,
+    /// Last address of the range (inclusive)
+    pub end: u16,
+    /// Bytes of the range in the old capture
+    #[serde(serialize_with = "crate::memory_image::hex_data::serialize")]
+    pub old: Vec<u8>,
+    /// Bytes of the range in the new capture
+    #[serde(serialize_with = "crate::memory_image::hex_data::serialize")]
+    pub new: Vec<u8>,
+    /// Registers the range overlaps, in address order
+    pub registers: Vec<RegisterChange>,
+    /// Changed addresses in the range that no register covers
+    pub uncovered: Vec<u16>,
+}
+
+impl fmt::Display for ChangedRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "0x{:04X}-0x{:04X}  {} -> {}",
+            self.start,
+            self.end,
+            hex(&self.old),
+            hex(&self.new)
+        )?;
+        for register in &self.registers {
+            writeln!(f, "    {}", register)?;
+        }
+        if !self.uncovered.is_empty() {
+            let addresses: Vec<String> = self
+                .uncovered
+                .iter()
+                .map(|address| format!("0x{:04X}", address))
+                .collect();
+            writeln!(f, "    not in any register: {}", addresses.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Every difference between two captures.
+///
+/// Only addresses read in both captures are compared; regions that failed in
+/// either one are skipped.
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageDiff {
+    /// Changed ranges, in address order
+    pub ranges: Vec<ChangedRange>,
+}
+
+impl ImageDiff {
+    /// Compare two captures.
+    ///
+    /// # Arguments
+    /// * `old` - Earlier capture, as `(address, data)` pairs from
+    ///   [`M18::read_all_raw`](crate::M18::read_all_raw) or [`MemoryImage::raw`](crate::MemoryImage::raw)
+    /// * `new` - Later capture in the same form
+    /// * `register_map` - Map used to label the changes
+    pub fn new(old: &[(u16, Vec<u8>)], new: &[(u16, Vec<u8>)], register_map: &RegisterMap) -> Self {
+        let old_bytes = byte_map(old);
+        let new_bytes = byte_map(new);
+        let changed: Vec<u16> = old_bytes
+            .iter()
+            .filter(|&(address, byte)| new_bytes.get(address).is_some_and(|new| new != byte))
+            .map(|(&address, _)| address)
+            .collect();
+
+        // Group the changed addresses, keeping each register in one group
+        let mut groups: Vec<(u16, u16)> = Vec::new();
+        for &address in &changed {
+            match groups.last_mut() {
+                Some((_, end))
+                    if *end as u32 + 1 == address as u32
+                        || covering(register_map, *end)
+                            .is_some_and(|id| covering(register_map, address) == Some(id)) =>
+                {
+                    *end = address;
+                }
+                _ => groups.push((address, address)),
+            }
+        }
+
+        let ranges = groups
+            .into_iter()
+            .map(|(start, end)| {
+                let bytes = |map: &BTreeMap<u16, u8>| -> Vec<u8> {
+                    (start..=end).filter_map(|a| map.get(&a).copied()).collect()
+                };
+                let mut registers = Vec::new();
+                let mut uncovered = Vec::new();
+                for &address in changed.iter().filter(|&&a| (start..=end).contains(&a)) {
+                    match covering(register_map, address) {
+                        Some(id) => {
+                            if !registers.iter().any(|r: &RegisterChange| r.id == id) {
+                                registers.push(register_change(
+                                    id,
+                                    &register_map.registers()[id],
+                                    &old_bytes,
+                                    &new_bytes,
+                                ));
+                            }
+                        }
+                        None => uncovered.push(address),
+                    }
+                }
+                ChangedRange {
+                    start,
+                    end,
+                    old: bytes(&old_bytes),
+                    new: bytes(&new_bytes),
+                    registers,
+                    uncovered,
+                }
+            })
+            .collect();
+
+        ImageDiff { ranges }
+    }
+
+    /// Whether the captures are identical.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Every register that changed.
+    pub fn registers(&self) -> impl Iterator<Item = &RegisterChange> {
+        self.ranges.iter().flat_map(|range| &range.registers)
+    }
+
+    /// Every changed address that no register covers.
+    pub fn uncovered(&self) -> impl Iterator<Item = u16> + '_ {
+        self.ranges
+            .iter()
+            .flat_map(|range| range.uncovered.iter().copied())
+    }
+
+    /// Serialize the diff as pretty-printed JSON.
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| M18Error::Parse(format!("Failed to serialize diff: {}", e)))
+    }
+}
+
+impl fmt::Display for ImageDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ranges.is_empty() {
+            return writeln!(f, "No changes");
+        }
+        for range in &self.ranges {
+            write!(f, "{}", range)?;
+        }
+        Ok(())
+    }
+}
+
+/// Bytes of a capture by address
+fn byte_map(capture: &[(u16, Vec<u8>)]) -> BTreeMap<u16, u8> {
+    let mut bytes = BTreeMap::new();
+    for (start, data) in capture {
+        for (address, &byte) in (*start..=u16::MAX).zip(data) {
+            bytes.insert(address, byte);
+        }
+    }
+    bytes
+}
+
+/// ID of the register containing `address`
+fn covering(register_map: &RegisterMap, address: u16) -> Option<usize> {
+    register_map.registers().iter().position(|register| {
+        let start = register.address as u32;
+        (start..start + register.length as u32).contains(&(address as u32))
+    })
+}
+
+/// Old and new values of a register whose bytes changed
+fn register_change(
+    id: usize,
+    register: &RegisterDef,
+    old_bytes: &BTreeMap<u16, u8>,
+    new_bytes: &BTreeMap<u16, u8>,
+) -> RegisterChange {
+    let old_data = register_bytes(register, old_bytes);
+    let new_data = register_bytes(register, new_bytes);
+    let parse = |data: &Option<Vec<u8>>| {
+        data.as_ref()
+            .and_then(|data| parse_register_data(register, data).ok())
+    };
+    let (old, new) = (parse(&old_data), parse(&new_data));
+    let delta = match (&old, &new) {
+        (Some(old), Some(new)) => value_delta(old, new),
+        _ => None,
+    };
+    RegisterChange {
+        id,
+        address: register.address,
+        label: register.label.clone(),
+        units: register.units.clone(),
+        old,
+        new,
+        delta,
+    }
+}
+
+/// Bytes of a register, if all of them were captured
+fn register_bytes(register: &RegisterDef, bytes: &BTreeMap<u16, u8>) -> Option<Vec<u8>> {
+    (0..register.length as u32)
+        .map(|offset| {
+            let address = u16::try_from(register.address as u32 + offset).ok()?;
+            bytes.get(&address).copied()
+        })
+        .collect()
+}
+
+/// Change between two parsed values, for numeric registers
+fn value_delta(old: &RegisterValue, new: &RegisterValue) -> Option<ValueDelta> {
+    match (old, new) {
+        (RegisterValue::UInt(old), RegisterValue::UInt(new)) => {
+            i64::try_from(*new as i128 - *old as i128)
+                .ok()
+                .map(ValueDelta::Int)
+        }
+        (RegisterValue::Float(old), RegisterValue::Float(new)) => {
+            Some(ValueDelta::Float(new - old))
+        }
+        (RegisterValue::DateTime(old), RegisterValue::DateTime(new)) => {
+            Some(ValueDelta::Seconds((*new - *old).num_seconds()))
+        }
+        (RegisterValue::Duration(old), RegisterValue::Duration(new)) => Some(ValueDelta::Seconds(
+            duration_seconds(new)? as i64 - duration_seconds(old)? as i64,
+        )),
+        (RegisterValue::CellVoltages(old), RegisterValue::CellVoltages(new)) => {
+            Some(ValueDelta::Cells(std::array::from_fn(|i| {
+                new[i] as i32 - old[i] as i32
+            })))
+        }
+        _ => None,
+    }
+}
+
+/// Seconds in a duration formatted as `HH:MM:SS`
+fn duration_seconds(text: &str) -> Option<u32> {
+    let mut parts = text.split(':').map(|part| part.parse::<u32>().ok());
+    let (hours, minutes, seconds) = (parts.next()??, parts.next()??, parts.next()??);
+    Some(hours * 3600 + minutes * 60 + seconds)
+}
+
+/// Parsed value for display
+fn value_text(value: &Option<RegisterValue>) -> String {
+    match value {
+        Some(value) => format_register_value(value, OutputFormat::Label),
+        None => "?".to_string(),
+    }
+}
+
+/// Format bytes as space-separated hex
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
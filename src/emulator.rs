@@ -0,0 +1,853 @@
+//! Virtual M18 battery for hardware-free testing.
+//!
+//! [`VirtualBattery`] is a software model of a pack that speaks the same wire
+//! protocol as the real thing. It echoes [`SYNC_BYTE`] after a reset, answers
+//! memory reads from a configurable memory image, accepts writes to the note
+//! field and replies to the charger commands (`Configure`, `Snapshot`,
+//! `Keepalive` and `Calibrate`).
+//!
+//! The battery can be driven in-process through [`VirtualBattery::transport`],
+//! or exposed on a pseudo-terminal with [`VirtualBattery::serve_pty`] so the
+//! examples can be pointed at it like a real serial port.
+//!
+//! # Examples
+//! ```
+//! use m18_protocol::{emulator::VirtualBattery, M18};
+//!
+//! let battery = VirtualBattery::new();
+//! let mut m18 = M18::with_transport(battery.transport());
+//!
+//! assert!(m18.reset()?.is_synced());
+//! m18.write_message("hello")?;
+//! assert_eq!(&battery.memory(0x0023, 5).unwrap(), b"hello");
+//! # Ok::<(), m18_protocol::M18Error>(())
+//! ```
+
+use crate::constants::*;
+use crate::data::RegisterMap;
+use crate::error::{M18Error, Result};
+use crate::frame::{self, reverse_bits, RequestFrame, ResponseFrame};
+use crate::line_control::{ControlLines, LineControl};
+use crate::transport::Transport;
+use crate::types::{ChargerProfile, Command, MemoryOperation};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Start address of the writable note field
+const NOTE_ADDRESS: u16 = 0x0023;
+
+/// Length of the writable note field
+const NOTE_LENGTH: u16 = 20;
+
+/// NACK code: address range is not readable or writable
+const NACK_BAD_ADDRESS: u8 = 0x01;
+
+/// NACK code: ACC value out of sequence
+const NACK_BAD_SEQUENCE: u8 = 0x02;
+
+/// NACK code: request checksum or length was invalid
+const NACK_BAD_CHECKSUM: u8 = 0x03;
+
+/// NACK code: unknown command
+const NACK_UNKNOWN_COMMAND: u8 = 0x04;
+
+/// Software model of an M18 battery pack.
+///
+/// Cloning a `VirtualBattery` yields another handle to the same battery, so a
+/// test can hand a transport to [`M18`](crate::M18) and still inspect or
+/// modify the memory image afterwards.
+#[derive(Clone)]
+pub struct VirtualBattery {
+    /// Shared battery state
+    state: Arc<Mutex<BatteryState>>,
+}
+
+/// Control-line levels last driven by the host.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineLevels {
+    /// Whether TX is held in break
+    pub break_enabled: bool,
+    /// Level of the DTR line
+    pub dtr: bool,
+    /// Level of the RTS line
+    pub rts: bool,
+}
+
+impl ControlLines for LineLevels {
+    fn set_break(&mut self, enabled: bool) -> Result<()> {
+        self.break_enabled = enabled;
+        Ok(())
+    }
+
+    fn set_dtr(&mut self, level: bool) -> Result<()> {
+        self.dtr = level;
+        Ok(())
+    }
+
+    fn set_rts(&mut self, level: bool) -> Result<()> {
+        self.rts = level;
+        Ok(())
+    }
+}
+
+/// Internal state of a virtual battery.
+struct BatteryState {
+    /// Memory image, one entry per readable byte
+    memory: BTreeMap<u16, u8>,
+    /// Partially received request (LSB order)
+    request: Vec<u8>,
+    /// Pending reply bytes (LSB order)
+    reply: VecDeque<u8>,
+    /// Whether J2 is currently held low
+    j2_low: bool,
+    /// Control-line levels last driven by the host
+    lines: LineLevels,
+    /// Which control lines pull J2 low
+    wiring: LineControl,
+    /// ACC value expected on the next charger command
+    expected_acc: u8,
+    /// Charger profile from the last `Configure` command
+    charger_profile: Option<ChargerProfile>,
+    /// Charge state from the last `Configure` command
+    charge_state: u8,
+    /// Number of keepalives received since the last reset
+    keepalive_count: u32,
+    /// Pack temperature reported in snapshots (°C)
+    temperature: u8,
+    /// Number of upcoming replies to discard
+    dropped_replies: u32,
+    /// Number of upcoming replies to send with a damaged checksum
+    corrupted_replies: u32,
+    /// Number of upcoming replies to send with an understated length byte
+    shortened_replies: u32,
+    /// Whether transmitted bytes are echoed back, as on a single-wire link
+    echo: bool,
+}
+
+impl VirtualBattery {
+    /// Create a virtual battery with a sample memory image.
+    ///
+    /// Every region of the embedded register map is readable. The image describes a
+    /// 9Ah HD pack with a few hundred hours of use, balanced cells around
+    /// 4.01V and a populated discharge histogram.
+    pub fn new() -> Self {
+        let battery = Self::blank();
+        for &(address, bytes) in SAMPLE_IMAGE {
+            battery.set_memory(address, bytes);
+        }
+        battery
+    }
+
+    /// Create a virtual battery with every register map region zero-filled.
+    pub fn blank() -> Self {
+        let mut memory = BTreeMap::new();
+        for region in RegisterMap::embedded().regions() {
+            let start = region.address();
+            for offset in 0..region.length as u16 {
+                memory.insert(start + offset, 0);
+            }
+        }
+
+        VirtualBattery {
+            state: Arc::new(Mutex::new(BatteryState {
+                memory,
+                request: Vec::new(),
+                reply: VecDeque::new(),
+                j2_low: true,
+                lines: LineLevels::default(),
+                wiring: LineControl::Break,
+                expected_acc: INITIAL_ACC,
+                charger_profile: None,
+                charge_state: 0,
+                keepalive_count: 0,
+                temperature: 25,
+                dropped_replies: 0,
+                corrupted_replies: 0,
+                shortened_replies: 0,
+                echo: false,
+            })),
+        }
+    }
+
+    /// Write bytes into the memory image, making them readable.
+    ///
+    /// # Arguments
+    /// * `address` - Address of the first byte
+    /// * `data` - Bytes to store
+    pub fn set_memory(&self, address: u16, data: &[u8]) {
+        let mut state = self.lock();
+        for (offset, &byte) in data.iter().enumerate() {
+            state
+                .memory
+                .insert(address.wrapping_add(offset as u16), byte);
+        }
+    }
+
+    /// Remove bytes from the memory image, so reads touching them are NACKed.
+    ///
+    /// # Arguments
+    /// * `address` - Address of the first byte
+    /// * `length` - Number of bytes to remove
+    pub fn remove_memory(&self, address: u16, length: u16) {
+        let mut state = self.lock();
+        for offset in 0..length {
+            state.memory.remove(&address.wrapping_add(offset));
+        }
+    }
+
+    /// Read bytes from the memory image.
+    ///
+    /// # Returns
+    /// The bytes at `address..address + length`, or `None` if any of them is
+    /// outside the image.
+    pub fn memory(&self, address: u16, length: u16) -> Option<Vec<u8>> {
+        self.lock().read(address, length)
+    }
+
+    /// Set the pack temperature reported in snapshot replies (°C).
+    ///
+    /// The value is placed at the provisional offset that
+    /// [`SnapshotResponse`](crate::SnapshotResponse) decodes, so it exercises
+    /// the decoder but does not confirm the real layout.
+    pub fn set_temperature(&self, celsius: u8) {
+        self.lock().temperature = celsius;
+    }
+
+    /// Cutoff and maximum current (mA) from the last `Configure` command.
+    pub fn charger_currents(&self) -> (u16, u16) {
+        self.charger_profile()
+            .map(|profile| (profile.cutoff_current, profile.max_current))
+            .unwrap_or_default()
+    }
+
+    /// Charger profile announced by the last `Configure` command, if any.
+    pub fn charger_profile(&self) -> Option<ChargerProfile> {
+        self.lock().charger_profile
+    }
+
+    /// Whether J2 is high, so the battery is awake and listening.
+    pub fn is_awake(&self) -> bool {
+        !self.lock().j2_low
+    }
+
+    /// Control-line levels last driven through [`VirtualBattery::transport`].
+    pub fn lines(&self) -> LineLevels {
+        self.lock().lines
+    }
+
+    /// Choose which control lines pull J2 low, as the adapter would.
+    ///
+    /// The default is [`LineControl::Break`]. J2 follows the lines from the
+    /// next change onwards; a [`LineControl::Custom`] wiring holds J2 low
+    /// while the lines are where its function puts them to pull J2 low.
+    ///
+    /// # Examples
+    /// ```
+    /// use m18_protocol::{emulator::VirtualBattery, LineControl, M18Builder};
+    ///
+    /// let battery = VirtualBattery::new();
+    /// battery.set_wiring(LineControl::Rts { inverted: true });
+    /// let mut m18 = M18Builder::from_transport(battery.transport())
+    ///     .line_control(LineControl::Rts { inverted: true })
+    ///     .build()?;
+    ///
+    /// let session = m18.reset()?;
+    /// assert!(session.is_synced());
+    /// assert!(battery.is_awake());
+    /// assert!(battery.lines().rts);
+    ///
+    /// // Ending the session idles J2 by driving RTS low
+    /// drop(session);
+    /// assert!(!battery.is_awake());
+    /// assert!(!battery.lines().rts);
+    /// # Ok::<(), m18_protocol::M18Error>(())
+    /// ```
+    pub fn set_wiring(&self, wiring: LineControl) {
+        self.lock().wiring = wiring;
+    }
+
+    /// Number of keepalives received since the last reset.
+    pub fn keepalive_count(&self) -> u32 {
+        self.lock().keepalive_count
+    }
+
+    /// Discard the next `count` replies, as if they were lost on the wire.
+    ///
+    /// The requests are still processed, so the host sees a timeout after the
+    /// battery has already acted on them. The sync echo is never dropped.
+    pub fn drop_replies(&self, count: u32) {
+        self.lock().dropped_replies = count;
+    }
+
+    /// Damage the last byte of the next `count` replies, which breaks the
+    /// checksum of a full frame.
+    pub fn corrupt_replies(&self, count: u32) {
+        self.lock().corrupted_replies = count;
+    }
+
+    /// Send the next `count` full replies with a length byte one lower than
+    /// their payload, so the host receives a short frame.
+    ///
+    /// # Examples
+    /// ```
+    /// use m18_protocol::{emulator::VirtualBattery, ReadFailure, Register, RetryPolicy, M18};
+    ///
+    /// let battery = VirtualBattery::new();
+    /// let mut m18 = M18::with_transport(battery.transport());
+    /// m18.set_retry_policy(RetryPolicy::none());
+    ///
+    /// battery.shorten_replies(1);
+    /// let results = m18.read_registers_detailed(&[Register::CellVoltages], false)?;
+    /// assert!(matches!(results[0].1, Err(ReadFailure::ShortFrame(_))));
+    /// # Ok::<(), m18_protocol::M18Error>(())
+    /// ```
+    pub fn shorten_replies(&self, count: u32) {
+        self.lock().shortened_replies = count;
+    }
+
+    /// Echo every byte written through [`VirtualBattery::transport`] back
+    /// ahead of the reply, like an adapter with TX and RX tied together.
+    pub fn set_echo(&self, enabled: bool) {
+        self.lock().echo = enabled;
+    }
+
+    /// Create an in-process transport connected to this battery.
+    pub fn transport(&self) -> VirtualTransport {
+        VirtualTransport {
+            battery: self.clone(),
+        }
+    }
+
+    /// Expose this battery on a new pseudo-terminal.
+    ///
+    /// The returned server answers on a background thread until it is stopped
+    /// or dropped. Open [`PtyServer::path`] like any other serial port.
+    ///
+    /// Break conditions are not visible through a pseudo-terminal, so in this
+    /// mode the battery treats every sync byte as the start of a new session.
+    ///
+    /// # Errors
+    /// Returns error if the pseudo-terminal cannot be created.
+    #[cfg(unix)]
+    pub fn serve_pty(&self) -> Result<PtyServer> {
+        PtyServer::start(self.clone())
+    }
+
+    /// Lock the shared state
+    fn lock(&self) -> MutexGuard<'_, BatteryState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for VirtualBattery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BatteryState {
+    /// Read a byte range from memory, if every byte is present
+    fn read(&self, address: u16, length: u16) -> Option<Vec<u8>> {
+        (0..length)
+            .map(|offset| self.memory.get(&address.wrapping_add(offset)).copied())
+            .collect()
+    }
+
+    /// Drive J2 low (break asserted) or high
+    fn set_j2_low(&mut self, low: bool) {
+        if low && !self.j2_low {
+            self.request.clear();
+            self.reply.clear();
+        }
+        self.j2_low = low;
+    }
+
+    /// Record new control-line levels and update J2 from the wiring
+    fn set_lines(&mut self, lines: LineLevels) {
+        self.lines = lines;
+        let j2_low = match &self.wiring {
+            LineControl::BreakAndDtr { inverted } => lines.break_enabled && lines.dtr != *inverted,
+            LineControl::Break => lines.break_enabled,
+            LineControl::Dtr { inverted } => lines.dtr != *inverted,
+            LineControl::Rts { inverted } => lines.rts != *inverted,
+            LineControl::Custom(drive) => {
+                let mut pulled_low = lines;
+                drive(&mut pulled_low, true).is_ok() && pulled_low == lines
+            }
+        };
+        self.set_j2_low(j2_low);
+    }
+
+    /// Start a new session after a sync byte
+    fn sync(&mut self) {
+        self.request.clear();
+        self.expected_acc = INITIAL_ACC;
+        self.keepalive_count = 0;
+        self.reply.push_back(SYNC_BYTE);
+    }
+
+    /// Feed received bytes (LSB order) into the request parser
+    fn receive(&mut self, bytes: &[u8]) {
+        if self.j2_low {
+            return;
+        }
+
+        for &byte in bytes {
+            if self.request.is_empty() && byte == SYNC_BYTE {
+                self.sync();
+                continue;
+            }
+
+            self.request.push(byte);
+            if self.request.len() >= 3
+                && self.request.len() == self.request[2] as usize + frame::FRAME_OVERHEAD
+            {
+                let request = std::mem::take(&mut self.request);
+                self.handle(&request);
+            }
+        }
+    }
+
+    /// Process one complete request frame
+    fn handle(&mut self, request: &[u8]) {
+        let RequestFrame {
+            header,
+            acc,
+            payload,
+        } = match RequestFrame::decode(request) {
+            Ok(request) => request,
+            Err(_) => {
+                self.nack(NACK_BAD_CHECKSUM);
+                return;
+            }
+        };
+        let payload = payload.as_slice();
+
+        match header {
+            h if h == u8::from(MemoryOperation::Read) => self.handle_memory(acc, payload),
+            h if h == u8::from(Command::Configure) => {
+                if !self.check_acc(acc) || payload.len() != 8 {
+                    return;
+                }
+                self.charger_profile = Some(ChargerProfile {
+                    cutoff_current: u16::from_be_bytes([payload[0], payload[1]]),
+                    max_current: u16::from_be_bytes([payload[2], payload[3]]),
+                    max_current_secondary: u16::from_be_bytes([payload[4], payload[5]]),
+                    trailer: payload[7],
+                });
+                self.charge_state = payload[6];
+                self.respond(header, acc, &[]);
+            }
+            h if h == u8::from(Command::Snapshot) => {
+                if !self.check_acc(acc) {
+                    return;
+                }
+                let flags = self.charge_state;
+                self.respond(header, acc, &[flags, self.temperature, 0]);
+            }
+            h if h == u8::from(Command::Keepalive) => {
+                // Keepalives repeat the current ACC rather than advancing it
+                if acc != self.expected_acc {
+                    self.nack(NACK_BAD_SEQUENCE);
+                    return;
+                }
+                self.keepalive_count += 1;
+                let current = match self.charger_profile {
+                    Some(profile) if self.charge_state == 1 => profile.max_current,
+                    _ => 0,
+                };
+                let voltage = self.pack_voltage();
+                let mut payload = current.to_be_bytes().to_vec();
+                payload.extend_from_slice(&voltage.to_be_bytes());
+                self.respond(header, acc, &payload);
+            }
+            h if h == u8::from(Command::Calibrate) => {
+                if !self.check_acc(acc) {
+                    return;
+                }
+                self.respond(header, acc, &[0, 0, 0]);
+            }
+            _ => self.nack(NACK_UNKNOWN_COMMAND),
+        }
+    }
+
+    /// Handle a memory read (ACC byte 0x04) or write (0x05)
+    fn handle_memory(&mut self, kind: u8, payload: &[u8]) {
+        if payload.len() != 3 {
+            self.nack(NACK_BAD_ADDRESS);
+            return;
+        }
+        let address = u16::from_be_bytes([payload[0], payload[1]]);
+
+        match kind {
+            0x04 => match self.read(address, payload[2] as u16) {
+                Some(data) => self.respond(MemoryOperation::Read as u8, kind, &data),
+                None => self.nack(NACK_BAD_ADDRESS),
+            },
+            0x05 if (NOTE_ADDRESS..NOTE_ADDRESS + NOTE_LENGTH).contains(&address) => {
+                self.memory.insert(address, payload[2]);
+                let ack = ResponseFrame::Short {
+                    header: MemoryOperation::Read as u8 | 0x80,
+                    code: kind,
+                };
+                self.queue_reply(ack.encode());
+            }
+            _ => self.nack(NACK_BAD_ADDRESS),
+        }
+    }
+
+    /// Check and advance the ACC sequence, sending a NACK on mismatch
+    fn check_acc(&mut self, acc: u8) -> bool {
+        if acc != self.expected_acc {
+            self.nack(NACK_BAD_SEQUENCE);
+            return false;
+        }
+        let index = ACC_VALUES.iter().position(|&x| x == acc).unwrap_or(0);
+        self.expected_acc = ACC_VALUES[(index + 1) % ACC_VALUES.len()];
+        true
+    }
+
+    /// Sum of the five cell voltages in the memory image (mV)
+    fn pack_voltage(&self) -> u16 {
+        self.read(0x400A, 10)
+            .map(|cells| {
+                cells
+                    .chunks(2)
+                    .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                    .sum()
+            })
+            .unwrap_or(0)
+    }
+
+    /// Queue a full reply frame with the request header's bit 7 set
+    fn respond(&mut self, header: u8, acc: u8, payload: &[u8]) {
+        let response = ResponseFrame::Data {
+            header: header | 0x80,
+            acc,
+            payload: payload.to_vec(),
+        };
+        self.queue_reply(response.encode());
+    }
+
+    /// Queue a two-byte NACK reply
+    fn nack(&mut self, code: u8) {
+        let response = ResponseFrame::Short {
+            header: frame::NACK_HEADER,
+            code,
+        };
+        self.queue_reply(response.encode());
+    }
+
+    /// Queue reply bytes, applying any pending dropped, shortened or
+    /// corrupted replies
+    fn queue_reply(&mut self, mut reply: Vec<u8>) {
+        if self.dropped_replies > 0 {
+            self.dropped_replies -= 1;
+            return;
+        }
+        if self.shortened_replies > 0 && reply.len() >= frame::FRAME_OVERHEAD {
+            self.shortened_replies -= 1;
+            reply[2] = reply[2].wrapping_sub(1);
+            let body = reply.len() - 2;
+            let checksum = frame::checksum(&reply[..body]);
+            reply[body..].copy_from_slice(&checksum.to_be_bytes());
+        }
+        if self.corrupted_replies > 0 {
+            self.corrupted_replies -= 1;
+            if let Some(last) = reply.last_mut() {
+                *last ^= 0xFF;
+            }
+        }
+        self.reply.extend(reply);
+    }
+}
+
+/// In-process [`Transport`] connected to a [`VirtualBattery`].
+pub struct VirtualTransport {
+    /// Battery on the other end of the link
+    battery: VirtualBattery,
+}
+
+impl Transport for VirtualTransport {
+    fn write_all(&mut self, data: &[u8]) -> Result<()> {
+        let lsb: Vec<u8> = data.iter().map(|&b| reverse_bits(b)).collect();
+        let mut state = self.battery.lock();
+        if state.echo {
+            state.reply.extend(&lsb);
+        }
+        state.receive(&lsb);
+        Ok(())
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        let mut state = self.battery.lock();
+        if state.reply.len() < buf.len() {
+            // A real port would consume whatever arrived before timing out
+            state.reply.clear();
+            return Err(M18Error::Timeout);
+        }
+        for byte in buf.iter_mut() {
+            *byte = reverse_bits(state.reply.pop_front().unwrap_or(0));
+        }
+        Ok(())
+    }
+
+    fn clear_input(&mut self) -> Result<()> {
+        self.battery.lock().reply.clear();
+        Ok(())
+    }
+
+    fn set_break(&mut self, enabled: bool) -> Result<()> {
+        let mut state = self.battery.lock();
+        let lines = LineLevels {
+            break_enabled: enabled,
+            ..state.lines
+        };
+        state.set_lines(lines);
+        Ok(())
+    }
+
+    fn set_dtr(&mut self, level: bool) -> Result<()> {
+        let mut state = self.battery.lock();
+        let lines = LineLevels {
+            dtr: level,
+            ..state.lines
+        };
+        state.set_lines(lines);
+        Ok(())
+    }
+
+    fn set_rts(&mut self, level: bool) -> Result<()> {
+        let mut state = self.battery.lock();
+        let lines = LineLevels {
+            rts: level,
+            ..state.lines
+        };
+        state.set_lines(lines);
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+pub use pty::PtyServer;
+
+#[cfg(unix)]
+mod pty {
+    use super::{reverse_bits, VirtualBattery};
+    use crate::error::{M18Error, Result};
+    use serialport::{SerialPort, TTYPort};
+    use std::io::{ErrorKind, Read, Write};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
+
+    /// A [`VirtualBattery`] served on a pseudo-terminal.
+    ///
+    /// The battery keeps answering until [`PtyServer::stop`] is called or the
+    /// server is dropped.
+    pub struct PtyServer {
+        /// Path of the slave side of the pseudo-terminal
+        path: String,
+        /// Signals the worker thread to exit
+        stop: Arc<AtomicBool>,
+        /// Worker thread answering requests
+        handle: Option<JoinHandle<()>>,
+    }
+
+    impl PtyServer {
+        /// Create the pseudo-terminal and start answering on it
+        pub(super) fn start(battery: VirtualBattery) -> Result<Self> {
+            let (mut master, slave) = TTYPort::pair()?;
+            let path = slave.name().ok_or_else(|| {
+                M18Error::Io(std::io::Error::other("pseudo-terminal has no name"))
+            })?;
+            master.set_timeout(Duration::from_millis(20))?;
+
+            let stop = Arc::new(AtomicBool::new(false));
+            let stop_flag = stop.clone();
+            let handle = thread::spawn(move || {
+                // Keep the slave open so reads on the master don't fail
+                // while no client is attached
+                let _slave = slave;
+                let mut buf = [0u8; 256];
+                while !stop_flag.load(Ordering::Relaxed) {
+                    let n = match master.read(&mut buf) {
+                        Ok(n) => n,
+                        Err(e) if e.kind() == ErrorKind::TimedOut => continue,
+                        Err(_) => break,
+                    };
+
+                    let lsb: Vec<u8> = buf[..n].iter().map(|&b| reverse_bits(b)).collect();
+                    let reply: Vec<u8> = {
+                        let mut state = battery.lock();
+                        state.set_j2_low(false);
+                        state.receive(&lsb);
+                        state.reply.drain(..).map(reverse_bits).collect()
+                    };
+                    if !reply.is_empty() && master.write_all(&reply).is_err() {
+                        break;
+                    }
+                }
+            });
+
+            Ok(PtyServer {
+                path,
+                stop,
+                handle: Some(handle),
+            })
+        }
+
+        /// Path of the pseudo-terminal to open (e.g. "/dev/pts/3").
+        pub fn path(&self) -> &str {
+            &self.path
+        }
+
+        /// Stop answering and close the pseudo-terminal.
+        pub fn stop(mut self) {
+            self.shutdown();
+        }
+
+        /// Signal the worker thread and wait for it to exit
+        fn shutdown(&mut self) {
+            self.stop.store(true, Ordering::Relaxed);
+            if let Some(handle) = self.handle.take() {
+                let _ = handle.join();
+            }
+        }
+    }
+
+    impl Drop for PtyServer {
+        fn drop(&mut self) {
+            self.shutdown();
+        }
+    }
+}
+
+/// Sample memory image loaded by [`VirtualBattery::new`].
+const SAMPLE_IMAGE: &[(u16, &[u8])] = &[
+    // Cell type
+    (0x0000, &[0x00, 0x03]),
+    // Battery type 47 (9Ah HD), electronic serial 1234567
+    (0x0004, &[0x00, 0x2F, 0x12, 0xD6, 0x87]),
+    // Manufacture date 2021-03-15
+    (0x0011, &[0x60, 0x4E, 0xA3, 0x80]),
+    // Note
+    (0x0023, b"--------------------"),
+    // Current date 2023-11-14
+    (0x0037, &[0x65, 0x52, 0xB8, 0x80]),
+    (0x0069, &[0x00, 0x02]),
+    // Cell voltages (mV)
+    (
+        0x400A,
+        &[0x0F, 0xAC, 0x0F, 0xAA, 0x0F, 0xAF, 0x0F, 0xA8, 0x0F, 0xAB],
+    ),
+    // Thermistor ADC reading
+    (0x4014, &[0x02, 0x00]),
+    // Dates of first charge, last tool use and last charge
+    (0x9000, &[0x60, 0x52, 0x98, 0x00]),
+    (0x9004, &[0x65, 0x4C, 0x21, 0x00]),
+    (0x9008, &[0x65, 0x50, 0x15, 0x80]),
+    // Days since first charge
+    (0x9010, &[0x03, 0xCA]),
+    // Total discharge (amp-sec)
+    (0x9012, &[0x00, 0x4A, 0x28, 0x60]),
+    // Total discharge (joules)
+    (0x9016, &[0x05, 0x6C, 0x8C, 0xC0]),
+    // Total, dumb, Redlink and completed charge counts
+    (0x901A, &[0x00, 0x00, 0x00, 0xA0]),
+    (0x901E, &[0x00, 0x0A]),
+    (0x9020, &[0x00, 0x96]),
+    (0x9022, &[0x00, 0x8C]),
+    // Total charging time and time on charger whilst full (seconds)
+    (0x9024, &[0x00, 0x08, 0x3D, 0x60]),
+    (0x9028, &[0x00, 0x0F, 0x42, 0x40]),
+    // Low-voltage charges, discharges to empty, overheat, overcurrent,
+    // low-voltage events and low-voltage bounce
+    (0x902E, &[0x00, 0x02]),
+    (0x9030, &[0x00, 0x0C]),
+    (0x9032, &[0x00, 0x01]),
+    (0x9034, &[0x00, 0x03]),
+    (0x9036, &[0x00, 0x05]),
+    (0x9038, &[0x00, 0x04]),
+    // Discharge histogram, 10-20A through 200A+ (seconds)
+    (
+        0x903A,
+        &[
+            0x4E, 0x20, 0x3A, 0x98, 0x23, 0x28, 0x17, 0x70, 0x0B, 0xB8, 0x05, 0xDC, 0x03, 0x20,
+            0x01, 0x90, 0x00, 0xC8, 0x00, 0x64, 0x00, 0x32, 0x00, 0x14, 0x00, 0x0A, 0x00, 0x05,
+            0x00, 0x02, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ],
+    ),
+];
+
+#[cfg(feature = "async")]
+mod async_io {
+    use super::{reverse_bits, VirtualTransport};
+    use crate::async_protocol::AsyncTransport;
+    use crate::error::Result;
+    use crate::transport::Transport;
+    use std::io;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+    impl AsyncRead for VirtualTransport {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let mut state = self.battery.lock();
+            if state.reply.is_empty() {
+                // Replies are queued synchronously by writes, so nothing more
+                // will arrive; time out at once instead of waiting forever
+                return Poll::Ready(Err(io::ErrorKind::TimedOut.into()));
+            }
+            while buf.remaining() > 0 {
+                match state.reply.pop_front() {
+                    Some(byte) => buf.put_slice(&[reverse_bits(byte)]),
+                    None => break,
+                }
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl AsyncWrite for VirtualTransport {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Transport::write_all(&mut *self, buf).map_err(io::Error::other)?;
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl AsyncTransport for VirtualTransport {
+        fn clear_input(&mut self) -> Result<()> {
+            Transport::clear_input(self)
+        }
+
+        fn set_break(&mut self, enabled: bool) -> Result<()> {
+            Transport::set_break(self, enabled)
+        }
+
+        fn set_dtr(&mut self, level: bool) -> Result<()> {
+            Transport::set_dtr(self, level)
+        }
+
+        fn set_rts(&mut self, level: bool) -> Result<()> {
+            Transport::set_rts(self, level)
+        }
+    }
+}
@@ -34,13 +34,55 @@ pub enum M18Error {
     EmptyResponse,
 
     /// Response checksum validation failed
-    #[error("Checksum mismatch")]
-    ChecksumMismatch,
+    #[error("Checksum mismatch: expected {expected:#06x}, got {actual:#06x}")]
+    ChecksumMismatch {
+        /// Checksum calculated over the received bytes
+        expected: u16,
+        /// Checksum carried in the frame
+        actual: u16,
+    },
+
+    /// Reply frame was cut off, or is not as long as its length byte says
+    #[error("Short frame: expected {expected} bytes, got {actual}")]
+    ShortFrame {
+        /// Frame length given by the length byte
+        expected: usize,
+        /// Bytes received
+        actual: usize,
+    },
+
+    /// Charger profile value outside the safe range
+    #[error("Charger profile {field} out of range: {value} mA (allowed {min}-{max} mA)")]
+    ChargerProfileOutOfRange {
+        /// Name of the offending field
+        field: &'static str,
+        /// Value that was rejected
+        value: u16,
+        /// Lowest allowed value
+        min: u16,
+        /// Highest allowed value
+        max: u16,
+    },
+
+    /// Replayed session diverged from the recording
+    #[error("Replay mismatch at write {position}: expected {expected}, got {actual}")]
+    ReplayMismatch {
+        /// Number of writes replayed before the mismatch
+        position: usize,
+        /// Bytes the recording expected next
+        expected: String,
+        /// Bytes actually written
+        actual: String,
+    },
 
     /// Unknown data type string
     #[error("Invalid data type: {0}")]
     InvalidDataType(String),
 
+    /// Register map is malformed or fails validation
+    #[error("Invalid register map: {0}")]
+    InvalidRegisterMap(String),
+
     /// Requested register address not found
     #[error("Register not found: {address:#06x}")]
     RegisterNotFound {
@@ -58,4 +100,116 @@ pub enum M18Error {
     /// Data parsing error
     #[error("Parse error: {0}")]
     Parse(String),
+
+    /// Transmitted bytes did not come back on a single-wire link
+    #[error("Wiring fault: sent {sent:02X?}, echoed {echoed:02X?}")]
+    WiringFault {
+        /// Bytes written to the link (wire order)
+        sent: Vec<u8>,
+        /// Bytes read back in their place; shorter if the echo timed out
+        echoed: Vec<u8>,
+    },
+
+    /// Battery rejected a command with a NACK (0x82)
+    #[error("NACK (code {code:#04x}){}", at_address(address))]
+    Nack {
+        /// Error code from the NACK reply
+        code: u8,
+        /// Memory address of a rejected read or write
+        address: Option<u16>,
+    },
+
+    /// Address-space scan settings are unusable
+    #[error("Invalid scan: {0}")]
+    InvalidScan(String),
+
+    /// The worker thread behind a shared connection has stopped
+    #[error("Shared connection stopped")]
+    WorkerStopped,
+
+    /// Request cannot be sent as given
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+}
+
+/// Reason a single register could not be read.
+///
+/// Reported per register by
+/// [`M18::read_registers_detailed`](crate::M18::read_registers_detailed), so
+/// one bad read does not hide the registers that were read successfully.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ReadFailure {
+    /// No reply arrived in time
+    #[error("timeout")]
+    Timeout,
+
+    /// Battery rejected the read with a NACK (0x82)
+    #[error("NACK (code {code:#04x})")]
+    Nack {
+        /// Error code from the NACK reply
+        code: u8,
+    },
+
+    /// Reply arrived with a bad checksum
+    #[error("bad checksum: expected {expected:#06x}, got {actual:#06x}")]
+    ChecksumMismatch {
+        /// Checksum computed over the reply
+        expected: u16,
+        /// Checksum carried by the reply
+        actual: u16,
+    },
+
+    /// Reply was cut off, or is not as long as its length byte says
+    #[error("short frame: {0}")]
+    ShortFrame(String),
+
+    /// Reply could not be decoded
+    #[error("malformed reply: {0}")]
+    Malformed(String),
+
+    /// Reply had an unexpected header
+    #[error("unexpected reply header {header:#04x}")]
+    UnexpectedResponse {
+        /// Header byte of the reply
+        header: u8,
+    },
+
+    /// Register bytes could not be parsed
+    #[error("parse error: {0}")]
+    Parse(String),
+
+    /// Register bytes are not available, e.g. its region was not captured
+    #[error("not captured")]
+    Unavailable,
+
+    /// Any other communication failure
+    #[error("{0}")]
+    Other(String),
+}
+
+impl From<M18Error> for ReadFailure {
+    fn from(error: M18Error) -> Self {
+        match error {
+            M18Error::Timeout | M18Error::EmptyResponse => ReadFailure::Timeout,
+            M18Error::ChecksumMismatch { expected, actual } => {
+                ReadFailure::ChecksumMismatch { expected, actual }
+            }
+            M18Error::ShortFrame { expected, actual } => {
+                ReadFailure::ShortFrame(format!("expected {} bytes, got {}", expected, actual))
+            }
+            M18Error::InvalidResponse { expected, actual } => {
+                ReadFailure::Malformed(format!("expected {}, got {}", expected, actual))
+            }
+            M18Error::Nack { code, .. } => ReadFailure::Nack { code },
+            M18Error::Parse(message) => ReadFailure::Parse(message),
+            other => ReadFailure::Other(other.to_string()),
+        }
+    }
+}
+
+/// Suffix naming the address of a NACK, if known
+fn at_address(address: &Option<u16>) -> String {
+    address
+        .map(|address| format!(" at {:#06x}", address))
+        .unwrap_or_default()
 }
@@ -0,0 +1,173 @@
+//! Protocol rules shared by the blocking and async interfaces.
+//!
+//! [`M18`](crate::M18) and `AsyncM18` each keep their own reset sequence,
+//! reply reading and operation loops, because they wait and move bytes
+//! differently. The rules those loops follow live here, so both apply the same
+//! ACC sequence, reply lengths, echo checks on single-wire links, retry
+//! bookkeeping behind [`RetryPolicy`] and charger handshake steps.
+//!
+//! The loops themselves are written twice and are not identical: `AsyncM18`
+//! has no equivalent of [`M18::capture_image`](crate::M18::capture_image) and
+//! its charger simulation reports no
+//! [`ChargeEvent`](crate::charge_session::ChargeEvent)s.
+
+use crate::constants::*;
+use crate::error::{M18Error, Result};
+use crate::frame;
+use crate::retry::{RetryAction, RetryPolicy, RetryStats};
+use crate::types::ChargeState;
+use log::{info, warn};
+use std::time::Duration;
+
+/// ACC value that follows `acc` in the command sequence
+pub(crate) fn next_acc(acc: u8) -> u8 {
+    let current_index = ACC_VALUES.iter().position(|&x| x == acc).unwrap_or(0);
+    ACC_VALUES[(current_index + 1) % ACC_VALUES.len()]
+}
+
+/// Bytes still to read after the first byte of a reply: one for a NACK,
+/// otherwise the rest of a reply of `expected_size` bytes
+pub(crate) fn remaining_reply_length(first_byte: u8, expected_size: usize) -> usize {
+    if first_byte == frame::NACK_HEADER {
+        1
+    } else {
+        expected_size.saturating_sub(1)
+    }
+}
+
+/// Bytes as space-separated hex, for debug output
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Check the echo read back after sending `sent` on a single-wire link.
+///
+/// # Errors
+/// Returns `M18Error::WiringFault` if the echo differs from the bytes sent or
+/// timed out, or the error that stopped the read.
+pub(crate) fn check_echo(sent: &[u8], echoed: Result<Vec<u8>>) -> Result<()> {
+    match echoed {
+        Ok(echoed) if echoed == sent => Ok(()),
+        Ok(echoed) => Err(M18Error::WiringFault {
+            sent: sent.to_vec(),
+            echoed,
+        }),
+        Err(M18Error::Timeout) => Err(M18Error::WiringFault {
+            sent: sent.to_vec(),
+            echoed: Vec::new(),
+        }),
+        Err(e) => Err(e),
+    }
+}
+
+/// Retry policy and the counters it is applied with.
+#[derive(Debug, Default)]
+pub(crate) struct Retries {
+    /// How failed commands are retried
+    pub(crate) policy: RetryPolicy,
+    /// Retries made by the current or last operation
+    pub(crate) stats: RetryStats,
+    /// Commands failed in a row, across operations
+    consecutive_failures: u32,
+}
+
+/// What to do before sending a failed command again
+pub(crate) struct RetryStep {
+    /// Error the command failed with, returned if the resync fails
+    pub(crate) error: M18Error,
+    /// Wait before retrying
+    pub(crate) backoff: Duration,
+    /// Whether to re-run the reset sequence first
+    pub(crate) resync: bool,
+}
+
+impl Retries {
+    /// Start counting retries for a new operation
+    pub(crate) fn start(&mut self) {
+        self.stats = RetryStats::default();
+    }
+
+    /// Log the retries made by an operation, if there were any
+    pub(crate) fn log(&self, operation: &str) {
+        if self.stats.retries > 0 {
+            info!("{}: {}", operation, self.stats);
+        }
+    }
+
+    /// Record a command that succeeded
+    pub(crate) fn succeeded(&mut self) {
+        self.consecutive_failures = 0;
+    }
+
+    /// Record a failed command and decide whether to send it again.
+    ///
+    /// `retry` counts the retries of this command so far and is advanced if
+    /// the command is to be retried.
+    ///
+    /// # Errors
+    /// Returns `error` if the policy gives up.
+    pub(crate) fn failed(&mut self, error: M18Error, retry: &mut u32) -> Result<RetryStep> {
+        self.consecutive_failures += 1;
+
+        let action = self.policy.action_for(&error);
+        if action == RetryAction::Fail || *retry >= self.policy.max_retries {
+            return Err(error);
+        }
+        let forced = self
+            .policy
+            .resync_after
+            .is_some_and(|count| self.consecutive_failures >= count);
+
+        *retry += 1;
+        self.stats.retries += 1;
+        warn!(
+            "Command failed ({}), retry {} of {}",
+            error, retry, self.policy.max_retries
+        );
+        Ok(RetryStep {
+            error,
+            backoff: self.policy.backoff_for(*retry),
+            resync: forced || action == RetryAction::Resync,
+        })
+    }
+
+    /// Record the outcome of a resync, returning whether it succeeded
+    pub(crate) fn resynced(&mut self, synced: bool) -> bool {
+        self.stats.resyncs += 1;
+        if synced {
+            self.consecutive_failures = 0;
+        } else {
+            warn!("Battery did not respond to reset");
+        }
+        synced
+    }
+}
+
+/// One step of the charger handshake.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum HandshakeStep {
+    /// Send `Configure` with the given charge state
+    Configure(ChargeState),
+    /// Request a snapshot after configuring the given charge state
+    Snapshot(ChargeState),
+    /// Send a keepalive
+    Keepalive,
+    /// Wait before the next step
+    Wait(Duration),
+}
+
+/// Steps that announce a charger: configure, snapshot and keepalive, then
+/// configure again as active
+pub(crate) const CHARGER_HANDSHAKE: [HandshakeStep; 7] = [
+    HandshakeStep::Configure(ChargeState::Initialization),
+    HandshakeStep::Snapshot(ChargeState::Initialization),
+    HandshakeStep::Wait(Duration::from_millis(CONFIGURE_DELAY_MS)),
+    HandshakeStep::Keepalive,
+    HandshakeStep::Wait(Duration::from_millis(CONFIGURE_DELAY_MS)),
+    HandshakeStep::Configure(ChargeState::Active),
+    HandshakeStep::Snapshot(ChargeState::Active),
+];
@@ -0,0 +1,275 @@
+//! Frame codec for the M18 wire protocol.
+//!
+//! Requests and most responses share one layout:
+//!
+//! | Byte(s)      | Meaning                                      |
+//! |--------------|----------------------------------------------|
+//! | 0            | Header (command, or response type)           |
+//! | 1            | ACC sequence value (or read/write selector)  |
+//! | 2            | Payload length `n`                           |
+//! | 3 .. 3+n     | Payload                                      |
+//! | 3+n .. 5+n   | 16-bit big-endian sum of all preceding bytes |
+//!
+//! Some replies (write acknowledgements and NACKs) are only two bytes long and
+//! carry no length or checksum. Every byte is sent MSB first, so frames are
+//! bit-reversed on their way to and from the wire.
+//!
+//! # Examples
+//! ```
+//! use m18_protocol::frame::{RequestFrame, ResponseFrame};
+//!
+//! let request = RequestFrame::new(0x01, 0x04, vec![0x40, 0x0A, 0x02]);
+//! assert_eq!(request.encode(), vec![0x01, 0x04, 0x03, 0x40, 0x0A, 0x02, 0x00, 0x54]);
+//!
+//! let response = ResponseFrame::decode(&[0x81, 0x04, 0x02, 0x0F, 0xAC, 0x01, 0x42])?;
+//! assert_eq!(response.payload(), &[0x0F, 0xAC]);
+//! # Ok::<(), m18_protocol::M18Error>(())
+//! ```
+
+use crate::error::{M18Error, Result};
+use crate::types::{Command, MemoryOperation};
+
+/// Header byte of a NACK reply
+pub const NACK_HEADER: u8 = 0x82;
+
+/// Header byte of a successful memory read reply
+pub const READ_RESPONSE_HEADER: u8 = 0x81;
+
+/// Number of bytes around the payload: header, ACC, length and checksum
+pub const FRAME_OVERHEAD: usize = 5;
+
+/// Whether `header` is one the battery is known to reply with.
+///
+/// Replies carry the request header with bit 7 set, or [`NACK_HEADER`].
+pub fn is_known_reply_header(header: u8) -> bool {
+    header == NACK_HEADER
+        || [
+            u8::from(MemoryOperation::Read),
+            u8::from(Command::Calibrate),
+            u8::from(Command::Configure),
+            u8::from(Command::Snapshot),
+            u8::from(Command::Keepalive),
+        ]
+        .iter()
+        .any(|&request| header == request | 0x80)
+}
+
+/// Reverse bits in a byte (for protocol bit ordering).
+pub fn reverse_bits(byte: u8) -> u8 {
+    let mut result = 0u8;
+    for i in 0..8 {
+        if byte & (1 << i) != 0 {
+            result |= 1 << (7 - i);
+        }
+    }
+    result
+}
+
+/// Convert bytes between LSB-first (logical) and MSB-first (wire) order.
+///
+/// The conversion is its own inverse.
+pub fn to_wire(bytes: &[u8]) -> Vec<u8> {
+    bytes.iter().map(|&b| reverse_bits(b)).collect()
+}
+
+/// Calculate the 16-bit sum checksum of a byte sequence.
+pub fn checksum(bytes: &[u8]) -> u16 {
+    bytes.iter().map(|&b| b as u16).sum()
+}
+
+/// Append the checksum of `bytes` in big-endian order.
+fn with_checksum(mut bytes: Vec<u8>) -> Vec<u8> {
+    let checksum = checksum(&bytes);
+    bytes.extend_from_slice(&checksum.to_be_bytes());
+    bytes
+}
+
+/// Split a full frame into header, ACC and payload after validating it
+fn decode_full(bytes: &[u8]) -> Result<(u8, u8, &[u8])> {
+    if bytes.len() < FRAME_OVERHEAD {
+        return Err(M18Error::ShortFrame {
+            expected: FRAME_OVERHEAD,
+            actual: bytes.len(),
+        });
+    }
+
+    let declared = bytes[2] as usize;
+    if bytes.len() != declared + FRAME_OVERHEAD {
+        return Err(M18Error::ShortFrame {
+            expected: declared + FRAME_OVERHEAD,
+            actual: bytes.len(),
+        });
+    }
+
+    let (body, trailer) = bytes.split_at(bytes.len() - 2);
+    let expected = checksum(body);
+    let actual = u16::from_be_bytes([trailer[0], trailer[1]]);
+    if expected != actual {
+        return Err(M18Error::ChecksumMismatch { expected, actual });
+    }
+
+    Ok((body[0], body[1], &body[3..]))
+}
+
+/// Request frame sent to the battery.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestFrame {
+    /// Command byte
+    pub header: u8,
+    /// ACC sequence value, or 0x04/0x05 to select a memory read/write
+    pub acc: u8,
+    /// Command payload
+    pub payload: Vec<u8>,
+}
+
+impl RequestFrame {
+    /// Create a request frame.
+    pub fn new(header: u8, acc: u8, payload: Vec<u8>) -> Self {
+        RequestFrame {
+            header,
+            acc,
+            payload,
+        }
+    }
+
+    /// Encode the frame in logical (LSB-first) byte order, including checksum.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![self.header, self.acc, self.payload.len() as u8];
+        bytes.extend_from_slice(&self.payload);
+        with_checksum(bytes)
+    }
+
+    /// Decode a complete request frame in logical byte order.
+    ///
+    /// # Errors
+    /// Returns `M18Error::ShortFrame` if the length byte does not match the
+    /// frame size, or `M18Error::ChecksumMismatch` if the checksum is wrong.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let (header, acc, payload) = decode_full(bytes)?;
+        Ok(RequestFrame::new(header, acc, payload.to_vec()))
+    }
+
+    /// Memory address of a read or write request, if this is one.
+    pub fn address(&self) -> Option<u16> {
+        match self.payload[..] {
+            [high, low, _] if self.header == u8::from(MemoryOperation::Read) => {
+                Some(u16::from_be_bytes([high, low]))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Response frame received from the battery.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResponseFrame {
+    /// Full reply with length byte and checksum
+    Data {
+        /// Response header (command with bit 7 set)
+        header: u8,
+        /// ACC value echoed by the battery
+        acc: u8,
+        /// Response payload
+        payload: Vec<u8>,
+    },
+    /// Two-byte reply without length or checksum (write acknowledgements and NACKs)
+    Short {
+        /// Response header
+        header: u8,
+        /// Status or error code
+        code: u8,
+    },
+}
+
+impl ResponseFrame {
+    /// Decode a response in logical (LSB-first) byte order.
+    ///
+    /// Two-byte replies decode as [`ResponseFrame::Short`]; anything longer
+    /// must be a full frame with a matching length byte and checksum.
+    ///
+    /// # Errors
+    /// Returns `M18Error::ShortFrame` for truncated frames or a length
+    /// mismatch, and `M18Error::ChecksumMismatch` if the checksum is wrong.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() == 2 {
+            return Ok(ResponseFrame::Short {
+                header: bytes[0],
+                code: bytes[1],
+            });
+        }
+
+        let (header, acc, payload) = decode_full(bytes)?;
+        Ok(ResponseFrame::Data {
+            header,
+            acc,
+            payload: payload.to_vec(),
+        })
+    }
+
+    /// Decode the reply to `request`, turning rejections into errors.
+    ///
+    /// # Errors
+    /// Returns `M18Error::Nack` for a NACK, carrying the address of a memory
+    /// request, and `M18Error::InvalidResponse` with the raw frame if the
+    /// header is not a known reply header. Otherwise fails as
+    /// [`ResponseFrame::decode`] does.
+    ///
+    /// # Examples
+    /// ```
+    /// use m18_protocol::frame::{RequestFrame, ResponseFrame};
+    /// use m18_protocol::M18Error;
+    ///
+    /// let request = RequestFrame::new(0x01, 0x04, vec![0x40, 0x0A, 0x02]);
+    /// let error = ResponseFrame::decode_reply(&[0x82, 0x01], &request).unwrap_err();
+    /// assert!(matches!(error, M18Error::Nack { code: 0x01, address: Some(0x400A) }));
+    /// ```
+    pub fn decode_reply(bytes: &[u8], request: &RequestFrame) -> Result<Self> {
+        match *bytes {
+            [NACK_HEADER, code] => Err(M18Error::Nack {
+                code,
+                address: request.address(),
+            }),
+            [header, ..] if !is_known_reply_header(header) => Err(M18Error::InvalidResponse {
+                expected: "a known reply header".to_string(),
+                actual: format!("header {:#04x} in {:02X?}", header, bytes),
+            }),
+            _ => Self::decode(bytes),
+        }
+    }
+
+    /// Encode the response in logical byte order.
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            ResponseFrame::Data {
+                header,
+                acc,
+                payload,
+            } => {
+                let mut bytes = vec![*header, *acc, payload.len() as u8];
+                bytes.extend_from_slice(payload);
+                with_checksum(bytes)
+            }
+            ResponseFrame::Short { header, code } => vec![*header, *code],
+        }
+    }
+
+    /// Response header byte.
+    pub fn header(&self) -> u8 {
+        match self {
+            ResponseFrame::Data { header, .. } | ResponseFrame::Short { header, .. } => *header,
+        }
+    }
+
+    /// Response payload (empty for short replies).
+    pub fn payload(&self) -> &[u8] {
+        match self {
+            ResponseFrame::Data { payload, .. } => payload,
+            ResponseFrame::Short { .. } => &[],
+        }
+    }
+
+    /// Whether this is a NACK reply.
+    pub fn is_nack(&self) -> bool {
+        self.header() == NACK_HEADER
+    }
+}
@@ -0,0 +1,133 @@
+//! Time-in-bin histograms kept by the battery.
+//!
+//! The pack counts how many seconds it has spent in each band of a quantity,
+//! such as discharge current. A [`Histogram`] keeps those counts with the
+//! numeric edges of every bin, so totals, percentages and statistics can be
+//! worked out without parsing labels like `"10-20A"` or durations like
+//! `"HH:MM:SS"`.
+//!
+//! # Examples
+//! ```
+//! use m18_protocol::{Histogram, HistogramBin};
+//!
+//! let histogram = Histogram {
+//!     units: "A".to_string(),
+//!     bins: vec![
+//!         HistogramBin { lower: 10.0, upper: Some(20.0), seconds: Some(300) },
+//!         HistogramBin { lower: 20.0, upper: Some(30.0), seconds: Some(100) },
+//!         HistogramBin { lower: 30.0, upper: None, seconds: Some(0) },
+//!     ],
+//! };
+//!
+//! assert_eq!(histogram.total_seconds(), Some(400));
+//! assert_eq!(histogram.percentages(), vec![Some(75.0), Some(25.0), Some(0.0)]);
+//! assert_eq!(histogram.mean(), Some(17.5));
+//! let median = histogram.percentile(50.0).unwrap();
+//! assert!((median - 16.67).abs() < 0.01);
+//! assert_eq!(histogram.bins[2].label(&histogram.units), "> 30A");
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+/// One bin of a [`Histogram`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistogramBin {
+    /// Lower edge of the bin
+    pub lower: f64,
+    /// Upper edge of the bin, or `None` if the bin has no upper bound
+    pub upper: Option<f64>,
+    /// Seconds spent in the bin, if known
+    pub seconds: Option<u32>,
+}
+
+impl HistogramBin {
+    /// Range of the bin as text, e.g. `"10-20A"` or `"> 200A"`.
+    pub fn label(&self, units: &str) -> String {
+        match self.upper {
+            Some(upper) => format!("{}-{}{}", self.lower, upper, units),
+            None => format!("> {}{}", self.lower, units),
+        }
+    }
+
+    /// Value the bin stands for: its midpoint, or its lower edge if it has
+    /// no upper bound
+    fn center(&self) -> f64 {
+        match self.upper {
+            Some(upper) => (self.lower + upper) / 2.0,
+            None => self.lower,
+        }
+    }
+}
+
+/// Seconds spent in each band of a quantity.
+///
+/// Statistics that need every bin return `None` if any bin is unknown.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Histogram {
+    /// Unit of the bin edges, e.g. `"A"`
+    pub units: String,
+    /// Bins in ascending order
+    pub bins: Vec<HistogramBin>,
+}
+
+impl Histogram {
+    /// Total seconds over all bins.
+    pub fn total_seconds(&self) -> Option<u64> {
+        self.bins.iter().map(|bin| bin.seconds.map(u64::from)).sum()
+    }
+
+    /// Share of the total time spent in each bin, in percent.
+    ///
+    /// Every bin is 0% if no time has been recorded at all.
+    pub fn percentages(&self) -> Vec<Option<f64>> {
+        let total = self.total_seconds();
+        self.bins
+            .iter()
+            .map(|bin| match (bin.seconds, total) {
+                (Some(seconds), Some(total)) if total > 0 => {
+                    Some(seconds as f64 / total as f64 * 100.0)
+                }
+                (Some(_), Some(_)) => Some(0.0),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Time-weighted mean, counting each bin at its midpoint.
+    ///
+    /// A bin with no upper bound counts at its lower edge, so the mean is a
+    /// lower estimate if time was spent there. Returns `None` if no time has
+    /// been recorded.
+    pub fn mean(&self) -> Option<f64> {
+        let total = self.total_seconds().filter(|&total| total > 0)?;
+        let weighted: f64 = self
+            .bins
+            .iter()
+            .map(|bin| bin.center() * bin.seconds.unwrap_or(0) as f64)
+            .sum();
+        Some(weighted / total as f64)
+    }
+
+    /// Value below which `percent` of the time was spent.
+    ///
+    /// Interpolates linearly within the bin the percentile falls in; a bin
+    /// with no upper bound gives its lower edge. `percent` is clamped to
+    /// 0-100. Returns `None` if no time has been recorded.
+    pub fn percentile(&self, percent: f64) -> Option<f64> {
+        let total = self.total_seconds().filter(|&total| total > 0)?;
+        let target = percent.clamp(0.0, 100.0) / 100.0 * total as f64;
+
+        let mut below = 0.0;
+        for bin in &self.bins {
+            let seconds = bin.seconds.unwrap_or(0) as f64;
+            if seconds > 0.0 && below + seconds >= target {
+                return Some(match bin.upper {
+                    Some(upper) => bin.lower + (target - below) / seconds * (upper - bin.lower),
+                    None => bin.lower,
+                });
+            }
+            below += seconds;
+        }
+        None
+    }
+}
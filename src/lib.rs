@@ -2,12 +2,46 @@
 //!
 //! A Rust library for interfacing with Milwaukee M18 battery packs via serial communication.
 
+#[cfg(feature = "async")]
+pub mod async_protocol;
+pub mod builder;
+pub mod charge_session;
 pub mod constants;
 pub mod data;
+pub mod diff;
+pub mod emulator;
 pub mod error;
+mod exchange;
+pub mod frame;
+pub mod histogram;
+pub mod line_control;
+pub mod memory_image;
 pub mod protocol;
+pub mod read_plan;
+pub mod recording;
+pub mod register;
+pub mod retry;
+pub mod scan;
+pub mod session;
+pub mod shared;
+pub mod transport;
 pub mod types;
 
-pub use error::{M18Error, Result};
+#[cfg(feature = "async")]
+pub use async_protocol::{AsyncM18, AsyncTransport};
+pub use builder::{M18Builder, Timings};
+pub use charge_session::{ChargeEvent, ChargeSession};
+pub use data::RegisterMap;
+pub use error::{M18Error, ReadFailure, Result};
+pub use histogram::{Histogram, HistogramBin};
+pub use line_control::LineControl;
+pub use memory_image::MemoryImage;
 pub use protocol::M18;
+pub use register::{Register, RegisterSelector};
+pub use retry::{RetryAction, RetryPolicy, RetryStats};
+#[cfg(feature = "async")]
+pub use session::AsyncSession;
+pub use session::Session;
+pub use shared::SharedM18;
+pub use transport::{SerialTransport, Transport};
 pub use types::*;
@@ -0,0 +1,182 @@
+//! J2 line-control wiring.
+//!
+//! The battery is woken and reset by pulling its J2 pin low, which adapters do
+//! with a serial control line. The reference level shifter pulls J2 low while
+//! TX is held in break and DTR is high, but optocoupler boards often invert
+//! DTR, and some adapters use RTS instead. [`LineControl`] describes which
+//! lines drive J2 and with what polarity; [`M18`](crate::M18) uses it
+//! everywhere J2 is driven.
+//!
+//! # Examples
+//! ```
+//! use m18_protocol::{emulator::VirtualBattery, LineControl, M18Builder};
+//! use std::sync::atomic::{AtomicU32, Ordering};
+//! use std::sync::Arc;
+//!
+//! // Drive J2 from break only, counting the changes
+//! let changes = Arc::new(AtomicU32::new(0));
+//! let counter = changes.clone();
+//! let line_control = LineControl::custom(move |lines, low| {
+//!     counter.fetch_add(1, Ordering::Relaxed);
+//!     lines.set_break(low)
+//! });
+//!
+//! let battery = VirtualBattery::new();
+//! let mut m18 = M18Builder::from_transport(battery.transport())
+//!     .line_control(line_control)
+//!     .build()?;
+//! assert!(m18.reset()?.is_synced());
+//! assert!(changes.load(Ordering::Relaxed) >= 2);
+//! # Ok::<(), m18_protocol::M18Error>(())
+//! ```
+
+use crate::error::Result;
+use crate::transport::Transport;
+use std::fmt;
+use std::sync::Arc;
+
+/// Serial control lines that can drive J2.
+pub trait ControlLines {
+    /// Assert (`true`) or release (`false`) the break condition on TX.
+    fn set_break(&mut self, enabled: bool) -> Result<()>;
+
+    /// Drive the DTR line to the given level.
+    fn set_dtr(&mut self, level: bool) -> Result<()>;
+
+    /// Drive the RTS line to the given level.
+    fn set_rts(&mut self, level: bool) -> Result<()>;
+}
+
+impl<T: Transport> ControlLines for T {
+    fn set_break(&mut self, enabled: bool) -> Result<()> {
+        Transport::set_break(self, enabled)
+    }
+
+    fn set_dtr(&mut self, level: bool) -> Result<()> {
+        Transport::set_dtr(self, level)
+    }
+
+    fn set_rts(&mut self, level: bool) -> Result<()> {
+        Transport::set_rts(self, level)
+    }
+}
+
+/// Function that puts J2 low (`true`) or high (`false`)
+type CustomLineControl = dyn Fn(&mut dyn ControlLines, bool) -> Result<()> + Send + Sync;
+
+/// How the adapter drives J2.
+///
+/// Without `inverted`, a modem line is driven high to pull J2 low.
+#[derive(Clone)]
+pub enum LineControl {
+    /// TX break and DTR together (the reference level shifter)
+    BreakAndDtr {
+        /// DTR is driven low to pull J2 low
+        inverted: bool,
+    },
+    /// TX break only
+    Break,
+    /// DTR only
+    Dtr {
+        /// DTR is driven low to pull J2 low
+        inverted: bool,
+    },
+    /// RTS only
+    Rts {
+        /// RTS is driven low to pull J2 low
+        inverted: bool,
+    },
+    /// Caller-supplied function, called with `true` to pull J2 low
+    Custom(Arc<CustomLineControl>),
+}
+
+impl LineControl {
+    /// Drive J2 with a custom function.
+    ///
+    /// # Arguments
+    /// * `drive` - Called with the control lines and `true` to pull J2 low,
+    ///   `false` to release it
+    pub fn custom(
+        drive: impl Fn(&mut dyn ControlLines, bool) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        LineControl::Custom(Arc::new(drive))
+    }
+
+    /// Pull J2 low (`true`) or release it high (`false`).
+    ///
+    /// # Errors
+    /// Returns error if a control line cannot be driven.
+    ///
+    /// # Examples
+    /// ```
+    /// use m18_protocol::emulator::{LineLevels, VirtualBattery};
+    /// use m18_protocol::LineControl;
+    ///
+    /// let levels = |wiring: LineControl, low: bool| {
+    ///     let battery = VirtualBattery::new();
+    ///     wiring.set_j2_low(&mut battery.transport(), low)?;
+    ///     Ok::<_, m18_protocol::M18Error>(battery.lines())
+    /// };
+    /// let lines = |break_enabled, dtr, rts| LineLevels { break_enabled, dtr, rts };
+    ///
+    /// // Pulling J2 low asserts break
+    /// assert_eq!(levels(LineControl::Break, true)?, lines(true, false, false));
+    /// assert_eq!(levels(LineControl::Break, false)?, lines(false, false, false));
+    ///
+    /// // A modem line is driven high to pull J2 low, and low when inverted
+    /// let dtr = |inverted| LineControl::Dtr { inverted };
+    /// assert_eq!(levels(dtr(false), true)?, lines(false, true, false));
+    /// assert_eq!(levels(dtr(false), false)?, lines(false, false, false));
+    /// assert_eq!(levels(dtr(true), true)?, lines(false, false, false));
+    /// assert_eq!(levels(dtr(true), false)?, lines(false, true, false));
+    ///
+    /// let rts = |inverted| LineControl::Rts { inverted };
+    /// assert_eq!(levels(rts(false), true)?, lines(false, false, true));
+    /// assert_eq!(levels(rts(false), false)?, lines(false, false, false));
+    /// assert_eq!(levels(rts(true), true)?, lines(false, false, false));
+    /// assert_eq!(levels(rts(true), false)?, lines(false, false, true));
+    ///
+    /// let break_and_dtr = |inverted| LineControl::BreakAndDtr { inverted };
+    /// assert_eq!(levels(break_and_dtr(false), true)?, lines(true, true, false));
+    /// assert_eq!(levels(break_and_dtr(true), true)?, lines(true, false, false));
+    /// assert_eq!(levels(break_and_dtr(true), false)?, lines(false, true, false));
+    /// # Ok::<(), m18_protocol::M18Error>(())
+    /// ```
+    pub fn set_j2_low(&self, lines: &mut dyn ControlLines, low: bool) -> Result<()> {
+        match self {
+            LineControl::BreakAndDtr { inverted } => {
+                lines.set_break(low)?;
+                lines.set_dtr(low != *inverted)
+            }
+            LineControl::Break => lines.set_break(low),
+            LineControl::Dtr { inverted } => lines.set_dtr(low != *inverted),
+            LineControl::Rts { inverted } => lines.set_rts(low != *inverted),
+            LineControl::Custom(drive) => drive(lines, low),
+        }
+    }
+}
+
+impl Default for LineControl {
+    fn default() -> Self {
+        LineControl::BreakAndDtr { inverted: false }
+    }
+}
+
+impl fmt::Debug for LineControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LineControl::BreakAndDtr { inverted } => f
+                .debug_struct("BreakAndDtr")
+                .field("inverted", inverted)
+                .finish(),
+            LineControl::Break => f.write_str("Break"),
+            LineControl::Dtr { inverted } => {
+                f.debug_struct("Dtr").field("inverted", inverted).finish()
+            }
+            LineControl::Rts { inverted } => {
+                f.debug_struct("Rts").field("inverted", inverted).finish()
+            }
+            LineControl::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
@@ -0,0 +1,490 @@
+//! Captured battery memory images.
+//!
+//! A [`MemoryImage`] holds the contents of every region in the
+//! [`RegisterMap`](crate::RegisterMap) together with when and how it was
+//! captured. Images can be saved and loaded without a battery or serial port,
+//! so a capture taken in the field can be analysed later.
+//!
+//! # File formats
+//!
+//! Images are saved either as JSON or in a compact binary form, and
+//! [`MemoryImage::load`] accepts both. Both carry a format version, currently
+//! [`IMAGE_FORMAT_VERSION`]; loading a file with a different version fails.
+//!
+//! JSON files mirror the struct fields, with region data as a hex string and
+//! `null` for regions that could not be read.
+//!
+//! Binary files are big-endian:
+//!
+//! | Size     | Field                                                    |
+//! |----------|----------------------------------------------------------|
+//! | 4        | Magic `M18I`                                             |
+//! | 2        | Format version                                           |
+//! | 8        | Capture time, milliseconds since the Unix epoch (signed) |
+//! | 4        | Host UTC offset in seconds (signed)                      |
+//! | 1 + n    | Library version: length, then UTF-8 bytes                |
+//! | 2        | Region count                                             |
+//!
+//! followed by each region as address (2), length (1), status (1: `0` read,
+//! `1` failed) and, for regions that were read, `length` data bytes.
+//!
+//! # Examples
+//! ```
+//! use m18_protocol::{emulator::VirtualBattery, MemoryImage, RegisterMap, M18};
+//!
+//! let battery = VirtualBattery::new();
+//! let mut m18 = M18::with_transport(battery.transport());
+//! let image = m18.capture_image()?;
+//!
+//! // Later, without a battery
+//! let image = MemoryImage::from_bytes(&image.to_bytes())?;
+//! let report = image.health_report(RegisterMap::embedded())?;
+//! assert_eq!(report.electronic_serial, 1234567);
+//! # Ok::<(), m18_protocol::M18Error>(())
+//! ```
+
+use crate::data::RegisterMap;
+use crate::diff::ImageDiff;
+use crate::error::{M18Error, Result};
+use crate::protocol::{format_register_lines, parse_register_data};
+use crate::register::RegisterSelector;
+use crate::types::{HealthReport, MemoryRegion, OutputFormat, RegisterValue};
+use chrono::{DateTime, FixedOffset, Local, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Version of the image file formats written by this library
+pub const IMAGE_FORMAT_VERSION: u16 = 1;
+
+/// Magic bytes at the start of a binary image
+const BINARY_MAGIC: &[u8; 4] = b"M18I";
+
+/// One memory region of a captured image.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageRegion {
+    /// Start address
+    pub address: u16,
+    /// Region length in bytes
+    pub length: u8,
+    /// Region contents, or `None` if the region could not be read
+    #[serde(with = "hex_bytes")]
+    pub data: Option<Vec<u8>>,
+}
+
+/// Contents of battery memory plus capture metadata.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryImage {
+    /// File format version
+    pub format_version: u16,
+    /// When the capture started
+    pub captured_at: DateTime<Utc>,
+    /// Host wall-clock time at the start of the capture, with its UTC offset
+    pub host_clock: DateTime<FixedOffset>,
+    /// Version of this library that took the capture
+    pub library_version: String,
+    /// Every register map region, in order
+    pub regions: Vec<ImageRegion>,
+}
+
+impl MemoryImage {
+    /// Create an image with every region unread, timestamped now.
+    pub fn new() -> Self {
+        Self::with_regions(RegisterMap::embedded().regions())
+    }
+
+    /// Create an image of the given regions, all unread, timestamped now
+    pub(crate) fn with_regions(regions: &[MemoryRegion]) -> Self {
+        Self::from_regions(
+            regions
+                .iter()
+                .map(|region| ImageRegion {
+                    address: region.address(),
+                    length: region.length,
+                    data: None,
+                })
+                .collect(),
+        )
+    }
+
+    /// Create an image holding the given regions, timestamped now
+    pub(crate) fn from_regions(regions: Vec<ImageRegion>) -> Self {
+        let host_clock = Local::now().fixed_offset();
+        MemoryImage {
+            format_version: IMAGE_FORMAT_VERSION,
+            captured_at: host_clock.with_timezone(&Utc),
+            host_clock,
+            library_version: env!("CARGO_PKG_VERSION").to_string(),
+            regions,
+        }
+    }
+
+    /// Create an image from `(address, data)` pairs as returned by
+    /// [`M18::read_all_raw`](crate::M18::read_all_raw).
+    ///
+    /// Regions without a matching pair are marked as failed.
+    ///
+    /// # Arguments
+    /// * `raw` - Captured `(address, data)` pairs
+    /// * `register_map` - Map the capture was read with, e.g. [`M18::register_map`](crate::M18::register_map)
+    pub fn from_raw(raw: &[(u16, Vec<u8>)], register_map: &RegisterMap) -> Self {
+        let mut image = Self::with_regions(register_map.regions());
+        for region in &mut image.regions {
+            region.data = raw
+                .iter()
+                .find(|(address, data)| {
+                    *address == region.address && data.len() == region.length as usize
+                })
+                .map(|(_, data)| data.clone());
+        }
+        image
+    }
+
+    /// Regions that could not be read.
+    pub fn failed_regions(&self) -> impl Iterator<Item = &ImageRegion> {
+        self.regions.iter().filter(|region| region.data.is_none())
+    }
+
+    /// `(address, data)` pairs for every region that was read.
+    pub fn raw(&self) -> Vec<(u16, Vec<u8>)> {
+        self.regions
+            .iter()
+            .filter_map(|region| Some((region.address, region.data.clone()?)))
+            .collect()
+    }
+
+    /// Read bytes from the image.
+    ///
+    /// # Returns
+    /// The bytes at `address..address + length` if they lie within a single
+    /// region that was read.
+    pub fn read(&self, address: u16, length: usize) -> Option<&[u8]> {
+        self.regions.iter().find_map(|region| {
+            let data = region.data.as_deref()?;
+            let offset = address.checked_sub(region.address)? as usize;
+            data.get(offset..offset + length)
+        })
+    }
+
+    /// Parse registers from the image.
+    ///
+    /// # Arguments
+    /// * `registers` - Registers to parse, as IDs (0-183) or [`Register`](crate::Register)s
+    /// * `register_map` - Map the image was captured with
+    ///
+    /// # Returns
+    /// Vector of (register_id, parsed_value) tuples for registers whose bytes
+    /// were captured and parse successfully.
+    pub fn registers<R: RegisterSelector>(
+        &self,
+        registers: &[R],
+        register_map: &RegisterMap,
+    ) -> Vec<(usize, RegisterValue)> {
+        registers
+            .iter()
+            .filter_map(|selector| {
+                let id = register_map.resolve(selector)?;
+                let register = &register_map.registers()[id];
+                let data = self.read(register.address, register.length as usize)?;
+                let value = parse_register_data(register, data).ok()?;
+                Some((id, value))
+            })
+            .collect()
+    }
+
+    /// Parse every register of `register_map` from the image.
+    pub fn all_registers(&self, register_map: &RegisterMap) -> Vec<(usize, RegisterValue)> {
+        let ids: Vec<usize> = (0..register_map.registers().len()).collect();
+        self.registers(&ids, register_map)
+    }
+
+    /// Format register values parsed from the image as lines of text.
+    ///
+    /// See [`M18::format_registers`](crate::M18::format_registers).
+    pub fn format_registers(
+        &self,
+        results: &[(usize, RegisterValue)],
+        format: OutputFormat,
+        register_map: &RegisterMap,
+    ) -> Vec<String> {
+        format_register_lines(register_map.registers(), results, format)
+    }
+
+    /// Build a health report from the image.
+    ///
+    /// The report is stamped with the capture time rather than the current time.
+    ///
+    /// # Errors
+    /// Returns error if registers required by the report were not captured.
+    pub fn health_report(&self, register_map: &RegisterMap) -> Result<HealthReport> {
+        HealthReport::from_raw(&self.raw(), self.captured_at, register_map)
+    }
+
+    /// Compare the image with a later one.
+    ///
+    /// See [`ImageDiff::new`].
+    pub fn diff(&self, newer: &MemoryImage, register_map: &RegisterMap) -> ImageDiff {
+        ImageDiff::new(&self.raw(), &newer.raw(), register_map)
+    }
+
+    /// Serialize the image as pretty-printed JSON.
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| M18Error::Parse(format!("Failed to serialize image: {}", e)))
+    }
+
+    /// Parse an image from JSON.
+    ///
+    /// # Errors
+    /// Returns `M18Error::Parse` if the JSON is invalid, has an unsupported
+    /// version, or holds a region whose data does not match its length.
+    ///
+    /// # Examples
+    /// ```
+    /// use m18_protocol::MemoryImage;
+    ///
+    /// let mut image = MemoryImage::new();
+    /// image.regions[0].data = Some(vec![0; image.regions[0].length as usize + 1]);
+    /// assert!(MemoryImage::from_json(&image.to_json()?).is_err());
+    /// # Ok::<(), m18_protocol::M18Error>(())
+    /// ```
+    pub fn from_json(json: &str) -> Result<Self> {
+        let image: MemoryImage = serde_json::from_str(json)
+            .map_err(|e| M18Error::Parse(format!("Invalid image JSON: {}", e)))?;
+        check_version(image.format_version)?;
+        for region in &image.regions {
+            if let Some(data) = &region.data {
+                if data.len() != region.length as usize {
+                    return Err(M18Error::Parse(format!(
+                        "Region 0x{:04X} has {} data bytes but a length of {}",
+                        region.address,
+                        data.len(),
+                        region.length
+                    )));
+                }
+            }
+        }
+        Ok(image)
+    }
+
+    /// Serialize the image in the compact binary form.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = BINARY_MAGIC.to_vec();
+        bytes.extend_from_slice(&IMAGE_FORMAT_VERSION.to_be_bytes());
+        bytes.extend_from_slice(&self.captured_at.timestamp_millis().to_be_bytes());
+        bytes.extend_from_slice(&self.host_clock.offset().local_minus_utc().to_be_bytes());
+
+        let version = self.library_version.as_bytes();
+        let version = &version[..version.len().min(u8::MAX as usize)];
+        bytes.push(version.len() as u8);
+        bytes.extend_from_slice(version);
+
+        bytes.extend_from_slice(&(self.regions.len() as u16).to_be_bytes());
+        for region in &self.regions {
+            bytes.extend_from_slice(&region.address.to_be_bytes());
+            bytes.push(region.length);
+            match &region.data {
+                Some(data) => {
+                    bytes.push(0);
+                    bytes.extend_from_slice(data);
+                }
+                None => bytes.push(1),
+            }
+        }
+        bytes
+    }
+
+    /// Parse an image from the compact binary form.
+    ///
+    /// # Errors
+    /// Returns `M18Error::Parse` if the data is truncated, malformed or has an
+    /// unsupported version.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut reader = ByteReader { bytes };
+        if reader.take(4)? != BINARY_MAGIC {
+            return Err(M18Error::Parse("Not a binary memory image".to_string()));
+        }
+        let format_version = reader.u16()?;
+        check_version(format_version)?;
+
+        let millis = i64::from_be_bytes(reader.array()?);
+        let offset = i32::from_be_bytes(reader.array()?);
+        let captured_at = Utc
+            .timestamp_millis_opt(millis)
+            .single()
+            .ok_or_else(|| M18Error::Parse("Invalid capture time".to_string()))?;
+        let offset = FixedOffset::east_opt(offset)
+            .ok_or_else(|| M18Error::Parse("Invalid host UTC offset".to_string()))?;
+
+        let version_length = reader.u8()? as usize;
+        let library_version = String::from_utf8(reader.take(version_length)?.to_vec())
+            .map_err(|_| M18Error::Parse("Invalid library version".to_string()))?;
+
+        let count = reader.u16()?;
+        let mut regions = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let address = reader.u16()?;
+            let length = reader.u8()?;
+            let data = match reader.u8()? {
+                0 => Some(reader.take(length as usize)?.to_vec()),
+                1 => None,
+                status => {
+                    return Err(M18Error::Parse(format!(
+                        "Invalid status {} for region 0x{:04X}",
+                        status, address
+                    )))
+                }
+            };
+            regions.push(ImageRegion {
+                address,
+                length,
+                data,
+            });
+        }
+
+        if !reader.bytes.is_empty() {
+            return Err(M18Error::Parse(format!(
+                "{} unexpected bytes after image",
+                reader.bytes.len()
+            )));
+        }
+
+        Ok(MemoryImage {
+            format_version,
+            captured_at,
+            host_clock: captured_at.with_timezone(&offset),
+            library_version,
+            regions,
+        })
+    }
+
+    /// Save the image as JSON.
+    pub fn save_json(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    /// Save the image in the compact binary form.
+    pub fn save_binary(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    /// Load an image saved in either format.
+    ///
+    /// # Errors
+    /// Returns `M18Error::Io` if the file cannot be read, or `M18Error::Parse`
+    /// if it is not a valid image.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let bytes = std::fs::read(path)?;
+        if bytes.starts_with(BINARY_MAGIC) {
+            Self::from_bytes(&bytes)
+        } else {
+            let json = String::from_utf8(bytes)
+                .map_err(|_| M18Error::Parse("Image is neither binary nor JSON".to_string()))?;
+            Self::from_json(&json)
+        }
+    }
+}
+
+impl Default for MemoryImage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reject image versions this library cannot read
+fn check_version(version: u16) -> Result<()> {
+    if version != IMAGE_FORMAT_VERSION {
+        return Err(M18Error::Parse(format!(
+            "Unsupported image format version {} (expected {})",
+            version, IMAGE_FORMAT_VERSION
+        )));
+    }
+    Ok(())
+}
+
+/// Cursor over a binary image
+struct ByteReader<'a> {
+    /// Bytes not yet consumed
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    /// Consume `length` bytes
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < length {
+            return Err(M18Error::Parse("Binary image is truncated".to_string()));
+        }
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    /// Consume a fixed-size array
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    /// Consume one byte
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// Consume a big-endian `u16`
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+}
+
+/// Serde helper storing optional bytes as a hex string
+pub(crate) mod hex_bytes {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        data: &Option<Vec<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match data {
+            Some(bytes) => {
+                let hex: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                serializer.serialize_some(&hex)
+            }
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u8>>, D::Error> {
+        let Some(hex) = Option::<String>::deserialize(deserializer)? else {
+            return Ok(None);
+        };
+        if !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+            return Err(D::Error::custom(
+                "hex data must have an even number of digits",
+            ));
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(D::Error::custom))
+            .collect::<Result<Vec<u8>, _>>()
+            .map(Some)
+    }
+}
+
+/// Serde helper storing bytes as a hex string
+pub(crate) mod hex_data {
+    use super::hex_bytes;
+    use serde::{de::Error, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        let hex: String = data.iter().map(|b| format!("{:02X}", b)).collect();
+        serializer.serialize_str(&hex)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        hex_bytes::deserialize(deserializer)?.ok_or_else(|| D::Error::custom("missing data"))
+    }
+}
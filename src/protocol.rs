@@ -3,15 +3,30 @@
 //! This module contains the main M18 struct and all protocol communication
 //! methods for interfacing with Milwaukee M18 batteries via UART.
 
+use crate::builder::{M18Builder, Timings};
+use crate::charge_session::ChargeEvent;
 use crate::constants::*;
-use crate::data::{create_data_id, DATA_MATRIX};
-use crate::error::{M18Error, Result};
+use crate::data::RegisterMap;
+use crate::error::{M18Error, ReadFailure, Result};
+use crate::exchange::{self, HandshakeStep, Retries, CHARGER_HANDSHAKE};
+use crate::frame::{self, RequestFrame, ResponseFrame};
+use crate::histogram::{Histogram, HistogramBin};
+use crate::line_control::LineControl;
+use crate::memory_image::MemoryImage;
+use crate::read_plan::{PlannedRead, ReadPlan};
+use crate::recording::{Recorder, RecordingTransport};
+use crate::register::{Register, RegisterSelector, DISCHARGE_HISTOGRAM_BINS};
+use crate::retry::{RetryPolicy, RetryStats};
+use crate::scan::{ProbeResult, Scan, ScanProbe};
+use crate::session::Session;
+use crate::transport::Transport;
 use crate::types::*;
 use chrono::{DateTime, TimeZone, Utc};
 use log::{debug, info, warn};
-use serialport::SerialPort;
 use std::collections::HashMap;
-use std::io::{Read, Write};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
 use std::thread;
 use std::time::{Duration, Instant};
 
@@ -21,25 +36,38 @@ use std::time::{Duration, Instant};
 /// including reading diagnostics, simulating charger behavior, and extracting
 /// comprehensive health reports.
 pub struct M18 {
-    /// Serial port connection to battery
-    port: Box<dyn SerialPort>,
+    /// Byte transport connected to the battery
+    port: Box<dyn Transport>,
+    /// Recording switch for traffic on `port`
+    recorder: Recorder,
     /// Current accumulator value for command sequencing
     acc: u8,
     /// Whether to print transmitted data (for debugging)
     print_tx: bool,
     /// Whether to print received data (for debugging)
     print_rx: bool,
-    /// Register definitions with metadata
-    register_defs: Vec<RegisterDef>,
+    /// Memory regions and register definitions
+    register_map: RegisterMap,
+    /// Longest read the read planner may send
+    max_read_length: u8,
     /// Battery type lookup table
     battery_lookup: HashMap<u16, BatteryType>,
+    /// Retry policy and retry counters
+    retries: Retries,
+    /// Reset, response and keepalive delays
+    timings: Timings,
+    /// How the adapter drives J2
+    line_control: LineControl,
+    /// Sessions currently open on this interface
+    open_sessions: u32,
 }
 
 impl M18 {
     /// Create a new M18 interface on the specified serial port.
     ///
     /// Opens the serial port, configures it for M18 communication (4800 baud,
-    /// 2 stop bits), and initializes the interface to idle state.
+    /// 2 stop bits), and initializes the interface to idle state. Use
+    /// [`M18Builder`] to change the timeout or protocol delays.
     ///
     /// # Arguments
     /// * `port_name` - Serial port name (e.g., "COM3" on Windows, "/dev/ttyUSB0" on Linux)
@@ -58,22 +86,81 @@ impl M18 {
     /// # Ok::<(), m18_protocol::M18Error>(())
     /// ```
     pub fn new(port_name: &str) -> Result<Self> {
-        let port = serialport::new(port_name, BAUD_RATE)
-            .timeout(Duration::from_millis(TIMEOUT_MS))
-            .stop_bits(STOP_BITS)
-            .open()?;
+        M18Builder::new(port_name).build()
+    }
 
+    /// Create a new M18 interface on top of an arbitrary transport.
+    ///
+    /// Use this to talk to a battery through something other than a local UART,
+    /// such as a TCP-to-serial bridge, a test double or a recorded trace. The
+    /// interface is put into the idle state before returning.
+    ///
+    /// # Arguments
+    /// * `transport` - Byte transport connected to the battery
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use m18_protocol::{transport::SerialTransport, M18};
+    ///
+    /// let transport = SerialTransport::open("/dev/ttyUSB0")?;
+    /// let mut m18 = M18::with_transport(transport);
+    /// # Ok::<(), m18_protocol::M18Error>(())
+    /// ```
+    pub fn with_transport(transport: impl Transport + 'static) -> Self {
+        Self::with_transport_and_timings(transport, Timings::default(), LineControl::default())
+    }
+
+    /// Create a new M18 interface with custom protocol delays
+    pub(crate) fn with_transport_and_timings(
+        transport: impl Transport + 'static,
+        timings: Timings,
+        line_control: LineControl,
+    ) -> Self {
+        let recorder = Recorder::default();
         let mut m18 = M18 {
-            port,
+            port: Box::new(RecordingTransport::new(transport, recorder.clone())),
+            recorder,
             acc: INITIAL_ACC,
             print_tx: false,
             print_rx: false,
-            register_defs: create_data_id(),
+            register_map: RegisterMap::embedded().clone(),
+            max_read_length: MAX_READ_LENGTH,
             battery_lookup: create_battery_lookup(),
+            retries: Retries::default(),
+            timings,
+            line_control,
+            open_sessions: 0,
         };
 
         m18.idle();
-        Ok(m18)
+        m18
+    }
+
+    /// Use a different register map.
+    ///
+    /// Replaces the embedded map for every read and for formatting output.
+    /// Register IDs are positions in the new map, so a map used for health
+    /// reports should keep the embedded map's order.
+    ///
+    /// # Arguments
+    /// * `register_map` - Validated map, e.g. from [`RegisterMap::load`]
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use m18_protocol::{RegisterMap, M18};
+    ///
+    /// let map = RegisterMap::load("my_registers.toml")?;
+    /// let mut m18 = M18::new("/dev/ttyUSB0")?.with_register_map(map);
+    /// # Ok::<(), m18_protocol::M18Error>(())
+    /// ```
+    pub fn with_register_map(mut self, register_map: RegisterMap) -> Self {
+        self.register_map = register_map;
+        self
+    }
+
+    /// Register map used by this interface.
+    pub fn register_map(&self) -> &RegisterMap {
+        &self.register_map
     }
 
     /// List available serial ports on the system.
@@ -84,6 +171,32 @@ impl M18 {
         Ok(serialport::available_ports()?)
     }
 
+    /// Reset, response and keepalive delays in use.
+    pub fn timings(&self) -> &Timings {
+        &self.timings
+    }
+
+    /// Set how the adapter drives J2.
+    ///
+    /// J2 is returned to idle with the new wiring straight away.
+    ///
+    /// # Arguments
+    /// * `line_control` - Control lines and polarity that pull J2 low
+    pub fn set_line_control(&mut self, line_control: LineControl) {
+        self.line_control = line_control;
+        self.idle();
+    }
+
+    /// How the adapter drives J2.
+    pub fn line_control(&self) -> &LineControl {
+        &self.line_control
+    }
+
+    /// Pull J2 low (`true`) or release it high (`false`)
+    fn set_j2_low(&mut self, low: bool) -> Result<()> {
+        self.line_control.set_j2_low(&mut self.port, low)
+    }
+
     /// Enable or disable debug printing for transmitted and received data.
     ///
     /// When enabled, all serial TX/RX will be printed to stdout in hex format.
@@ -97,33 +210,99 @@ impl M18 {
         self.print_rx = rx;
     }
 
+    /// Start recording all traffic to a file.
+    ///
+    /// Every byte sent and received from now on is written to `path`, along
+    /// with break and DTR changes, in the format described in
+    /// [`recording`](crate::recording). A recording already in progress is
+    /// ended first.
+    ///
+    /// # Errors
+    /// Returns `M18Error::Io` if the file cannot be created.
+    pub fn start_recording(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.record_to(BufWriter::new(File::create(path)?))
+    }
+
+    /// Start recording all traffic to an arbitrary writer.
+    ///
+    /// See [`M18::start_recording`].
+    pub fn record_to(&mut self, writer: impl Write + Send + 'static) -> Result<()> {
+        self.recorder.start(writer)
+    }
+
+    /// Stop recording and flush the recording.
+    ///
+    /// Does nothing if no recording is in progress.
+    pub fn stop_recording(&mut self) -> Result<()> {
+        self.recorder.stop()
+    }
+
     /// Reset the connected battery and establish communication.
     ///
-    /// Performs the reset sequence by toggling break/DTR, then sends a sync byte
+    /// Performs the reset sequence by pulsing J2 low through the configured
+    /// [`LineControl`], then sends a sync byte
     /// and waits for the battery to echo it back. This is required before most
     /// communication operations.
     ///
+    /// Resetting through an open session syncs the battery again, but J2
+    /// stays high until the outermost session ends.
+    ///
     /// # Returns
-    /// `Ok(true)` if reset succeeded and battery responded correctly,
-    /// `Ok(false)` if battery didn't respond or responded incorrectly.
-    pub fn reset(&mut self) -> Result<bool> {
+    /// A [`Session`] that keeps J2 high until it is dropped.
+    /// [`Session::is_synced`] is false if the battery didn't respond or
+    /// responded incorrectly.
+    ///
+    /// # Errors
+    /// Returns error if J2 cannot be driven or the sync byte cannot be sent.
+    /// J2 is returned to idle first.
+    pub fn reset(&mut self) -> Result<Session<'_>> {
+        let mut session = Session::new(self);
+        session.synced = session.sync()?;
+        Ok(session)
+    }
+
+    /// Reset the battery for an operation that cannot run without it.
+    ///
+    /// # Errors
+    /// Returns `M18Error::Timeout` if the battery did not answer the sync byte.
+    fn reset_synced(&mut self) -> Result<Session<'_>> {
+        let session = self.reset()?;
+        if !session.is_synced() {
+            return Err(M18Error::Timeout);
+        }
+        Ok(session)
+    }
+
+    /// Count a newly opened session
+    pub(crate) fn open_session(&mut self) {
+        self.open_sessions += 1;
+    }
+
+    /// Count a closed session, returning J2 to idle once none is left open
+    pub(crate) fn close_session(&mut self) {
+        self.open_sessions = self.open_sessions.saturating_sub(1);
+        if self.open_sessions == 0 {
+            self.idle();
+        }
+    }
+
+    /// Run the reset sequence, leaving J2 high
+    fn sync(&mut self) -> Result<bool> {
         self.acc = INITIAL_ACC;
 
-        // Toggle break condition and DTR for reset
-        self.port.set_break()?;
-        self.port.write_data_terminal_ready(true)?;
-        thread::sleep(Duration::from_millis(RESET_BREAK_DURATION_MS));
+        // Pulse J2 low for reset
+        self.set_j2_low(true)?;
+        thread::sleep(self.timings.reset_break);
 
-        self.port.clear_break()?;
-        self.port.write_data_terminal_ready(false)?;
-        thread::sleep(Duration::from_millis(RESET_SETTLE_DURATION_MS));
+        self.set_j2_low(false)?;
+        thread::sleep(self.timings.reset_settle);
 
         // Send sync byte
         self.send(&[SYNC_BYTE])?;
 
-        match self.read_response(1) {
+        match self.read_raw(1) {
             Ok(response) if response.len() == 1 && response[0] == SYNC_BYTE => {
-                thread::sleep(Duration::from_millis(RESET_SYNC_DELAY_MS));
+                thread::sleep(self.timings.reset_sync_delay);
                 Ok(true)
             }
             Ok(response) => {
@@ -136,103 +315,119 @@ impl M18 {
         }
     }
 
-    /// Update the ACC (accumulator) value for next command
-    fn update_acc(&mut self) {
-        let current_index = ACC_VALUES.iter().position(|&x| x == self.acc).unwrap_or(0);
-        let next_index = (current_index + 1) % ACC_VALUES.len();
-        self.acc = ACC_VALUES[next_index];
+    /// Set how failed commands are retried.
+    ///
+    /// Used by [`M18::read_all_raw`], [`M18::read_registers`],
+    /// [`M18::write_message`] and [`M18::simulate_for`]. The default is
+    /// [`RetryPolicy::default`].
+    ///
+    /// # Arguments
+    /// * `policy` - Retry policy; [`RetryPolicy::none`] disables retries
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retries.policy = policy;
+    }
+
+    /// Current retry policy.
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retries.policy
     }
 
-    /// Reverse bits in a byte (for protocol bit ordering)
-    fn reverse_bits(byte: u8) -> u8 {
-        let mut result = 0u8;
-        for i in 0..8 {
-            if byte & (1 << i) != 0 {
-                result |= 1 << (7 - i);
+    /// Retries made by the last operation that uses the retry policy.
+    pub fn retry_stats(&self) -> RetryStats {
+        self.retries.stats
+    }
+
+    /// Run a command, retrying failures as the retry policy allows
+    pub(crate) fn retrying<T>(
+        &mut self,
+        mut command: impl FnMut(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        let mut retry = 0;
+        loop {
+            let error = match command(self) {
+                Ok(value) => {
+                    self.retries.succeeded();
+                    return Ok(value);
+                }
+                Err(e) => e,
+            };
+
+            let step = self.retries.failed(error, &mut retry)?;
+            thread::sleep(step.backoff);
+            if step.resync && !self.resync() {
+                return Err(step.error);
             }
         }
-        result
     }
 
-    /// Calculate checksum for payload
-    fn checksum(payload: &[u8]) -> u16 {
-        payload.iter().map(|&b| b as u16).sum()
+    /// Re-run the reset sequence to get the ACC sequence back in step
+    fn resync(&mut self) -> bool {
+        let synced = matches!(self.sync(), Ok(true));
+        self.retries.resynced(synced)
     }
 
-    /// Add checksum to command
-    fn add_checksum(command: &[u8]) -> Vec<u8> {
-        let mut result = command.to_vec();
-        let checksum = Self::checksum(command);
-        result.extend_from_slice(&checksum.to_be_bytes());
-        result
+    /// Update the ACC (accumulator) value for next command
+    fn update_acc(&mut self) {
+        self.acc = exchange::next_acc(self.acc);
     }
 
     /// Send raw bytes to the battery
     fn send(&mut self, command: &[u8]) -> Result<()> {
-        self.port.clear(serialport::ClearBuffer::Input)?;
+        self.port.clear_input()?;
 
         if self.print_tx {
-            let debug_print: String = command
-                .iter()
-                .map(|b| format!("{:02X}", b))
-                .collect::<Vec<_>>()
-                .join(" ");
-            debug!("Sending:  {}", debug_print);
+            debug!("Sending:  {}", exchange::hex(command));
         }
 
         // Convert to MSB format (reverse bits)
-        let msb_command: Vec<u8> = command.iter().map(|&b| Self::reverse_bits(b)).collect();
-        self.port.write_all(&msb_command)?;
+        self.port.write_all(&frame::to_wire(command))?;
         Ok(())
     }
 
-    /// Send command with checksum
-    fn send_command(&mut self, command: &[u8]) -> Result<()> {
-        let command_with_checksum = Self::add_checksum(command);
-        self.send(&command_with_checksum)
+    /// Encode and send a request frame
+    fn send_command(&mut self, request: &RequestFrame) -> Result<()> {
+        self.send(&request.encode())
     }
 
-    /// Read response from battery
-    fn read_response(&mut self, expected_size: usize) -> Result<Vec<u8>> {
-        let mut msb_response = vec![0u8; 1];
+    /// Read raw bytes from the battery, converted to LSB order
+    fn read_raw(&mut self, length: usize) -> Result<Vec<u8>> {
+        let mut msb_response = vec![0u8; length];
         self.port.read_exact(&mut msb_response)?;
+        Ok(frame::to_wire(&msb_response))
+    }
 
-        if msb_response.is_empty() {
+    /// Read the reply to `request` and decode it, rejecting NACKs and unknown
+    /// headers
+    fn read_response(
+        &mut self,
+        request: &RequestFrame,
+        expected_size: usize,
+    ) -> Result<ResponseFrame> {
+        ResponseFrame::decode_reply(&self.read_frame(expected_size)?, request)
+    }
+
+    /// Read a reply frame of `expected_size` bytes, or two for a NACK
+    fn read_frame(&mut self, expected_size: usize) -> Result<Vec<u8>> {
+        let mut response = self.read_raw(1)?;
+
+        if response.is_empty() {
             return Err(M18Error::EmptyResponse);
         }
 
         // Check if we need to read more based on first byte
-        let additional_bytes = if Self::reverse_bits(msb_response[0]) == 0x82 {
-            1
-        } else {
-            expected_size - 1
-        };
-
+        let additional_bytes = exchange::remaining_reply_length(response[0], expected_size);
         if additional_bytes > 0 {
-            let mut additional = vec![0u8; additional_bytes];
-            self.port.read_exact(&mut additional)?;
-            msb_response.extend(additional);
+            response.extend(self.read_raw(additional_bytes)?);
         }
 
-        // Convert from MSB format (reverse bits)
-        let lsb_response: Vec<u8> = msb_response
-            .iter()
-            .map(|&b| Self::reverse_bits(b))
-            .collect();
-
         if self.print_rx {
-            let debug_print: String = lsb_response
-                .iter()
-                .map(|b| format!("{:02X}", b))
-                .collect::<Vec<_>>()
-                .join(" ");
-            debug!("Received: {}", debug_print);
+            debug!("Received: {}", exchange::hex(&response));
         }
 
         // Add delay to improve reliability with isolation circuits
-        thread::sleep(Duration::from_millis(50));
+        thread::sleep(self.timings.post_response_delay);
 
-        Ok(lsb_response)
+        Ok(response)
     }
 
     /// Configure battery charging parameters.
@@ -241,26 +436,25 @@ impl M18 {
     ///
     /// # Arguments
     /// * `state` - Charging state (Active or Initialization)
+    /// * `profile` - Charger current limits to announce
     ///
     /// # Returns
-    /// Battery response (5 bytes).
-    pub fn configure(&mut self, state: ChargeState) -> Result<Vec<u8>> {
-        let command = [
-            Command::Configure as u8,
-            self.acc,
-            8,
-            (CUTOFF_CURRENT >> 8) as u8,
-            (CUTOFF_CURRENT & 0xFF) as u8,
-            (MAX_CURRENT >> 8) as u8,
-            (MAX_CURRENT & 0xFF) as u8,
-            (MAX_CURRENT >> 8) as u8,
-            (MAX_CURRENT & 0xFF) as u8,
-            state as u8,
-            13,
-        ];
-        self.send_command(&command)?;
+    /// Battery response frame (no payload).
+    ///
+    /// # Errors
+    /// Returns `M18Error::ChargerProfileOutOfRange` without sending anything if
+    /// the profile fails [`ChargerProfile::validate`].
+    pub fn configure(
+        &mut self,
+        state: ChargeState,
+        profile: &ChargerProfile,
+    ) -> Result<ResponseFrame> {
+        profile.validate()?;
+        let payload = profile.encode(state);
+        let request = RequestFrame::new(Command::Configure as u8, self.acc, payload);
+        self.send_command(&request)?;
         self.update_acc();
-        self.read_response(5)
+        self.read_response(&request, 5)
     }
 
     /// Get snapshot data from battery.
@@ -268,12 +462,12 @@ impl M18 {
     /// Requests current battery state (voltage, current, temperature, etc.).
     ///
     /// # Returns
-    /// Battery response (8 bytes).
-    pub fn get_snapchat(&mut self) -> Result<Vec<u8>> {
-        let command = [Command::Snapshot as u8, self.acc, 0];
-        self.send_command(&command)?;
+    /// Decoded snapshot reply.
+    pub fn get_snapchat(&mut self) -> Result<SnapshotResponse> {
+        let request = RequestFrame::new(Command::Snapshot as u8, self.acc, vec![]);
+        self.send_command(&request)?;
         self.update_acc();
-        self.read_response(8)
+        SnapshotResponse::decode(&self.read_response(&request, 8)?)
     }
 
     /// Send keepalive message to battery.
@@ -281,11 +475,11 @@ impl M18 {
     /// Must be sent periodically during charging simulation to maintain connection.
     ///
     /// # Returns
-    /// Battery response (9 bytes) containing current state.
-    pub fn keepalive(&mut self) -> Result<Vec<u8>> {
-        let command = [Command::Keepalive as u8, self.acc, 0];
-        self.send_command(&command)?;
-        self.read_response(9)
+    /// Decoded keepalive reply containing current state.
+    pub fn keepalive(&mut self) -> Result<KeepaliveResponse> {
+        let request = RequestFrame::new(Command::Keepalive as u8, self.acc, vec![]);
+        self.send_command(&request)?;
+        KeepaliveResponse::decode(&self.read_response(&request, 9)?)
     }
 
     /// Send calibration/interrupt command to battery.
@@ -293,12 +487,12 @@ impl M18 {
     /// Purpose not fully understood, but appears in charger communication sequence.
     ///
     /// # Returns
-    /// Battery response (8 bytes).
-    pub fn calibrate(&mut self) -> Result<Vec<u8>> {
-        let command = [Command::Calibrate as u8, self.acc, 0];
-        self.send_command(&command)?;
+    /// Battery response frame (3-byte payload).
+    pub fn calibrate(&mut self) -> Result<ResponseFrame> {
+        let request = RequestFrame::new(Command::Calibrate as u8, self.acc, vec![]);
+        self.send_command(&request)?;
         self.update_acc();
-        self.read_response(8)
+        self.read_response(&request, 8)
     }
 
     /// Send custom command to battery.
@@ -312,70 +506,241 @@ impl M18 {
     /// * `length` - Number of bytes to read/write
     ///
     /// # Returns
-    /// Battery response including header and checksum.
+    /// Validated battery response frame.
     pub fn send_custom_command(
         &mut self,
         operation: MemoryOperation,
         address_high: u8,
         address_low: u8,
         length: u8,
-    ) -> Result<Vec<u8>> {
-        let cmd = [operation as u8, 0x04, 0x03, address_high, address_low, length];
-        self.send_command(&cmd)?;
-        self.read_response((length + 5) as usize)
+    ) -> Result<ResponseFrame> {
+        let request = RequestFrame::new(
+            operation as u8,
+            0x04,
+            vec![address_high, address_low, length],
+        );
+        self.send_command(&request)?;
+        self.read_response(&request, length as usize + frame::FRAME_OVERHEAD)
+    }
+
+    /// Send an arbitrary request frame and read the reply.
+    ///
+    /// The frame is sent as-is: its ACC value is not checked and the ACC
+    /// sequence is not advanced. Intended for protocol exploration.
+    ///
+    /// The reply is deliberately decoded with [`ResponseFrame::decode`] rather
+    /// than [`ResponseFrame::decode_reply`]: NACKs and replies with unknown
+    /// headers are returned as frames rather than errors, so that they can be
+    /// inspected. Only the framing, length byte and checksum are checked.
+    ///
+    /// # Arguments
+    /// * `request` - Request frame to send
+    /// * `response_len` - Expected reply length in bytes, including header and checksum
+    ///
+    /// # Returns
+    /// Validated battery response frame.
+    ///
+    /// # Errors
+    /// Returns `M18Error::InvalidRequest` if `response_len` is zero.
+    pub fn send_raw(
+        &mut self,
+        request: &RequestFrame,
+        response_len: usize,
+    ) -> Result<ResponseFrame> {
+        if response_len == 0 {
+            return Err(M18Error::InvalidRequest(
+                "reply length must be at least 1 byte".to_string(),
+            ));
+        }
+        self.send_command(request)?;
+        ResponseFrame::decode(&self.read_frame(response_len)?)
     }
 
     /// Simulate charger communication for specified duration.
     ///
     /// Mimics the behavior of a Milwaukee charger by sending the proper sequence
     /// of configuration, snapshot, and keepalive commands. Useful for testing
-    /// or keeping a battery "awake" for diagnostic purposes.
+    /// or keeping a battery "awake" for diagnostic purposes. Snapshot and
+    /// keepalive telemetry is logged as it arrives.
     ///
     /// # Arguments
     /// * `duration` - How long to simulate charging
+    /// * `profile` - Charger parameters to announce (see [`ChargerProfile`])
     ///
     /// # Returns
     /// Ok if simulation completed successfully.
-    pub fn simulate_for(&mut self, duration: Duration) -> Result<()> {
+    pub fn simulate_for(&mut self, duration: Duration, profile: &ChargerProfile) -> Result<()> {
+        self.simulate_with(duration, profile, |keepalive| {
+            info!(
+                "Keepalive: pack {:.2}V, requested current {}mA",
+                keepalive.pack_voltage, keepalive.requested_current
+            );
+        })
+    }
+
+    /// Simulate charger communication, passing each keepalive reply to a callback.
+    ///
+    /// Behaves like [`M18::simulate_for`], but hands every decoded keepalive
+    /// reply to `on_keepalive` instead of logging it.
+    ///
+    /// # Arguments
+    /// * `duration` - How long to simulate charging
+    /// * `profile` - Charger parameters to announce (see [`ChargerProfile`])
+    /// * `on_keepalive` - Called with each keepalive reply
+    ///
+    /// # Examples
+    /// ```
+    /// use m18_protocol::{emulator::VirtualBattery, ChargerProfile, M18};
+    /// use std::time::Duration;
+    ///
+    /// let battery = VirtualBattery::new();
+    /// let mut m18 = M18::with_transport(battery.transport());
+    ///
+    /// let profile = ChargerProfile::rapid();
+    /// let mut currents = Vec::new();
+    /// m18.simulate_with(Duration::from_secs(3), &profile, |keepalive| {
+    ///     currents.push(keepalive.requested_current);
+    /// })?;
+    /// assert_eq!(currents.last(), Some(&8000));
+    /// assert_eq!(battery.charger_profile(), Some(profile));
+    /// # Ok::<(), m18_protocol::M18Error>(())
+    /// ```
+    pub fn simulate_with(
+        &mut self,
+        duration: Duration,
+        profile: &ChargerProfile,
+        mut on_keepalive: impl FnMut(&KeepaliveResponse),
+    ) -> Result<()> {
+        profile.validate()?;
         info!(
             "Simulating charger communication for {} seconds...",
             duration.as_secs()
         );
         let start_time = Instant::now();
+        let mut active = false;
+
+        self.retries.start();
+        let result = self.run_charger(
+            profile,
+            |_, delay| {
+                if start_time.elapsed() >= duration {
+                    return false;
+                }
+                thread::sleep(delay);
+                true
+            },
+            |event| match event {
+                ChargeEvent::Initialized(snapshot) => debug!("Snapshot: {:?}", snapshot),
+                ChargeEvent::Active(snapshot) => {
+                    active = true;
+                    info!(
+                        "Snapshot: temperature {:.0}C, state flags {:#04x}",
+                        snapshot.temperature, snapshot.state_flags
+                    );
+                }
+                ChargeEvent::Keepalive(keepalive) => on_keepalive(&keepalive),
+                _ => {}
+            },
+        );
 
-        self.reset()?;
-        self.acc = INITIAL_ACC; // Ensure ACC starts at initial value for configure sequence
-        self.configure(ChargeState::Initialization)?;
-        self.get_snapchat()?;
-        thread::sleep(Duration::from_millis(CONFIGURE_DELAY_MS));
-        self.keepalive()?;
-        thread::sleep(Duration::from_millis(CONFIGURE_DELAY_MS)); // Additional delay before second configure
-        self.configure(ChargeState::Active)?;
-        self.get_snapchat()?;
-
-        while start_time.elapsed() < duration {
-            thread::sleep(Duration::from_millis(KEEPALIVE_INTERVAL_MS));
-            if let Err(e) = self.keepalive() {
-                warn!("Keepalive failed: {}", e);
-                break;
-            }
-        }
-
-        self.idle();
+        self.retries.log("Charger simulation");
         info!(
             "Duration: {:.2} seconds",
             start_time.elapsed().as_secs_f64()
         );
+        match result {
+            Err(e) if active => {
+                warn!("Keepalive failed: {}", e);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    /// Run the charger handshake followed by keepalives.
+    ///
+    /// `wait` is called for every delay with the interface and the time to
+    /// wait, and returns `false` to end the run. It may send further commands
+    /// while it waits, as long as it does not reset the battery.
+    ///
+    /// Progress is reported through `on_event`. J2 is returned to idle however
+    /// the run ends, and the caller reports how it ended.
+    ///
+    /// Commands are retried as the retry policy allows, and the handshake is
+    /// repeated if a retry had to reset the battery.
+    ///
+    /// # Returns
+    /// Ok once `wait` ends the run, or the error that broke the link.
+    pub(crate) fn run_charger(
+        &mut self,
+        profile: &ChargerProfile,
+        mut wait: impl FnMut(&mut M18, Duration) -> bool,
+        mut on_event: impl FnMut(ChargeEvent),
+    ) -> Result<()> {
+        let mut session = self.reset_synced()?;
+        session.acc = INITIAL_ACC; // Ensure ACC starts at initial value for configure sequence
+        if !session.charger_handshake(profile, &mut wait, &mut on_event)? {
+            return Ok(());
+        }
+
+        let keepalive_interval = session.timings.keepalive_interval;
+        let mut resyncs = session.retries.stats.resyncs;
+        while wait(&mut session, keepalive_interval) {
+            on_event(ChargeEvent::Keepalive(session.retrying(M18::keepalive)?));
+
+            // A reset ends the charge session, so announce the charger again
+            if session.retries.stats.resyncs != resyncs
+                && !session.charger_handshake(profile, &mut wait, &mut on_event)?
+            {
+                return Ok(());
+            }
+            resyncs = session.retries.stats.resyncs;
+        }
         Ok(())
     }
 
+    /// Announce the charger: configure, snapshot and keepalive, then configure
+    /// again as active.
+    ///
+    /// # Returns
+    /// `Ok(false)` if `wait` ended the run part way.
+    fn charger_handshake(
+        &mut self,
+        profile: &ChargerProfile,
+        wait: &mut impl FnMut(&mut M18, Duration) -> bool,
+        on_event: &mut impl FnMut(ChargeEvent),
+    ) -> Result<bool> {
+        for step in CHARGER_HANDSHAKE {
+            match step {
+                HandshakeStep::Configure(state) => {
+                    self.retrying(|m18| m18.configure(state, profile))?;
+                }
+                HandshakeStep::Snapshot(state) => {
+                    let snapshot = self.retrying(M18::get_snapchat)?;
+                    on_event(match state {
+                        ChargeState::Initialization => ChargeEvent::Initialized(snapshot),
+                        ChargeState::Active => ChargeEvent::Active(snapshot),
+                    });
+                }
+                HandshakeStep::Keepalive => {
+                    on_event(ChargeEvent::Keepalive(self.retrying(M18::keepalive)?));
+                }
+                HandshakeStep::Wait(delay) => {
+                    if !wait(self, delay) {
+                        return Ok(false);
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+
     /// Set J2 pin to idle state (low voltage).
     ///
     /// This is the default safe state when not communicating. The battery
     /// will power down its communication interface.
     pub fn idle(&mut self) {
-        let _ = self.port.set_break();
-        let _ = self.port.write_data_terminal_ready(true);
+        let _ = self.set_j2_low(true);
     }
 
     /// Set J2 pin to high state (~20V).
@@ -383,8 +748,7 @@ impl M18 {
     /// This powers the battery's communication interface. Required before
     /// sending commands.
     pub fn high(&mut self) {
-        let _ = self.port.clear_break();
-        let _ = self.port.write_data_terminal_ready(false);
+        let _ = self.set_j2_low(false);
     }
 
     /// Set J2 pin high for specified duration, then return to idle.
@@ -397,45 +761,6 @@ impl M18 {
         self.idle();
     }
 
-    /// Calculate temperature from ADC reading
-    fn calculate_temperature(&self, adc_value: u16) -> f64 {
-        // Constants from original implementation
-        const R1: f64 = 10e3; // 10k ohm
-        const R2: f64 = 20e3; // 20k ohm
-        const T1: f64 = 50.0; // 50°C
-        const T2: f64 = 35.0; // 35°C
-        const ADC1: f64 = 0x0180 as f64;
-        const ADC2: f64 = 0x022E as f64;
-
-        let m = (T2 - T1) / (R2 - R1);
-        let b = T1 - m * R1;
-        let resistance = R1 + (adc_value as f64 - ADC1) * (R2 - R1) / (ADC2 - ADC1);
-        let temperature = m * resistance + b;
-
-        (temperature * 100.0).round() / 100.0 // Round to 2 decimal places
-    }
-
-    /// Convert bytes to DateTime
-    fn bytes_to_datetime(&self, bytes: &[u8]) -> Result<DateTime<Utc>> {
-        if bytes.len() != 4 {
-            return Err(M18Error::Parse("Invalid date bytes length".to_string()));
-        }
-
-        let epoch_time = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
-
-        Utc.timestamp_opt(epoch_time as i64, 0)
-            .single()
-            .ok_or_else(|| M18Error::Parse("Invalid timestamp".to_string()))
-    }
-
-    /// Format duration from seconds to HH:MM:SS
-    fn format_duration(&self, seconds: u32) -> String {
-        let hours = seconds / 3600;
-        let minutes = (seconds % 3600) / 60;
-        let secs = seconds % 60;
-        format!("{:02}:{:02}:{:02}", hours, minutes, secs)
-    }
-
     /// Write a custom message to battery memory (register 0x0023).
     ///
     /// Stores up to 20 ASCII characters in the battery's user message field.
@@ -448,7 +773,8 @@ impl M18 {
     /// Ok if write succeeded.
     ///
     /// # Errors
-    /// Returns `M18Error::MessageTooLong` if message exceeds 20 characters.
+    /// Returns `M18Error::MessageTooLong` if message exceeds 20 characters, or
+    /// `M18Error::Timeout` if the battery does not answer the reset.
     pub fn write_message(&mut self, message: &str) -> Result<()> {
         if message.len() > 20 {
             return Err(M18Error::MessageTooLong {
@@ -457,202 +783,318 @@ impl M18 {
         }
 
         info!("Writing \"{}\" to memory", message);
-        self.reset()?;
+        self.retries.start();
+        let mut session = self.reset_synced()?;
 
         let padded_message = format!("{:-<20}", message);
         for (i, byte) in padded_message.bytes().enumerate() {
-            let command = [MemoryOperation::Read as u8, MemoryOperation::Write as u8, 0x03, 0x00, (0x23 + i) as u8, byte];
-            self.send_command(&command)?;
-            let _response = self.read_response(2)?;
+            let request = RequestFrame::new(
+                MemoryOperation::Read as u8,
+                MemoryOperation::Write as u8,
+                vec![0x00, (0x23 + i) as u8, byte],
+            );
+            session.retrying(|m18| {
+                m18.send_command(&request)?;
+                m18.read_response(&request, 2)
+            })?;
         }
 
+        session.retries.log("Write");
         Ok(())
     }
 
     /// Read all memory regions and return raw data.
     ///
-    /// Reads every memory region defined in DATA_MATRIX and returns the
+    /// Reads every memory region in the register map and returns the
     /// raw bytes without parsing.
     ///
     /// # Returns
     /// Vector of (address, data) tuples for each successfully read region.
     pub fn read_all_raw(&mut self) -> Result<Vec<(u16, Vec<u8>)>> {
-        let mut results = Vec::new();
-        self.reset()?;
+        Ok(self.capture_image()?.raw())
+    }
 
-        for region in DATA_MATRIX {
-            let address = (region.address_high as u16) << 8 | region.address_low as u16;
-            match self.send_custom_command(
-                MemoryOperation::Read,
-                region.address_high,
-                region.address_low,
-                region.length,
-            ) {
-                Ok(response) if response.len() >= 4 && response[0] == 0x81 => {
-                    let data = response[3..3 + region.length as usize].to_vec();
-                    results.push((address, data));
+    /// Capture every memory region into a [`MemoryImage`].
+    ///
+    /// Regions that cannot be read are recorded as failed rather than
+    /// aborting the capture.
+    ///
+    /// # Returns
+    /// Image with capture metadata, ready to be saved or analysed offline.
+    ///
+    /// # Errors
+    /// Returns `M18Error::Timeout` if the battery does not answer the reset.
+    pub fn capture_image(&mut self) -> Result<MemoryImage> {
+        let mut image = MemoryImage::with_regions(self.register_map.regions());
+        self.retries.start();
+        let mut session = self.reset_synced()?;
+
+        for region in &mut image.regions {
+            let address = region.address;
+            let length = region.length;
+            match session.retrying(|m18| {
+                m18.send_custom_command(
+                    MemoryOperation::Read,
+                    (address >> 8) as u8,
+                    (address & 0xFF) as u8,
+                    length,
+                )
+            }) {
+                Ok(response)
+                    if response.header() == frame::READ_RESPONSE_HEADER
+                        && response.payload().len() == region.length as usize =>
+                {
+                    region.data = Some(response.payload().to_vec());
                 }
                 Ok(response) => {
-                    if self.print_rx {
-                        let debug_print: String = response
-                            .iter()
-                            .map(|b| format!("{:02X}", b))
-                            .collect::<Vec<_>>()
-                            .join(" ");
+                    if session.print_rx {
                         debug!(
                             "Invalid response from: 0x{:04X} Response: {}",
-                            address, debug_print
+                            address,
+                            exchange::hex(&response.encode())
                         );
                     }
                 }
+                Err(e @ M18Error::WiringFault { .. }) => return Err(e),
                 Err(e) => {
-                    if self.print_rx {
+                    if session.print_rx {
                         debug!("Failed to read from 0x{:04X}: {}", address, e);
                     }
                 }
             }
         }
 
-        self.idle();
-        Ok(results)
+        session.retries.log("Capture");
+        Ok(image)
     }
 
-    /// Parse raw data according to register definition
-    fn parse_register_data(&self, register: &RegisterDef, data: &[u8]) -> Result<RegisterValue> {
-        if data.len() != register.length as usize {
-            return Err(M18Error::Parse(format!(
-                "Data length mismatch for register 0x{:04X}",
-                register.address
-            )));
-        }
-
-        match register.data_type {
-            DataType::UInt => {
-                let value = match data.len() {
-                    1 => data[0] as u64,
-                    2 => u16::from_be_bytes([data[0], data[1]]) as u64,
-                    4 => u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as u64,
-                    8 => u64::from_be_bytes([
-                        data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7],
-                    ]),
-                    _ => return Err(M18Error::Parse("Invalid uint length".to_string())),
-                };
-                Ok(RegisterValue::UInt(value))
-            }
-            DataType::Date => {
-                let dt = self.bytes_to_datetime(data)?;
-                Ok(RegisterValue::DateTime(dt))
-            }
-            DataType::Duration => {
-                let seconds = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
-                let formatted = self.format_duration(seconds);
-                Ok(RegisterValue::Duration(formatted))
-            }
-            DataType::Ascii => {
-                let s = String::from_utf8_lossy(data).to_string();
-                Ok(RegisterValue::String(format!("\"{}\"", s)))
-            }
-            DataType::SerialNumber => {
-                if data.len() != 5 {
-                    return Err(M18Error::Parse("Invalid serial number length".to_string()));
+    /// Probe a range of the address space, recording every read in `scan`.
+    ///
+    /// Reads already recorded in `scan` are skipped, so a scan loaded with
+    /// [`Scan::load`] carries on where it stopped. The battery is reset after
+    /// every timeout, and rests with J2 idle as the scan's
+    /// [`ScanConfig`](crate::scan::ScanConfig) asks. Reads are not retried.
+    ///
+    /// # Arguments
+    /// * `scan` - Scan to continue
+    /// * `on_probe` - Called with the scan after every read, e.g. to save progress
+    ///
+    /// # Errors
+    /// Returns `M18Error::Timeout` if the battery stops answering resets, or
+    /// the error that broke the link. The reads made until then stay in `scan`.
+    pub fn scan(&mut self, scan: &mut Scan, mut on_probe: impl FnMut(&Scan)) -> Result<()> {
+        let config = scan.config.clone();
+        let mut session = self.reset_synced()?;
+
+        for (count, (address, length)) in scan.pending().into_iter().enumerate() {
+            if count > 0 {
+                if let Some(every) = config.rest_every.filter(|&every| every > 0) {
+                    if count % every as usize == 0 {
+                        info!("Resting for {} seconds", config.rest.as_secs());
+                        drop(session);
+                        thread::sleep(config.rest);
+                        session = self.reset_synced()?;
+                    }
                 }
-                let battery_type = u16::from_be_bytes([data[0], data[1]]);
-                let serial = u32::from_be_bytes([0, data[2], data[3], data[4]]);
-                Ok(RegisterValue::SerialInfo {
-                    battery_type,
-                    serial,
-                })
-            }
-            DataType::AdcTemperature => {
-                let adc_value = u16::from_be_bytes([data[0], data[1]]);
-                let temp = self.calculate_temperature(adc_value);
-                Ok(RegisterValue::Float(temp))
+                thread::sleep(config.delay);
             }
-            DataType::DecimalTemperature => {
-                let temp = data[0] as f64 + (data[1] as f64) / 256.0;
-                Ok(RegisterValue::Float((temp * 100.0).round() / 100.0))
-            }
-            DataType::CellVoltages => {
-                if data.len() != 10 {
-                    return Err(M18Error::Parse("Invalid cell voltages length".to_string()));
-                }
-                let mut voltages = [0u16; 5];
-                for i in 0..5 {
-                    voltages[i] = u16::from_be_bytes([data[i * 2], data[i * 2 + 1]]);
+
+            let result = match session.send_custom_command(
+                MemoryOperation::Read,
+                (address >> 8) as u8,
+                (address & 0xFF) as u8,
+                length,
+            ) {
+                Ok(response)
+                    if response.header() == frame::READ_RESPONSE_HEADER
+                        && response.payload().len() == length as usize =>
+                {
+                    ProbeResult::Data {
+                        data: response.payload().to_vec(),
+                    }
                 }
-                Ok(RegisterValue::CellVoltages(voltages))
+                Ok(response) => ProbeResult::Error {
+                    message: format!("unexpected reply {:02X?}", response.encode()),
+                },
+                Err(M18Error::Nack { code, .. }) => ProbeResult::Nack { code },
+                Err(M18Error::Timeout | M18Error::EmptyResponse) => ProbeResult::Timeout,
+                Err(
+                    e @ (M18Error::Io(_) | M18Error::SerialPort(_) | M18Error::WiringFault { .. }),
+                ) => return Err(e),
+                Err(e) => ProbeResult::Error {
+                    message: e.to_string(),
+                },
+            };
+
+            let timed_out = result == ProbeResult::Timeout;
+            scan.probes.push(ScanProbe {
+                address,
+                length,
+                result,
+            });
+            on_probe(scan);
+
+            // A read the battery choked on may have left it mid-frame
+            if timed_out && !session.sync()? {
+                return Err(M18Error::Timeout);
             }
         }
+        Ok(())
     }
 
-    /// Read specific registers by ID and return parsed values.
+    /// Plan the reads needed for a set of registers.
+    ///
+    /// Returns the plan [`M18::read_registers`] would execute, for inspection
+    /// and debugging.
     ///
     /// # Arguments
-    /// * `register_ids` - Array of register IDs to read (0-183)
-    /// * `force_refresh` - If true, reads all memory regions first to ensure fresh data
+    /// * `registers` - Registers to read, as IDs (0-183) or [`Register`]s
+    /// * `force_refresh` - Plan a read of every memory region
+    ///
+    /// # Examples
+    /// ```
+    /// use m18_protocol::{emulator::VirtualBattery, M18};
+    ///
+    /// let battery = VirtualBattery::new();
+    /// let m18 = M18::with_transport(battery.transport());
+    ///
+    /// // Every register takes only a few reads
+    /// let ids: Vec<usize> = (0..m18.register_map().registers().len()).collect();
+    /// let plan = m18.plan_reads(&ids, false);
+    /// assert!(plan.reads().len() < ids.len() / 4);
+    /// ```
+    pub fn plan_reads<R: RegisterSelector>(
+        &self,
+        registers: &[R],
+        force_refresh: bool,
+    ) -> ReadPlan {
+        let ids: Vec<usize> = registers
+            .iter()
+            .filter_map(|selector| selector.resolve(&self.register_map))
+            .collect();
+
+        if force_refresh {
+            ReadPlan::covering_regions(&self.register_map, &ids, self.max_read_length)
+        } else {
+            ReadPlan::new(&self.register_map, &ids, self.max_read_length)
+        }
+    }
+
+    /// Set the longest read the planner may send.
+    ///
+    /// Defaults to [`MAX_READ_LENGTH`]. Lower it for adapters or packs that
+    /// struggle with long replies.
+    ///
+    /// # Arguments
+    /// * `length` - Maximum read length in bytes
+    pub fn set_max_read_length(&mut self, length: u8) {
+        self.max_read_length = length;
+    }
+
+    /// Read specific registers and return parsed values.
+    ///
+    /// The registers are read with as few commands as possible, following
+    /// [`M18::plan_reads`].
+    ///
+    /// # Arguments
+    /// * `registers` - Registers to read, as IDs (0-183) or [`Register`]s
+    /// * `force_refresh` - If true, reads every memory region once and takes the
+    ///   registers from those reads
     ///
     /// # Returns
-    /// Vector of (register_id, parsed_value) tuples.
-    pub fn read_registers(
+    /// Vector of (register_id, parsed_value) tuples, in the order requested.
+    /// Registers that are not in the register map or could not be read are
+    /// left out; use [`M18::read_registers_detailed`] to find out why.
+    pub fn read_registers<R: RegisterSelector>(
         &mut self,
-        register_ids: &[usize],
+        registers: &[R],
         force_refresh: bool,
     ) -> Result<Vec<(usize, RegisterValue)>> {
-        let mut results = Vec::new();
+        Ok(successful_values(
+            self.read_registers_detailed(registers, force_refresh)?,
+        ))
+    }
 
-        self.reset()?;
+    /// Read specific registers, reporting the outcome of each one.
+    ///
+    /// # Arguments
+    /// * `registers` - Registers to read, as IDs (0-183) or [`Register`]s
+    /// * `force_refresh` - If true, reads every memory region once and takes the
+    ///   registers from those reads
+    ///
+    /// # Returns
+    /// One (register_id, result) tuple per requested register in the map, in
+    /// the order requested. Failed registers carry the [`ReadFailure`] that
+    /// stopped them.
+    ///
+    /// # Errors
+    /// Returns error if the battery cannot be reset or the link shows a
+    /// wiring fault; other failed reads are reported per register.
+    ///
+    /// # Examples
+    /// ```
+    /// use m18_protocol::{emulator::VirtualBattery, ReadFailure, Register, M18};
+    ///
+    /// let battery = VirtualBattery::new();
+    /// battery.remove_memory(0x0023, 20);
+    /// let mut m18 = M18::with_transport(battery.transport());
+    ///
+    /// let results = m18.read_registers_detailed(&[Register::CellVoltages, Register::Note], false)?;
+    /// assert!(results[0].1.is_ok());
+    /// assert!(matches!(results[1].1, Err(ReadFailure::Nack { .. })));
+    /// # Ok::<(), m18_protocol::M18Error>(())
+    /// ```
+    pub fn read_registers_detailed<R: RegisterSelector>(
+        &mut self,
+        registers: &[R],
+        force_refresh: bool,
+    ) -> Result<Vec<(usize, RegisterResult)>> {
+        self.retries.start();
+        let mut session = self.reset_synced()?;
+        let results = session.read_planned(registers, force_refresh)?;
+        session.retries.log("Read");
+        Ok(results)
+    }
 
-        if force_refresh {
-            // Read all regions to refresh data
-            for region in DATA_MATRIX {
-                let _ = self.send_custom_command(
-                    MemoryOperation::Read,
-                    region.address_high,
-                    region.address_low,
-                    region.length,
-                );
-            }
-            self.idle();
-            std::thread::sleep(Duration::from_millis(100));
+    /// Plan and run the reads for a set of registers on the open link,
+    /// without resetting the battery.
+    ///
+    /// The reads do not use the ACC sequence, so they can be interleaved with
+    /// charger commands.
+    pub(crate) fn read_planned<R: RegisterSelector>(
+        &mut self,
+        registers: &[R],
+        force_refresh: bool,
+    ) -> Result<Vec<(usize, RegisterResult)>> {
+        let plan = self.plan_reads(registers, force_refresh);
+        if self.print_rx {
+            debug!("Read plan: {}", plan);
         }
 
-        self.reset()?;
-
-        for &id in register_ids {
-            if id >= self.register_defs.len() {
-                continue;
-            }
-
-            let register = self.register_defs[id].clone();
-            let address_high = ((register.address >> 8) & 0xFF) as u8;
-            let address_low = (register.address & 0xFF) as u8;
-
-            match self.send_custom_command(MemoryOperation::Read, address_high, address_low, register.length) {
-                Ok(response) if response.len() >= 4 && response[0] == 0x81 => {
-                    let data = &response[3..3 + register.length as usize];
-                    match self.parse_register_data(&register, data) {
-                        Ok(value) => results.push((id, value)),
-                        Err(e) => {
-                            if self.print_rx {
-                                debug!("Failed to parse register {}: {}", id, e);
-                            }
-                        }
-                    }
-                }
-                Ok(_) | Err(_) => {
-                    // Skip invalid responses
-                }
-            }
+        let mut replies = PlannedReplies::default();
+        for read in plan.reads() {
+            let response = match self.retrying(|m18| {
+                m18.send_custom_command(
+                    MemoryOperation::Read,
+                    (read.address >> 8) as u8,
+                    (read.address & 0xFF) as u8,
+                    read.length,
+                )
+            }) {
+                Err(e @ M18Error::WiringFault { .. }) => return Err(e),
+                response => response,
+            };
+            replies.record(read, response, self.print_rx);
         }
 
-        self.idle();
-        Ok(results)
+        let results = replies.parse(&self.register_map, &plan, self.print_rx);
+        Ok(requested_results(&self.register_map, registers, &results))
     }
 
     /// Read all 184 registers and return parsed values.
     ///
     /// # Arguments
-    /// * `force_refresh` - If true, reads all memory regions first to ensure fresh data
+    /// * `force_refresh` - If true, reads every memory region once
     ///
     /// # Returns
     /// Vector of (register_id, parsed_value) tuples for all registers.
@@ -660,107 +1102,53 @@ impl M18 {
         &mut self,
         force_refresh: bool,
     ) -> Result<Vec<(usize, RegisterValue)>> {
-        let ids: Vec<usize> = (0..self.register_defs.len()).collect();
+        let ids: Vec<usize> = (0..self.register_map.registers().len()).collect();
         self.read_registers(&ids, force_refresh)
     }
 
     /// Print register data to stdout in various formats.
     ///
     /// # Arguments
-    /// * `register_ids` - Register IDs to print (empty = all registers)
+    /// * `registers` - Registers to print, as IDs or [`Register`]s (empty = all registers)
     /// * `format` - Output format (Label, Raw, Array, or Form)
-    /// * `force_refresh` - If true, reads all memory first
-    pub fn print_registers(
+    /// * `force_refresh` - If true, reads every memory region once
+    pub fn print_registers<R: RegisterSelector>(
         &mut self,
-        register_ids: &[usize],
+        registers: &[R],
         format: OutputFormat,
         force_refresh: bool,
     ) -> Result<()> {
-        let ids = if register_ids.is_empty() {
-            (0..self.register_defs.len()).collect()
+        let ids: Vec<usize> = if registers.is_empty() {
+            (0..self.register_map.registers().len()).collect()
         } else {
-            register_ids.to_vec()
+            registers
+                .iter()
+                .filter_map(|selector| selector.resolve(&self.register_map))
+                .collect()
         };
 
         let results = self.read_registers(&ids, force_refresh)?;
-        let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
-
-        match format {
-            OutputFormat::Label => {
-                info!("{}", timestamp);
-                info!("ID  ADDR   LEN TYPE       LABEL                                   VALUE");
-                for (id, value) in results {
-                    let register = &self.register_defs[id];
-                    let type_str = format!("{:?}", register.data_type);
-                    let value_str = self.format_register_value(&value, format);
-                    info!(
-                        "{:3} 0x{:04X} {:2} {:>6}   {:<39} {:<}",
-                        id, register.address, register.length, type_str, register.label, value_str
-                    );
-                }
-            }
-            OutputFormat::Raw => {
-                info!("{}", timestamp);
-                for (_, value) in results {
-                    info!("{}", self.format_register_value(&value, format));
-                }
-            }
-            OutputFormat::Array => {
-                info!("Results as array: {:?}", results);
-            }
-            OutputFormat::Form => {
-                info!("{}", timestamp);
-                for (_, value) in results {
-                    info!("{}", self.format_register_value(&value, format));
-                }
-            }
+        for line in self.format_registers(&results, format) {
+            info!("{}", line);
         }
 
         Ok(())
     }
 
-    /// Format register value for display
-    fn format_register_value(&self, value: &RegisterValue, format: OutputFormat) -> String {
-        match (value, format) {
-            (RegisterValue::UInt(v), _) => v.to_string(),
-            (RegisterValue::Float(v), _) => format!("{:.2}", v),
-            (RegisterValue::String(s), _) => s.clone(),
-            (RegisterValue::DateTime(dt), _) => dt.format("%Y-%m-%d %H:%M:%S").to_string(),
-            (RegisterValue::Duration(d), _) => d.clone(),
-            (RegisterValue::CellVoltages(voltages), OutputFormat::Label) => {
-                format!(
-                    "1: {:4}, 2: {:4}, 3: {:4}, 4: {:4}, 5: {:4}",
-                    voltages[0], voltages[1], voltages[2], voltages[3], voltages[4]
-                )
-            }
-            (RegisterValue::CellVoltages(voltages), OutputFormat::Raw) => {
-                format!(
-                    "{:4}\n{:4}\n{:4}\n{:4}\n{:4}",
-                    voltages[0], voltages[1], voltages[2], voltages[3], voltages[4]
-                )
-            }
-            (RegisterValue::CellVoltages(voltages), _) => {
-                format!("{:?}", voltages)
-            }
-            (
-                RegisterValue::SerialInfo {
-                    battery_type,
-                    serial,
-                },
-                OutputFormat::Raw,
-            ) => {
-                format!("{}\n{}", battery_type, serial)
-            }
-            (
-                RegisterValue::SerialInfo {
-                    battery_type,
-                    serial,
-                },
-                _,
-            ) => {
-                format!("Type: {:3}, Serial: {}", battery_type, serial)
-            }
-        }
+    /// Format register values as lines of text.
+    ///
+    /// Produces the same output as [`M18::print_registers`], so values read
+    /// with [`M18::read_registers`] can be written somewhere other than the log.
+    ///
+    /// # Arguments
+    /// * `results` - Register values as returned by `read_registers`
+    /// * `format` - Output format (Label, Raw, Array, or Form)
+    pub fn format_registers(
+        &self,
+        results: &[(usize, RegisterValue)],
+        format: OutputFormat,
+    ) -> Vec<String> {
+        format_register_lines(self.register_map.registers(), results, format)
     }
 
     /// Generate a comprehensive health report.
@@ -772,6 +1160,9 @@ impl M18 {
     /// # Returns
     /// A HealthReport struct containing all diagnostic information.
     ///
+    /// Fields whose registers could not be read are `None` and shown as
+    /// "unknown".
+    ///
     /// # Errors
     /// Returns error if battery communication fails or required data cannot be read.
     ///
@@ -782,333 +1173,574 @@ impl M18 {
     /// let mut m18 = M18::new("/dev/ttyUSB0")?;
     /// let report = m18.health_report()?;
     /// println!("Battery voltage: {:.2}V", report.pack_voltage);
-    /// println!("Total cycles: {:.2}", report.usage_stats.total_discharge_cycles);
+    /// if let Some(cycles) = report.usage_stats.total_discharge_cycles {
+    ///     println!("Total cycles: {:.2}", cycles);
+    /// }
     /// # Ok::<(), m18_protocol::M18Error>(())
     /// ```
     pub fn health_report(&mut self) -> Result<HealthReport> {
-        info!("Reading battery. This will take 5-10sec");
-
-        // Define the register IDs needed for health report
-        let reg_list = vec![
-            4,  // Manufacture date
-            28, // Days since first charge
-            25, // Days since last tool use (corrected for current time)
-            26, // Days since last charge (corrected for current time)
-            12, // Voltages and imbalance
-            13, // temp (non-forge)
-            18, // temp (forge)
-            29, // Total discharge (Ah)
-            39, // Discharged to empty (count)
-            40, // Overheat events
-            41, // Overcurrent events
-            42, // Low-voltage events
-            43, // Low-voltage bounce
-            33, 32, 31, // Redlink, dumb, total charge count
-            35, // Total charge time
-            36, // Time idling on charger
-            38, // Low-voltage charges (any cell <2.5V)
-            8,  // System date
-            2,  // type & serial
-        ];
-
-        // Add discharge histogram registers (44-63 for 10-20A through 200A+)
-        let mut full_reg_list = reg_list;
-        full_reg_list.extend(44..=63);
-
-        let results = self.read_registers(&full_reg_list, true)?;
-        let values: HashMap<usize, RegisterValue> = results.into_iter().collect();
-
-        // Extract battery info
-        let (battery_type, electronic_serial) = if let Some(RegisterValue::SerialInfo {
-            battery_type,
-            serial,
-        }) = values.get(&2)
-        {
-            (*battery_type, *serial)
-        } else {
-            return Err(M18Error::Parse(
-                "Could not read battery serial info".to_string(),
-            ));
-        };
-
-        let battery_info = self
-            .battery_lookup
-            .get(&battery_type)
-            .cloned()
-            .unwrap_or_else(|| BatteryType {
-                capacity_ah: 0,
-                description: "Unknown".to_string(),
-            });
-
-        // Extract dates
-        let manufacture_date = if let Some(RegisterValue::DateTime(dt)) = values.get(&4) {
-            *dt
-        } else {
-            return Err(M18Error::Parse(
-                "Could not read manufacture date".to_string(),
-            ));
-        };
-
-        let system_date = if let Some(RegisterValue::DateTime(dt)) = values.get(&8) {
-            *dt
-        } else {
-            Utc::now()
-        };
+        info!("Reading battery health registers");
+
+        let results = self.read_registers_detailed(&health_report_registers(), true)?;
+        let results: HashMap<usize, RegisterResult> = results.into_iter().collect();
+        build_health_report(
+            &results,
+            &self.register_map,
+            &self.battery_lookup,
+            Utc::now(),
+        )
+    }
 
-        let last_tool_use = if let Some(RegisterValue::DateTime(dt)) = values.get(&25) {
-            *dt
-        } else {
-            system_date
-        };
+    /// Generate and print a formatted health report to stdout.
+    ///
+    /// Calls `health_report()` and displays the results in a human-readable format.
+    ///
+    /// # Returns
+    /// Ok if report generation and printing succeeded.
+    pub fn print_health_report(&mut self) -> Result<()> {
+        let report = self.health_report()?;
+        for line in report.to_string().lines() {
+            info!("{}", line);
+        }
 
-        let last_charge = if let Some(RegisterValue::DateTime(dt)) = values.get(&26) {
-            *dt
-        } else {
-            system_date
-        };
+        Ok(())
+    }
+}
 
-        // Extract cell voltages
-        let cell_voltages = if let Some(RegisterValue::CellVoltages(voltages)) = values.get(&12) {
-            *voltages
-        } else {
-            return Err(M18Error::Parse("Could not read cell voltages".to_string()));
-        };
+impl Drop for M18 {
+    /// Return J2 to idle so the battery is not left awake
+    fn drop(&mut self) {
+        self.idle();
+    }
+}
 
-        let pack_voltage = cell_voltages.iter().sum::<u16>() as f64 / 1000.0;
-        let cell_imbalance =
-            *cell_voltages.iter().max().unwrap() - *cell_voltages.iter().min().unwrap();
-
-        // Extract temperature
-        let temperature = values
-            .get(&13)
-            .or_else(|| values.get(&18))
-            .and_then(|v| match v {
-                RegisterValue::Float(temp) => Some(*temp),
-                _ => None,
-            });
+/// Format register values as lines of text for the given register definitions
+pub(crate) fn format_register_lines(
+    register_defs: &[RegisterDef],
+    results: &[(usize, RegisterValue)],
+    format: OutputFormat,
+) -> Vec<String> {
+    let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let mut lines = Vec::new();
+
+    match format {
+        OutputFormat::Label => {
+            lines.push(timestamp);
+            lines.push(
+                "ID  ADDR   LEN TYPE       LABEL                                   VALUE"
+                    .to_string(),
+            );
+            for (id, value) in results {
+                let register = &register_defs[*id];
+                let type_str = format!("{:?}", register.data_type);
+                let value_str = format_register_value(value, format);
+                lines.push(format!(
+                    "{:3} 0x{:04X} {:2} {:>6}   {:<39} {:<}",
+                    id, register.address, register.length, type_str, register.label, value_str
+                ));
+            }
+        }
+        OutputFormat::Raw | OutputFormat::Form => {
+            lines.push(timestamp);
+            for (_, value) in results {
+                lines.push(format_register_value(value, format));
+            }
+        }
+        OutputFormat::Array => {
+            lines.push(format!("Results as array: {:?}", results));
+        }
+    }
 
-        // Extract charging stats
-        let get_uint = |id: usize| -> u16 {
-            values
-                .get(&id)
-                .and_then(|v| match v {
-                    RegisterValue::UInt(val) => Some(*val as u16),
-                    _ => None,
-                })
-                .unwrap_or(0)
-        };
+    lines
+}
 
-        let get_duration = |id: usize| -> String {
-            values
-                .get(&id)
-                .and_then(|v| match v {
-                    RegisterValue::Duration(dur) => Some(dur.clone()),
-                    _ => None,
-                })
-                .unwrap_or_else(|| "00:00:00".to_string())
-        };
+/// Format register value for display
+pub(crate) fn format_register_value(value: &RegisterValue, format: OutputFormat) -> String {
+    match (value, format) {
+        (RegisterValue::UInt(v), _) => v.to_string(),
+        (RegisterValue::Float(v), _) => format!("{:.2}", v),
+        (RegisterValue::String(s), _) => s.clone(),
+        (RegisterValue::DateTime(dt), _) => dt.format("%Y-%m-%d %H:%M:%S").to_string(),
+        (RegisterValue::Duration(d), _) => d.clone(),
+        (RegisterValue::CellVoltages(voltages), OutputFormat::Label) => {
+            format!(
+                "1: {:4}, 2: {:4}, 3: {:4}, 4: {:4}, 5: {:4}",
+                voltages[0], voltages[1], voltages[2], voltages[3], voltages[4]
+            )
+        }
+        (RegisterValue::CellVoltages(voltages), OutputFormat::Raw) => {
+            format!(
+                "{:4}\n{:4}\n{:4}\n{:4}\n{:4}",
+                voltages[0], voltages[1], voltages[2], voltages[3], voltages[4]
+            )
+        }
+        (RegisterValue::CellVoltages(voltages), _) => {
+            format!("{:?}", voltages)
+        }
+        (
+            RegisterValue::SerialInfo {
+                battery_type,
+                serial,
+            },
+            OutputFormat::Raw,
+        ) => {
+            format!("{}\n{}", battery_type, serial)
+        }
+        (
+            RegisterValue::SerialInfo {
+                battery_type,
+                serial,
+            },
+            _,
+        ) => {
+            format!("Type: {:3}, Serial: {}", battery_type, serial)
+        }
+    }
+}
 
-        let charging_stats = ChargingStats {
-            redlink_charge_count: get_uint(33),
-            dumb_charge_count: get_uint(32),
-            total_charge_count: get_uint(31),
-            total_charge_time: get_duration(35),
-            time_idling_on_charger: get_duration(36),
-            low_voltage_charges: get_uint(38),
-        };
+/// Parse raw data according to register definition
+pub(crate) fn parse_register_data(register: &RegisterDef, data: &[u8]) -> Result<RegisterValue> {
+    if data.len() != register.length as usize {
+        return Err(M18Error::Parse(format!(
+            "Data length mismatch for register 0x{:04X}",
+            register.address
+        )));
+    }
 
-        // Extract usage stats
-        let total_discharge_amp_sec = values
-            .get(&29)
-            .and_then(|v| match v {
-                RegisterValue::UInt(val) => Some(*val),
-                _ => None,
+    match register.data_type {
+        DataType::UInt => {
+            let value = match data.len() {
+                1 => data[0] as u64,
+                2 => u16::from_be_bytes([data[0], data[1]]) as u64,
+                4 => u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as u64,
+                8 => u64::from_be_bytes([
+                    data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7],
+                ]),
+                _ => return Err(M18Error::Parse("Invalid uint length".to_string())),
+            };
+            Ok(RegisterValue::UInt(value))
+        }
+        DataType::Date => {
+            let dt = bytes_to_datetime(data)?;
+            Ok(RegisterValue::DateTime(dt))
+        }
+        DataType::Duration => {
+            let bytes: [u8; 4] = data
+                .try_into()
+                .map_err(|_| M18Error::Parse("Invalid duration length".to_string()))?;
+            let seconds = u32::from_be_bytes(bytes);
+            let formatted = format_duration(seconds);
+            Ok(RegisterValue::Duration(formatted))
+        }
+        DataType::Ascii => {
+            let s = String::from_utf8_lossy(data).to_string();
+            Ok(RegisterValue::String(format!("\"{}\"", s)))
+        }
+        DataType::SerialNumber => {
+            if data.len() != 5 {
+                return Err(M18Error::Parse("Invalid serial number length".to_string()));
+            }
+            let battery_type = u16::from_be_bytes([data[0], data[1]]);
+            let serial = u32::from_be_bytes([0, data[2], data[3], data[4]]);
+            Ok(RegisterValue::SerialInfo {
+                battery_type,
+                serial,
             })
-            .unwrap_or(0) as f64;
+        }
+        DataType::AdcTemperature => {
+            let bytes: [u8; 2] = data
+                .try_into()
+                .map_err(|_| M18Error::Parse("Invalid ADC temperature length".to_string()))?;
+            let adc_value = u16::from_be_bytes(bytes);
+            let temp = calculate_temperature(adc_value);
+            Ok(RegisterValue::Float(temp))
+        }
+        DataType::DecimalTemperature => {
+            let [whole, fraction]: [u8; 2] = data
+                .try_into()
+                .map_err(|_| M18Error::Parse("Invalid decimal temperature length".to_string()))?;
+            let temp = whole as f64 + (fraction as f64) / 256.0;
+            Ok(RegisterValue::Float((temp * 100.0).round() / 100.0))
+        }
+        DataType::CellVoltages => {
+            if data.len() != 10 {
+                return Err(M18Error::Parse("Invalid cell voltages length".to_string()));
+            }
+            let mut voltages = [0u16; 5];
+            for i in 0..5 {
+                voltages[i] = u16::from_be_bytes([data[i * 2], data[i * 2 + 1]]);
+            }
+            Ok(RegisterValue::CellVoltages(voltages))
+        }
+    }
+}
 
-        let total_discharge_ah = total_discharge_amp_sec / 3600.0;
-        let total_discharge_cycles = if battery_info.capacity_ah > 0 {
-            total_discharge_ah / (battery_info.capacity_ah as f64)
-        } else {
-            0.0
-        };
+/// Calculate temperature from ADC reading
+fn calculate_temperature(adc_value: u16) -> f64 {
+    // Constants from original implementation
+    const R1: f64 = 10e3; // 10k ohm
+    const R2: f64 = 20e3; // 20k ohm
+    const T1: f64 = 50.0; // 50°C
+    const T2: f64 = 35.0; // 35°C
+    const ADC1: f64 = 0x0180 as f64;
+    const ADC2: f64 = 0x022E as f64;
+
+    let m = (T2 - T1) / (R2 - R1);
+    let b = T1 - m * R1;
+    let resistance = R1 + (adc_value as f64 - ADC1) * (R2 - R1) / (ADC2 - ADC1);
+    let temperature = m * resistance + b;
+
+    (temperature * 100.0).round() / 100.0 // Round to 2 decimal places
+}
 
-        let usage_stats = UsageStats {
-            total_discharge_ah,
-            total_discharge_cycles,
-            times_discharged_to_empty: get_uint(39),
-            times_overheated: get_uint(40),
-            overcurrent_events: get_uint(41),
-            low_voltage_events: get_uint(42),
-            low_voltage_bounce: get_uint(43),
-            total_time_on_tool: "calculating...".to_string(), // Will be calculated below
-        };
+/// Convert bytes to DateTime
+fn bytes_to_datetime(bytes: &[u8]) -> Result<DateTime<Utc>> {
+    if bytes.len() != 4 {
+        return Err(M18Error::Parse("Invalid date bytes length".to_string()));
+    }
 
-        // Build discharge histogram
-        let mut discharge_histogram = Vec::new();
-        let mut total_tool_time = 0u32;
+    let epoch_time = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
 
-        for i in 44..=63 {
-            let time_seconds = get_uint(i) as u32;
-            total_tool_time += time_seconds;
+    Utc.timestamp_opt(epoch_time as i64, 0)
+        .single()
+        .ok_or_else(|| M18Error::Parse("Invalid timestamp".to_string()))
+}
 
-            let current_range = match i - 44 {
-                0..=18 => format!("{}-{}A", (i - 44 + 1) * 10, (i - 44 + 2) * 10),
-                19 => "> 200A".to_string(),
-                _ => continue,
-            };
+/// Format duration from seconds to HH:MM:SS
+pub(crate) fn format_duration(seconds: u32) -> String {
+    let hours = seconds / 3600;
+    let minutes = (seconds % 3600) / 60;
+    let secs = seconds % 60;
+    format!("{:02}:{:02}:{:02}", hours, minutes, secs)
+}
 
-            let duration = self.format_duration(time_seconds);
-            let percentage = if total_tool_time > 0 {
-                ((time_seconds as f64 / total_tool_time as f64) * 100.0).round() as u8
-            } else {
-                0
-            };
+/// Replies to the reads of a plan, kept until every register can be parsed.
+///
+/// A register longer than the maximum read length is split over several
+/// reads, so registers are parsed from the collected bytes once every read
+/// has been made.
+#[derive(Default)]
+pub(crate) struct PlannedReplies {
+    /// Bytes returned so far, by address
+    bytes: HashMap<u16, u8>,
+    /// First failed read covering each register
+    failures: HashMap<usize, ReadFailure>,
+}
 
-            discharge_histogram.push(DischargeHistogramEntry {
-                current_range,
-                duration,
-                percentage,
-            });
+impl PlannedReplies {
+    /// Record the outcome of one planned read
+    pub(crate) fn record(
+        &mut self,
+        read: &PlannedRead,
+        response: Result<ResponseFrame>,
+        print_rx: bool,
+    ) {
+        match check_read_response(response) {
+            Ok(response) => {
+                for (address, &byte) in (read.address..=u16::MAX).zip(response.payload()) {
+                    self.bytes.insert(address, byte);
+                }
+            }
+            Err(failure) => {
+                if print_rx {
+                    debug!("Failed to read from 0x{:04X}: {}", read.address, failure);
+                }
+                for &id in &read.registers {
+                    self.failures.entry(id).or_insert_with(|| failure.clone());
+                }
+            }
         }
+    }
 
-        // Update total time on tool in usage stats
-        let mut usage_stats = usage_stats;
-        usage_stats.total_time_on_tool = self.format_duration(total_tool_time);
-
-        // Calculate percentage for histogram entries
-        for entry in &mut discharge_histogram {
-            let time_seconds: u32 = entry
-                .duration
-                .split(':')
-                .map(|s| s.parse::<u32>().unwrap_or(0))
-                .fold(0, |acc, x| acc * 60 + x);
-
-            entry.percentage = if total_tool_time > 0 {
-                ((time_seconds as f64 / total_tool_time as f64) * 100.0).round() as u8
-            } else {
-                0
+    /// Outcome for every register covered by `plan`
+    pub(crate) fn parse(
+        &self,
+        register_map: &RegisterMap,
+        plan: &ReadPlan,
+        print_rx: bool,
+    ) -> HashMap<usize, RegisterResult> {
+        let mut results = HashMap::new();
+        for &id in plan.reads().iter().flat_map(|read| &read.registers) {
+            if results.contains_key(&id) {
+                continue;
+            }
+            let result = match self.failures.get(&id) {
+                Some(failure) => Err(failure.clone()),
+                None => {
+                    let register = &register_map.registers()[id];
+                    let data: Option<Vec<u8>> = (register.address..=u16::MAX)
+                        .take(register.length as usize)
+                        .map(|address| self.bytes.get(&address).copied())
+                        .collect();
+                    match data {
+                        Some(data) => {
+                            parse_register_data(register, &data).map_err(ReadFailure::from)
+                        }
+                        None => Err(ReadFailure::Unavailable),
+                    }
+                }
             };
+            if let (Err(e), true) = (&result, print_rx) {
+                debug!("Failed to parse register {}: {}", id, e);
+            }
+            results.insert(id, result);
         }
+        results
+    }
+}
 
-        Ok(HealthReport {
-            timestamp: Utc::now(),
-            battery_type,
-            battery_description: battery_info.description,
-            electronic_serial,
-            manufacture_date,
-            days_since_first_charge: get_uint(28),
-            days_since_last_tool_use: (system_date - last_tool_use).num_days(),
-            days_since_last_charge: (system_date - last_charge).num_days(),
-            pack_voltage,
-            cell_voltages,
-            cell_imbalance,
-            temperature,
-            charging_stats,
-            usage_stats,
-            discharge_histogram,
+/// Check that a reply answers a planned read.
+///
+/// The frame was read at the planned length and its length byte checked, so
+/// its payload is exactly as long as the read.
+fn check_read_response(
+    response: Result<ResponseFrame>,
+) -> std::result::Result<ResponseFrame, ReadFailure> {
+    let response = response?;
+    if response.header() != frame::READ_RESPONSE_HEADER {
+        Err(ReadFailure::UnexpectedResponse {
+            header: response.header(),
         })
+    } else {
+        Ok(response)
     }
+}
 
-    /// Generate and print a formatted health report to stdout.
-    ///
-    /// Calls `health_report()` and displays the results in a human-readable format.
-    ///
-    /// # Returns
-    /// Ok if report generation and printing succeeded.
-    pub fn print_health_report(&mut self) -> Result<()> {
-        let report = self.health_report()?;
+/// Results for the requested registers, in request order
+pub(crate) fn requested_results<R: RegisterSelector>(
+    register_map: &RegisterMap,
+    registers: &[R],
+    results: &HashMap<usize, RegisterResult>,
+) -> Vec<(usize, RegisterResult)> {
+    registers
+        .iter()
+        .filter_map(|selector| {
+            let id = selector.resolve(register_map)?;
+            let result = results
+                .get(&id)
+                .cloned()
+                .unwrap_or(Err(ReadFailure::Unavailable));
+            Some((id, result))
+        })
+        .collect()
+}
 
-        info!(
-            "Type: {} [{}]",
-            report.battery_type, report.battery_description
-        );
-        info!(
-            "E-serial: {} (does NOT match case serial)",
-            report.electronic_serial
-        );
-        info!("");
-        info!(
-            "Manufacture date: {}",
-            report.manufacture_date.format("%Y-%m-%d")
-        );
-        info!("Days since 1st charge: {}", report.days_since_first_charge);
-        info!(
-            "Days since last tool use: {}",
-            report.days_since_last_tool_use
-        );
-        info!("Days since last charge: {}", report.days_since_last_charge);
-        info!("Pack voltage: {:.2}V", report.pack_voltage);
-        info!("Cell Voltages (mV): {:?}", report.cell_voltages);
-        info!("Cell Imbalance (mV): {}", report.cell_imbalance);
+/// Values of the registers that were read successfully
+pub(crate) fn successful_values(
+    results: Vec<(usize, RegisterResult)>,
+) -> Vec<(usize, RegisterValue)> {
+    results
+        .into_iter()
+        .filter_map(|(id, result)| Some((id, result.ok()?)))
+        .collect()
+}
 
-        if let Some(temp) = report.temperature {
-            info!("Temperature (deg C): {:.2}", temp);
-        }
+/// Registers read to build a health report.
+pub(crate) fn health_report_registers() -> Vec<Register> {
+    let mut reg_list = vec![
+        Register::ManufactureDate,
+        Register::DaysSinceFirstCharge,
+        Register::LastToolUseDate, // corrected for current time
+        Register::LastChargeDate,  // corrected for current time
+        Register::CellVoltages,    // voltages and imbalance
+        Register::Temperature,
+        Register::ForgeTemperature,
+        Register::TotalDischargeAmpSeconds,
+        Register::DischargedToEmpty,
+        Register::OverheatEvents,
+        Register::OvercurrentEvents,
+        Register::LowVoltageEvents,
+        Register::LowVoltageBounce,
+        Register::RedlinkChargeCount,
+        Register::DumbChargeCount,
+        Register::TotalChargeCount,
+        Register::TotalChargeTime,
+        Register::TimeIdlingOnCharger,
+        Register::LowVoltageCharges, // any cell <2.5V
+        Register::SystemDate,
+        Register::SerialNumber, // type & serial
+    ];
+
+    // Add discharge histogram registers (10-20A through 200A+)
+    reg_list.extend((0..DISCHARGE_HISTOGRAM_BINS).map(Register::discharge_histogram_bin));
+    reg_list
+}
 
-        info!("");
-        info!("CHARGING STATS:");
-        info!(
-            "Charge count [Redlink, dumb, (total)]: {}, {}, ({})",
-            report.charging_stats.redlink_charge_count,
-            report.charging_stats.dumb_charge_count,
-            report.charging_stats.total_charge_count
-        );
-        info!(
-            "Total charge time: {}",
-            report.charging_stats.total_charge_time
-        );
-        info!(
-            "Time idling on charger: {}",
-            report.charging_stats.time_idling_on_charger
-        );
-        info!(
-            "Low-voltage charges (any cell <2.5V): {}",
-            report.charging_stats.low_voltage_charges
-        );
+/// Build a health report from register read results keyed by register ID.
+///
+/// Fields whose registers could not be read are left unknown. Only the
+/// battery type, manufacture date and cell voltages are required.
+///
+/// `now` stamps the report.
+pub(crate) fn build_health_report(
+    results: &HashMap<usize, RegisterResult>,
+    register_map: &RegisterMap,
+    battery_lookup: &HashMap<u16, BatteryType>,
+    now: DateTime<Utc>,
+) -> Result<HealthReport> {
+    let result = |register: Register| -> std::result::Result<&RegisterValue, ReadFailure> {
+        let id = register_map
+            .resolve(&register)
+            .ok_or(ReadFailure::Unavailable)?;
+        match results.get(&id) {
+            Some(Ok(value)) => Ok(value),
+            Some(Err(failure)) => Err(failure.clone()),
+            None => Err(ReadFailure::Unavailable),
+        }
+    };
+    let value = |register: Register| result(register).ok();
+    let required = |register: Register, name: &str| {
+        result(register)
+            .map_err(|failure| M18Error::Parse(format!("Could not read {}: {}", name, failure)))
+    };
+
+    // Extract battery info
+    let (battery_type, electronic_serial) =
+        match required(Register::SerialNumber, "battery serial info")? {
+            RegisterValue::SerialInfo {
+                battery_type,
+                serial,
+            } => (*battery_type, *serial),
+            _ => {
+                return Err(M18Error::Parse(
+                    "Could not read battery serial info".to_string(),
+                ))
+            }
+        };
 
-        info!("");
-        info!("TOOL USE STATS:");
-        info!(
-            "Total discharge (Ah): {:.2}",
-            report.usage_stats.total_discharge_ah
-        );
-        info!(
-            "Total discharge cycles: {:.2}",
-            report.usage_stats.total_discharge_cycles
-        );
-        info!(
-            "Times discharged to empty: {}",
-            report.usage_stats.times_discharged_to_empty
-        );
-        info!("Times overheated: {}", report.usage_stats.times_overheated);
-        info!(
-            "Overcurrent events: {}",
-            report.usage_stats.overcurrent_events
-        );
-        info!(
-            "Low-voltage events: {}",
-            report.usage_stats.low_voltage_events
-        );
-        info!(
-            "Low-voltage bounce/stutter: {}",
-            report.usage_stats.low_voltage_bounce
-        );
-        info!(
-            "Total time on tool (>10A): {}",
-            report.usage_stats.total_time_on_tool
-        );
+    let battery_info = battery_lookup
+        .get(&battery_type)
+        .cloned()
+        .unwrap_or_else(|| BatteryType {
+            capacity_ah: 0,
+            description: "Unknown".to_string(),
+        });
+
+    // Extract dates
+    let manufacture_date = match required(Register::ManufactureDate, "manufacture date")? {
+        RegisterValue::DateTime(dt) => *dt,
+        _ => {
+            return Err(M18Error::Parse(
+                "Could not read manufacture date".to_string(),
+            ))
+        }
+    };
 
-        info!("");
-        info!("DISCHARGE HISTOGRAM:");
-        for entry in &report.discharge_histogram {
-            let bar = "X".repeat(entry.percentage as usize);
-            info!(
-                "Time @ {:>8}: {} {:2}% {}",
-                entry.current_range, entry.duration, entry.percentage, bar
-            );
+    let get_date = |register: Register| -> Option<DateTime<Utc>> {
+        match value(register)? {
+            RegisterValue::DateTime(dt) => Some(*dt),
+            _ => None,
+        }
+    };
+    let system_date = get_date(Register::SystemDate);
+    let days_since = |register: Register| -> Option<i64> {
+        Some((system_date? - get_date(register)?).num_days())
+    };
+
+    // Extract cell voltages
+    let cell_voltages = match required(Register::CellVoltages, "cell voltages")? {
+        RegisterValue::CellVoltages(voltages) => *voltages,
+        _ => return Err(M18Error::Parse("Could not read cell voltages".to_string())),
+    };
+
+    let pack_voltage = cell_voltages.iter().sum::<u16>() as f64 / 1000.0;
+    let cell_imbalance =
+        *cell_voltages.iter().max().unwrap() - *cell_voltages.iter().min().unwrap();
+
+    // Extract temperature
+    let temperature = value(Register::Temperature)
+        .or_else(|| value(Register::ForgeTemperature))
+        .and_then(|v| match v {
+            RegisterValue::Float(temp) => Some(*temp),
+            _ => None,
+        });
+
+    // Extract charging stats
+    let get_uint = |register: Register| -> Option<u16> {
+        match value(register)? {
+            RegisterValue::UInt(val) => Some(*val as u16),
+            _ => None,
         }
+    };
 
-        Ok(())
-    }
+    let get_duration = |register: Register| -> Option<String> {
+        match value(register)? {
+            RegisterValue::Duration(dur) => Some(dur.clone()),
+            _ => None,
+        }
+    };
+
+    let charging_stats = ChargingStats {
+        redlink_charge_count: get_uint(Register::RedlinkChargeCount),
+        dumb_charge_count: get_uint(Register::DumbChargeCount),
+        total_charge_count: get_uint(Register::TotalChargeCount),
+        total_charge_time: get_duration(Register::TotalChargeTime),
+        time_idling_on_charger: get_duration(Register::TimeIdlingOnCharger),
+        low_voltage_charges: get_uint(Register::LowVoltageCharges),
+    };
+
+    // Extract usage stats
+    let total_discharge_ah = match value(Register::TotalDischargeAmpSeconds) {
+        Some(RegisterValue::UInt(amp_sec)) => Some(*amp_sec as f64 / 3600.0),
+        _ => None,
+    };
+    let total_discharge_cycles = total_discharge_ah.map(|ah| {
+        if battery_info.capacity_ah > 0 {
+            ah / (battery_info.capacity_ah as f64)
+        } else {
+            0.0
+        }
+    });
+
+    // Build discharge histogram
+    let bins: Vec<Option<u32>> = (0..DISCHARGE_HISTOGRAM_BINS)
+        .map(|bin| get_uint(Register::discharge_histogram_bin(bin)).map(u32::from))
+        .collect();
+    let last_bin = bins.len() - 1;
+    let discharge_histogram = Histogram {
+        units: "A".to_string(),
+        bins: bins
+            .into_iter()
+            .enumerate()
+            .map(|(bin, seconds)| {
+                let lower = ((bin + 1) * 10) as f64;
+                HistogramBin {
+                    lower,
+                    upper: (bin < last_bin).then_some(lower + 10.0),
+                    seconds,
+                }
+            })
+            .collect(),
+    };
+    let total_tool_time = discharge_histogram.total_seconds();
+
+    let usage_stats = UsageStats {
+        total_discharge_ah,
+        total_discharge_cycles,
+        times_discharged_to_empty: get_uint(Register::DischargedToEmpty),
+        times_overheated: get_uint(Register::OverheatEvents),
+        overcurrent_events: get_uint(Register::OvercurrentEvents),
+        low_voltage_events: get_uint(Register::LowVoltageEvents),
+        low_voltage_bounce: get_uint(Register::LowVoltageBounce),
+        total_time_on_tool: total_tool_time
+            .map(|seconds| format_duration(u32::try_from(seconds).unwrap_or(u32::MAX))),
+    };
+
+    Ok(HealthReport {
+        timestamp: now,
+        battery_type,
+        battery_description: battery_info.description,
+        electronic_serial,
+        manufacture_date,
+        days_since_first_charge: get_uint(Register::DaysSinceFirstCharge),
+        days_since_last_tool_use: days_since(Register::LastToolUseDate),
+        days_since_last_charge: days_since(Register::LastChargeDate),
+        pack_voltage,
+        cell_voltages,
+        cell_imbalance,
+        temperature,
+        charging_stats,
+        usage_stats,
+        discharge_histogram,
+    })
 }
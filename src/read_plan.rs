@@ -0,0 +1,219 @@
+//! Planning of bulk memory reads.
+//!
+//! Reading one register per command is slow: every command costs a request,
+//! a reply and a post-response delay. A [`ReadPlan`] groups the registers to
+//! be read into as few contiguous reads as possible, never longer than a
+//! maximum length and never crossing a region of the [`RegisterMap`]. Each
+//! read is sent once and the register values are sliced out of its reply; a
+//! register longer than the maximum is split over several reads and put back
+//! together from their replies.
+//!
+//! # Examples
+//! ```
+//! use m18_protocol::{read_plan::ReadPlan, Register, RegisterMap};
+//!
+//! let map = RegisterMap::embedded();
+//! let ids: Vec<usize> = [Register::TotalChargeCount, Register::DumbChargeCount]
+//!     .iter()
+//!     .filter_map(|register| map.resolve(register))
+//!     .collect();
+//!
+//! let plan = ReadPlan::new(map, &ids, 58);
+//! assert_eq!(plan.reads().len(), 1);
+//! println!("{}", plan);
+//! ```
+
+use crate::data::RegisterMap;
+use std::fmt;
+
+/// One read command and the registers it covers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedRead {
+    /// Start address of the read
+    pub address: u16,
+    /// Number of bytes to read
+    pub length: u8,
+    /// IDs of the registers the read covers, in address order, including a
+    /// register split over several reads
+    pub registers: Vec<usize>,
+}
+
+/// Ordered list of reads that covers a set of registers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReadPlan {
+    /// Reads in address order
+    reads: Vec<PlannedRead>,
+}
+
+impl ReadPlan {
+    /// Plan the fewest reads that cover the given registers.
+    ///
+    /// Registers in the same region are merged into one read, including any
+    /// bytes between them, as long as the read stays within `max_length`.
+    /// A register longer than `max_length` gets consecutive reads of its own,
+    /// each listing the register. IDs that are not in the map are ignored and
+    /// duplicates are read once.
+    ///
+    /// # Arguments
+    /// * `map` - Register map the IDs refer to
+    /// * `ids` - Register IDs to cover
+    /// * `max_length` - Longest read to plan, in bytes
+    ///
+    /// # Examples
+    /// ```
+    /// use m18_protocol::{emulator::VirtualBattery, read_plan::ReadPlan, Register, RegisterMap, M18};
+    ///
+    /// // The 10-byte cell voltage register takes three 4-byte reads
+    /// let map = RegisterMap::embedded();
+    /// let cells = map.resolve(&Register::CellVoltages).unwrap();
+    /// let plan = ReadPlan::new(map, &[cells], 4);
+    /// let lengths: Vec<u8> = plan.reads().iter().map(|read| read.length).collect();
+    /// assert_eq!(lengths, [4, 4, 2]);
+    ///
+    /// // Its value is put back together from the three replies
+    /// let battery = VirtualBattery::new();
+    /// let mut m18 = M18::with_transport(battery.transport());
+    /// let whole = m18.read_registers(&[Register::CellVoltages], false)?;
+    /// m18.set_max_read_length(4);
+    /// let split = m18.read_registers(&[Register::CellVoltages], false)?;
+    /// assert_eq!(format!("{:?}", split), format!("{:?}", whole));
+    /// # Ok::<(), m18_protocol::M18Error>(())
+    /// ```
+    pub fn new(map: &RegisterMap, ids: &[usize], max_length: u8) -> Self {
+        let mut spans: Vec<(usize, u32, u32, usize)> = ids
+            .iter()
+            .filter_map(|&id| {
+                let register = map.register(id)?;
+                let start = register.address as u32;
+                let end = start + register.length as u32;
+                let region = region_of(map, start, end)?;
+                Some((region, start, end, id))
+            })
+            .collect();
+        spans.sort_unstable_by_key(|&(region, start, _, id)| (start, region, id));
+        spans.dedup_by_key(|&mut (_, _, _, id)| id);
+
+        let mut reads = Vec::new();
+        let mut current: Option<(usize, u32, u32, Vec<usize>)> = None;
+
+        for (region, start, end, id) in spans {
+            if end - start > max_length as u32 {
+                // Too long for one read: give the register reads of its own
+                reads.extend(current.take());
+                let step = max_length.max(1) as u32;
+                for part in (start..end).step_by(step as usize) {
+                    reads.push((region, part, (part + step).min(end), vec![id]));
+                }
+                continue;
+            }
+            if let Some((read_region, read_start, read_end, registers)) = &mut current {
+                if *read_region == region && end - *read_start <= max_length as u32 {
+                    *read_end = (*read_end).max(end);
+                    registers.push(id);
+                    continue;
+                }
+            }
+            if let Some(read) = current.take() {
+                reads.push(read);
+            }
+            current = Some((region, start, end, vec![id]));
+        }
+        reads.extend(current);
+
+        ReadPlan {
+            reads: reads
+                .into_iter()
+                .map(|(_, start, end, registers)| PlannedRead {
+                    address: start as u16,
+                    length: (end - start) as u8,
+                    registers,
+                })
+                .collect(),
+        }
+    }
+
+    /// Plan reads of every region in the map, covering the given registers.
+    ///
+    /// Used to refresh the whole memory in a single pass: each region is read
+    /// once, whole if it fits in `max_length`, and the requested registers are
+    /// taken from those reads. Empty regions are skipped.
+    ///
+    /// # Arguments
+    /// * `map` - Register map the IDs refer to
+    /// * `ids` - Register IDs to cover
+    /// * `max_length` - Longest read to plan, in bytes
+    pub fn covering_regions(map: &RegisterMap, ids: &[usize], max_length: u8) -> Self {
+        let minimal = ReadPlan::new(map, ids, max_length);
+        let mut reads = Vec::new();
+
+        for region in map.regions() {
+            let start = region.address() as u32;
+            let end = start + region.length as u32;
+            let inside = |read: &&PlannedRead| {
+                let address = read.address as u32;
+                start <= address && address < end
+            };
+
+            if region.length > max_length {
+                reads.extend(minimal.reads.iter().filter(inside).cloned());
+            } else if region.length > 0 {
+                reads.push(PlannedRead {
+                    address: region.address(),
+                    length: region.length,
+                    registers: minimal
+                        .reads
+                        .iter()
+                        .filter(inside)
+                        .flat_map(|read| read.registers.iter().copied())
+                        .collect(),
+                });
+            }
+        }
+
+        ReadPlan { reads }
+    }
+
+    /// Reads in the order they are sent.
+    pub fn reads(&self) -> &[PlannedRead] {
+        &self.reads
+    }
+
+    /// Total number of bytes the plan reads.
+    pub fn total_bytes(&self) -> usize {
+        self.reads.iter().map(|read| read.length as usize).sum()
+    }
+}
+
+impl fmt::Display for ReadPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} reads, {} bytes",
+            self.reads.len(),
+            self.total_bytes()
+        )?;
+        for read in &self.reads {
+            let ids: Vec<String> = read.registers.iter().map(|id| id.to_string()).collect();
+            writeln!(
+                f,
+                "0x{:04X} {:3} bytes  registers {}",
+                read.address,
+                read.length,
+                if ids.is_empty() {
+                    "-".to_string()
+                } else {
+                    ids.join(", ")
+                }
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Index of the region holding the bytes `start..end`
+fn region_of(map: &RegisterMap, start: u32, end: u32) -> Option<usize> {
+    map.regions().iter().position(|region| {
+        let region_start = region.address() as u32;
+        region_start <= start && end <= region_start + region.length as u32
+    })
+}
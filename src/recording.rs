@@ -0,0 +1,498 @@
+//! Recording and replay of protocol sessions.
+//!
+//! [`M18::start_recording`](crate::M18::start_recording) logs every byte sent
+//! to and received from the battery, along with J2 line-control changes, to a
+//! text file. [`Recording::load`] reads such a file back, and
+//! [`Recording::replay`] turns it into a [`ReplayTransport`] that answers the
+//! same requests offline, so reads and health reports can be repeated without
+//! the pack.
+//!
+//! # Format
+//!
+//! Recordings are UTF-8 text, one entry per line. The first line is a header:
+//!
+//! ```text
+//! M18REC 1 2026-10-16T09:30:00Z
+//! ```
+//!
+//! holding the magic word, the format version and the time recording started
+//! (RFC 3339, UTC). Every following line is an entry of the form
+//! `<milliseconds> <KIND> [arguments]`, where the timestamp counts from the
+//! start of the recording:
+//!
+//! | Kind      | Arguments             | Meaning                                       |
+//! |-----------|-----------------------|-----------------------------------------------|
+//! | `TX`      | hex bytes             | Bytes written to the battery                  |
+//! | `RX`      | hex bytes             | Bytes read from the battery                   |
+//! | `TIMEOUT` | byte count            | A read of that many bytes timed out           |
+//! | `CLEAR`   |                       | Receive buffer discarded                      |
+//! | `BREAK`   | `ON` or `OFF`         | Break condition asserted or released          |
+//! | `DTR`     | `HIGH` or `LOW`       | DTR line driven to the given level            |
+//! | `RTS`     | `HIGH` or `LOW`       | RTS line driven to the given level            |
+//!
+//! Bytes are recorded exactly as they appear on the wire, i.e. bit-reversed
+//! (see [`frame::to_wire`](crate::frame::to_wire)). Blank lines and lines
+//! starting with `#` are ignored, so recordings can be annotated by hand.
+//! Readers must ignore extra arguments after the ones listed above; any other
+//! change to the format increments the version, and files with a version
+//! other than [`RECORDING_VERSION`] are rejected.
+//!
+//! # Examples
+//! ```
+//! use m18_protocol::{emulator::VirtualBattery, recording::Recording, M18};
+//!
+//! // Record a session against a battery...
+//! let battery = VirtualBattery::new();
+//! let mut m18 = M18::with_transport(battery.transport());
+//! let log = tempfile::NamedTempFile::new()?;
+//! m18.start_recording(log.path())?;
+//! let original = m18.read_all_raw()?;
+//! m18.stop_recording()?;
+//!
+//! // ...and run it again without one
+//! let mut offline = M18::with_transport(Recording::load(log.path())?.replay());
+//! assert_eq!(offline.read_all_raw()?, original);
+//! # Ok::<(), m18_protocol::M18Error>(())
+//! ```
+
+use crate::error::{M18Error, Result};
+use crate::transport::Transport;
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::warn;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Magic word at the start of every recording
+pub const RECORDING_MAGIC: &str = "M18REC";
+
+/// Version of the recording format written by this library
+pub const RECORDING_VERSION: u32 = 1;
+
+/// Single event in a recording.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordedEvent {
+    /// Bytes written to the battery (wire order)
+    Tx(Vec<u8>),
+    /// Bytes read from the battery (wire order)
+    Rx(Vec<u8>),
+    /// A read of this many bytes timed out
+    Timeout(usize),
+    /// Receive buffer discarded
+    ClearInput,
+    /// Break condition asserted (`true`) or released (`false`)
+    Break(bool),
+    /// DTR line driven to the given level
+    Dtr(bool),
+    /// RTS line driven to the given level
+    Rts(bool),
+}
+
+/// Timestamped entry in a recording.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordEntry {
+    /// Time since the recording started
+    pub elapsed: Duration,
+    /// What happened
+    pub event: RecordedEvent,
+}
+
+impl fmt::Display for RecordEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.elapsed.as_millis())?;
+        match &self.event {
+            RecordedEvent::Tx(bytes) => write!(f, " TX{}", HexBytes(bytes)),
+            RecordedEvent::Rx(bytes) => write!(f, " RX{}", HexBytes(bytes)),
+            RecordedEvent::Timeout(wanted) => write!(f, " TIMEOUT {}", wanted),
+            RecordedEvent::ClearInput => write!(f, " CLEAR"),
+            RecordedEvent::Break(enabled) => {
+                write!(f, " BREAK {}", if *enabled { "ON" } else { "OFF" })
+            }
+            RecordedEvent::Dtr(level) => write!(f, " DTR {}", if *level { "HIGH" } else { "LOW" }),
+            RecordedEvent::Rts(level) => write!(f, " RTS {}", if *level { "HIGH" } else { "LOW" }),
+        }
+    }
+}
+
+/// Formats bytes as space-prefixed hex pairs
+struct HexBytes<'a>(&'a [u8]);
+
+impl fmt::Display for HexBytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, " {:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parsed recording of a protocol session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recording {
+    /// When recording started
+    pub started: DateTime<Utc>,
+    /// Entries in the order they happened
+    pub entries: Vec<RecordEntry>,
+}
+
+impl Recording {
+    /// Load a recording from a file.
+    ///
+    /// # Errors
+    /// Returns `M18Error::Io` if the file cannot be read, or `M18Error::Parse`
+    /// if it is not a valid recording.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parse a recording from its text form.
+    ///
+    /// # Errors
+    /// Returns `M18Error::Parse` naming the offending line if the text is not a
+    /// valid recording or uses an unsupported version.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        let (_, header) = lines
+            .next()
+            .ok_or_else(|| M18Error::Parse("Recording is empty".to_string()))?;
+        let started = parse_header(header)?;
+
+        let entries = lines
+            .map(|(number, line)| {
+                parse_entry(line)
+                    .map_err(|e| M18Error::Parse(format!("Recording line {}: {}", number, e)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Recording { started, entries })
+    }
+
+    /// Save the recording to a file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    /// Create a transport that replays this recording.
+    pub fn replay(&self) -> ReplayTransport {
+        ReplayTransport::new(self)
+    }
+}
+
+impl fmt::Display for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", format_header(&self.started))?;
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+/// Format the header line for a recording started at `started`
+fn format_header(started: &DateTime<Utc>) -> String {
+    format!(
+        "{} {} {}",
+        RECORDING_MAGIC,
+        RECORDING_VERSION,
+        started.to_rfc3339_opts(SecondsFormat::Millis, true)
+    )
+}
+
+/// Parse and check the header line
+fn parse_header(line: &str) -> Result<DateTime<Utc>> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    match fields.as_slice() {
+        [magic, version, started, ..] if *magic == RECORDING_MAGIC => {
+            if version.parse::<u32>().ok() != Some(RECORDING_VERSION) {
+                return Err(M18Error::Parse(format!(
+                    "Unsupported recording version {}",
+                    version
+                )));
+            }
+            DateTime::parse_from_rfc3339(started)
+                .map(|time| time.with_timezone(&Utc))
+                .map_err(|e| M18Error::Parse(format!("Invalid recording start time: {}", e)))
+        }
+        _ => Err(M18Error::Parse(format!(
+            "Missing {} header",
+            RECORDING_MAGIC
+        ))),
+    }
+}
+
+/// Parse a single entry line
+fn parse_entry(line: &str) -> std::result::Result<RecordEntry, String> {
+    let mut fields = line.split_whitespace();
+    let millis = fields
+        .next()
+        .and_then(|field| field.parse::<u64>().ok())
+        .ok_or("missing timestamp")?;
+    let kind = fields.next().ok_or("missing entry kind")?;
+
+    let event = match kind {
+        "TX" | "RX" => {
+            let bytes = fields
+                .map(|field| u8::from_str_radix(field, 16))
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .map_err(|e| format!("invalid byte: {}", e))?;
+            if kind == "TX" {
+                RecordedEvent::Tx(bytes)
+            } else {
+                RecordedEvent::Rx(bytes)
+            }
+        }
+        "TIMEOUT" => RecordedEvent::Timeout(
+            fields
+                .next()
+                .and_then(|field| field.parse().ok())
+                .ok_or("missing byte count")?,
+        ),
+        "CLEAR" => RecordedEvent::ClearInput,
+        "BREAK" => match fields.next() {
+            Some("ON") => RecordedEvent::Break(true),
+            Some("OFF") => RecordedEvent::Break(false),
+            _ => return Err("expected ON or OFF".to_string()),
+        },
+        "DTR" => match fields.next() {
+            Some("HIGH") => RecordedEvent::Dtr(true),
+            Some("LOW") => RecordedEvent::Dtr(false),
+            _ => return Err("expected HIGH or LOW".to_string()),
+        },
+        "RTS" => match fields.next() {
+            Some("HIGH") => RecordedEvent::Rts(true),
+            Some("LOW") => RecordedEvent::Rts(false),
+            _ => return Err("expected HIGH or LOW".to_string()),
+        },
+        other => return Err(format!("unknown entry kind {}", other)),
+    };
+
+    Ok(RecordEntry {
+        elapsed: Duration::from_millis(millis),
+        event,
+    })
+}
+
+/// Destination of an active recording
+struct RecordSink {
+    /// Where entries are written
+    writer: Box<dyn Write + Send>,
+    /// When recording started
+    start: Instant,
+}
+
+/// Shared switch that turns recording on and off for a [`RecordingTransport`].
+#[derive(Clone, Default)]
+pub(crate) struct Recorder {
+    /// Active sink, if recording
+    sink: Arc<Mutex<Option<RecordSink>>>,
+}
+
+impl Recorder {
+    /// Start writing entries to `writer`, replacing any active recording
+    pub(crate) fn start(&self, writer: impl Write + Send + 'static) -> Result<()> {
+        let mut writer: Box<dyn Write + Send> = Box::new(writer);
+        writeln!(writer, "{}", format_header(&Utc::now()))?;
+        writer.flush()?;
+
+        let previous = lock(&self.sink).replace(RecordSink {
+            writer,
+            start: Instant::now(),
+        });
+        if let Some(mut previous) = previous {
+            previous.writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Stop recording and flush the sink
+    pub(crate) fn stop(&self) -> Result<()> {
+        if let Some(mut sink) = lock(&self.sink).take() {
+            sink.writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Append an entry if recording
+    fn record(&self, event: RecordedEvent) {
+        let mut guard = lock(&self.sink);
+        let Some(sink) = guard.as_mut() else {
+            return;
+        };
+
+        let entry = RecordEntry {
+            elapsed: sink.start.elapsed(),
+            event,
+        };
+        let written = writeln!(sink.writer, "{}", entry).and_then(|_| sink.writer.flush());
+        if let Err(e) = written {
+            // A failing disk must not break communication with the battery
+            warn!("Recording stopped: {}", e);
+            *guard = None;
+        }
+    }
+}
+
+/// Lock a mutex, ignoring poisoning
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Transport wrapper that logs traffic to a [`Recorder`] while it is active.
+pub(crate) struct RecordingTransport<T> {
+    /// Wrapped transport
+    inner: T,
+    /// Recording switch shared with the owner
+    recorder: Recorder,
+}
+
+impl<T: Transport> RecordingTransport<T> {
+    /// Wrap `inner`, logging through `recorder`
+    pub(crate) fn new(inner: T, recorder: Recorder) -> Self {
+        RecordingTransport { inner, recorder }
+    }
+}
+
+impl<T: Transport> Transport for RecordingTransport<T> {
+    fn write_all(&mut self, data: &[u8]) -> Result<()> {
+        self.recorder.record(RecordedEvent::Tx(data.to_vec()));
+        self.inner.write_all(data)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        let result = self.inner.read_exact(buf);
+        match &result {
+            Ok(()) => self.recorder.record(RecordedEvent::Rx(buf.to_vec())),
+            Err(M18Error::Timeout) => self.recorder.record(RecordedEvent::Timeout(buf.len())),
+            Err(_) => {}
+        }
+        result
+    }
+
+    fn clear_input(&mut self) -> Result<()> {
+        self.recorder.record(RecordedEvent::ClearInput);
+        self.inner.clear_input()
+    }
+
+    fn set_break(&mut self, enabled: bool) -> Result<()> {
+        self.recorder.record(RecordedEvent::Break(enabled));
+        self.inner.set_break(enabled)
+    }
+
+    fn set_dtr(&mut self, level: bool) -> Result<()> {
+        self.recorder.record(RecordedEvent::Dtr(level));
+        self.inner.set_dtr(level)
+    }
+
+    fn set_rts(&mut self, level: bool) -> Result<()> {
+        self.recorder.record(RecordedEvent::Rts(level));
+        self.inner.set_rts(level)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+}
+
+/// Transport that answers requests from a [`Recording`].
+///
+/// Each write must match the next recorded `TX` entry; the `RX` bytes recorded
+/// after it then become readable. Reads past the recorded bytes time out, as
+/// they did in the original session. Line-control calls are accepted and
+/// ignored.
+pub struct ReplayTransport {
+    /// Recorded exchanges: bytes sent, followed by the bytes received
+    exchanges: VecDeque<(Vec<u8>, Vec<u8>)>,
+    /// Bytes available to read
+    pending: VecDeque<u8>,
+    /// Number of writes replayed so far
+    position: usize,
+}
+
+impl ReplayTransport {
+    /// Create a replay transport for a recording.
+    pub fn new(recording: &Recording) -> Self {
+        let mut exchanges: VecDeque<(Vec<u8>, Vec<u8>)> = VecDeque::new();
+        for entry in &recording.entries {
+            match &entry.event {
+                RecordedEvent::Tx(bytes) => exchanges.push_back((bytes.clone(), Vec::new())),
+                RecordedEvent::Rx(bytes) => {
+                    if let Some((_, received)) = exchanges.back_mut() {
+                        received.extend_from_slice(bytes);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        ReplayTransport {
+            exchanges,
+            pending: VecDeque::new(),
+            position: 0,
+        }
+    }
+
+    /// Number of recorded writes not yet replayed.
+    pub fn remaining(&self) -> usize {
+        self.exchanges.len()
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn write_all(&mut self, data: &[u8]) -> Result<()> {
+        let Some((sent, received)) = self.exchanges.pop_front() else {
+            return Err(M18Error::ReplayMismatch {
+                position: self.position,
+                expected: "end of recording".to_string(),
+                actual: format!("{:02X?}", data),
+            });
+        };
+        if sent != data {
+            return Err(M18Error::ReplayMismatch {
+                position: self.position,
+                expected: format!("{:02X?}", sent),
+                actual: format!("{:02X?}", data),
+            });
+        }
+
+        self.position += 1;
+        self.pending = received.into();
+        Ok(())
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        if self.pending.len() < buf.len() {
+            self.pending.clear();
+            return Err(M18Error::Timeout);
+        }
+        for byte in buf.iter_mut() {
+            *byte = self.pending.pop_front().unwrap_or_default();
+        }
+        Ok(())
+    }
+
+    fn clear_input(&mut self) -> Result<()> {
+        self.pending.clear();
+        Ok(())
+    }
+
+    fn set_break(&mut self, _enabled: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_dtr(&mut self, _level: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_rts(&mut self, _level: bool) -> Result<()> {
+        Ok(())
+    }
+}
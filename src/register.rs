@@ -0,0 +1,259 @@
+//! Symbolic register names.
+//!
+//! Register IDs are positions in a [`RegisterMap`] and shift when registers
+//! are added to the map. A [`Register`] names a register by its address
+//! instead, so it keeps pointing at the same value whichever map is in use.
+//! Anything implementing [`RegisterSelector`], including plain IDs, can be
+//! passed to [`M18::read_registers`](crate::M18::read_registers).
+//!
+//! # Examples
+//! ```
+//! use m18_protocol::{emulator::VirtualBattery, Register, RegisterValue, M18};
+//!
+//! let battery = VirtualBattery::new();
+//! let mut m18 = M18::with_transport(battery.transport());
+//!
+//! let values = m18.read_registers(&[Register::CellVoltages, Register::Note], false)?;
+//! assert!(matches!(values[0].1, RegisterValue::CellVoltages(_)));
+//!
+//! // Registers without a name are selected by address
+//! let values = m18.read_registers(&[Register::Address(0x9142)], false)?;
+//! assert_eq!(values.len(), 1);
+//! # Ok::<(), m18_protocol::M18Error>(())
+//! ```
+
+use crate::data::RegisterMap;
+use crate::error::M18Error;
+use std::{fmt, str::FromStr};
+
+/// Address of the first bin of the 10A discharge histogram
+const DISCHARGE_HISTOGRAM_ADDRESS: u16 = 0x903A;
+
+/// Number of bins in the 10A discharge histogram (10-20A through 200A+)
+pub const DISCHARGE_HISTOGRAM_BINS: u8 = 20;
+
+/// Battery register identified by address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Register {
+    /// Cell type code
+    CellType,
+    /// Battery type and electronic serial number
+    SerialNumber,
+    /// Manufacture date
+    ManufactureDate,
+    /// Date of first charge as recorded by Forge packs
+    ForgeFirstChargeDate,
+    /// Date of last charge as recorded by Forge packs
+    ForgeLastChargeDate,
+    /// User-writable 20-character note
+    Note,
+    /// Battery clock
+    SystemDate,
+    /// Five cell voltages
+    CellVoltages,
+    /// Pack temperature (non-Forge packs)
+    Temperature,
+    /// Pack temperature (Forge packs)
+    ForgeTemperature,
+    /// Date of first charge
+    FirstChargeDate,
+    /// Date of last use in a tool
+    LastToolUseDate,
+    /// Date of last charge
+    LastChargeDate,
+    /// Days since first charge
+    DaysSinceFirstCharge,
+    /// Lifetime discharge in amp-seconds
+    TotalDischargeAmpSeconds,
+    /// Lifetime discharge in joules
+    TotalDischargeJoules,
+    /// Total charge count
+    TotalChargeCount,
+    /// Charges on chargers that do not talk to the pack
+    DumbChargeCount,
+    /// Charges on RedLink (UART) chargers
+    RedlinkChargeCount,
+    /// Completed charge count
+    CompletedChargeCount,
+    /// Total time spent charging
+    TotalChargeTime,
+    /// Time spent on a charger while full
+    TimeIdlingOnCharger,
+    /// Charges started with a cell below 2.5V
+    LowVoltageCharges,
+    /// Times discharged to empty
+    DischargedToEmpty,
+    /// Overheat events in a tool
+    OverheatEvents,
+    /// Overcurrent events
+    OvercurrentEvents,
+    /// Low-voltage events
+    LowVoltageEvents,
+    /// Low-voltage bounce events (4 flashing LEDs)
+    LowVoltageBounce,
+    /// Any register, by start address
+    Address(u16),
+}
+
+impl Register {
+    /// Every named register, in address order.
+    pub const NAMED: &'static [Register] = &[
+        Register::CellType,
+        Register::SerialNumber,
+        Register::ManufactureDate,
+        Register::ForgeFirstChargeDate,
+        Register::ForgeLastChargeDate,
+        Register::Note,
+        Register::SystemDate,
+        Register::CellVoltages,
+        Register::Temperature,
+        Register::ForgeTemperature,
+        Register::FirstChargeDate,
+        Register::LastToolUseDate,
+        Register::LastChargeDate,
+        Register::DaysSinceFirstCharge,
+        Register::TotalDischargeAmpSeconds,
+        Register::TotalDischargeJoules,
+        Register::TotalChargeCount,
+        Register::DumbChargeCount,
+        Register::RedlinkChargeCount,
+        Register::CompletedChargeCount,
+        Register::TotalChargeTime,
+        Register::TimeIdlingOnCharger,
+        Register::LowVoltageCharges,
+        Register::DischargedToEmpty,
+        Register::OverheatEvents,
+        Register::OvercurrentEvents,
+        Register::LowVoltageEvents,
+        Register::LowVoltageBounce,
+    ];
+
+    /// Start address of the register.
+    pub fn address(self) -> u16 {
+        match self {
+            Register::CellType => 0x0000,
+            Register::SerialNumber => 0x0004,
+            Register::ManufactureDate => 0x0011,
+            Register::ForgeFirstChargeDate => 0x0015,
+            Register::ForgeLastChargeDate => 0x0019,
+            Register::Note => 0x0023,
+            Register::SystemDate => 0x0037,
+            Register::CellVoltages => 0x400A,
+            Register::Temperature => 0x4014,
+            Register::ForgeTemperature => 0x401F,
+            Register::FirstChargeDate => 0x9000,
+            Register::LastToolUseDate => 0x9004,
+            Register::LastChargeDate => 0x9008,
+            Register::DaysSinceFirstCharge => 0x9010,
+            Register::TotalDischargeAmpSeconds => 0x9012,
+            Register::TotalDischargeJoules => 0x9016,
+            Register::TotalChargeCount => 0x901A,
+            Register::DumbChargeCount => 0x901E,
+            Register::RedlinkChargeCount => 0x9020,
+            Register::CompletedChargeCount => 0x9022,
+            Register::TotalChargeTime => 0x9024,
+            Register::TimeIdlingOnCharger => 0x9028,
+            Register::LowVoltageCharges => 0x902E,
+            Register::DischargedToEmpty => 0x9030,
+            Register::OverheatEvents => 0x9032,
+            Register::OvercurrentEvents => 0x9034,
+            Register::LowVoltageEvents => 0x9036,
+            Register::LowVoltageBounce => 0x9038,
+            Register::Address(address) => address,
+        }
+    }
+
+    /// Snake-case name used by [`FromStr`], or `None` for [`Register::Address`].
+    pub fn name(self) -> Option<&'static str> {
+        let name = match self {
+            Register::CellType => "cell_type",
+            Register::SerialNumber => "serial_number",
+            Register::ManufactureDate => "manufacture_date",
+            Register::ForgeFirstChargeDate => "forge_first_charge_date",
+            Register::ForgeLastChargeDate => "forge_last_charge_date",
+            Register::Note => "note",
+            Register::SystemDate => "system_date",
+            Register::CellVoltages => "cell_voltages",
+            Register::Temperature => "temperature",
+            Register::ForgeTemperature => "forge_temperature",
+            Register::FirstChargeDate => "first_charge_date",
+            Register::LastToolUseDate => "last_tool_use_date",
+            Register::LastChargeDate => "last_charge_date",
+            Register::DaysSinceFirstCharge => "days_since_first_charge",
+            Register::TotalDischargeAmpSeconds => "total_discharge_amp_seconds",
+            Register::TotalDischargeJoules => "total_discharge_joules",
+            Register::TotalChargeCount => "total_charge_count",
+            Register::DumbChargeCount => "dumb_charge_count",
+            Register::RedlinkChargeCount => "redlink_charge_count",
+            Register::CompletedChargeCount => "completed_charge_count",
+            Register::TotalChargeTime => "total_charge_time",
+            Register::TimeIdlingOnCharger => "time_idling_on_charger",
+            Register::LowVoltageCharges => "low_voltage_charges",
+            Register::DischargedToEmpty => "discharged_to_empty",
+            Register::OverheatEvents => "overheat_events",
+            Register::OvercurrentEvents => "overcurrent_events",
+            Register::LowVoltageEvents => "low_voltage_events",
+            Register::LowVoltageBounce => "low_voltage_bounce",
+            Register::Address(_) => return None,
+        };
+        Some(name)
+    }
+
+    /// Bin of the 10A discharge histogram, from 0 (10-20A) to 19 (200A+).
+    ///
+    /// # Panics
+    /// Panics if `bin` is not below [`DISCHARGE_HISTOGRAM_BINS`].
+    pub fn discharge_histogram_bin(bin: u8) -> Register {
+        assert!(bin < DISCHARGE_HISTOGRAM_BINS, "histogram bin out of range");
+        Register::Address(DISCHARGE_HISTOGRAM_ADDRESS + 2 * bin as u16)
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "0x{:04X}", self.address()),
+        }
+    }
+}
+
+impl FromStr for Register {
+    type Err = M18Error;
+
+    /// Parse a register name (`cell_voltages`) or hex address (`0x400A`).
+    fn from_str(s: &str) -> Result<Self, M18Error> {
+        if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            return u16::from_str_radix(hex, 16)
+                .map(Register::Address)
+                .map_err(|_| M18Error::Parse(format!("Invalid register address: {}", s)));
+        }
+
+        let name = s.trim().to_ascii_lowercase().replace('-', "_");
+        Register::NAMED
+            .iter()
+            .copied()
+            .find(|register| register.name() == Some(name.as_str()))
+            .ok_or_else(|| M18Error::Parse(format!("Unknown register name: {}", s)))
+    }
+}
+
+/// Way of picking a register out of a [`RegisterMap`].
+///
+/// Implemented for register IDs (`usize`) and [`Register`].
+pub trait RegisterSelector {
+    /// ID of the selected register in `map`, if it has one.
+    fn resolve(&self, map: &RegisterMap) -> Option<usize>;
+}
+
+impl RegisterSelector for usize {
+    fn resolve(&self, map: &RegisterMap) -> Option<usize> {
+        map.register(*self).map(|_| *self)
+    }
+}
+
+impl RegisterSelector for Register {
+    fn resolve(&self, map: &RegisterMap) -> Option<usize> {
+        map.find_address(self.address())
+    }
+}
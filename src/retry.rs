@@ -0,0 +1,131 @@
+//! Retry and resynchronisation of failed commands.
+//!
+//! At 4800 baud a single dropped or corrupted byte is enough to fail a
+//! command. A [`RetryPolicy`] decides whether a failed command is sent again,
+//! how long to wait first, and when to re-run the reset sequence so the ACC
+//! sequence is back in step with the battery.
+//!
+//! Failures are handled by kind:
+//!
+//! * Timeouts mean the request or its reply was lost, so it is unknown whether
+//!   the battery advanced its ACC value. By default the link is reset before
+//!   the command is retried.
+//! * Checksum errors, short frames and malformed frames mean the reply
+//!   arrived but was damaged. The battery did process the request, so the command is simply
+//!   sent again.
+//! * Anything else, such as a NACK or an I/O error, is not retried.
+//!
+//! # Examples
+//! ```
+//! use m18_protocol::{emulator::VirtualBattery, Register, RetryPolicy, M18};
+//! use std::time::Duration;
+//!
+//! let battery = VirtualBattery::new();
+//! let mut m18 = M18::with_transport(battery.transport());
+//! m18.set_retry_policy(RetryPolicy {
+//!     max_retries: 3,
+//!     backoff: Duration::from_millis(1),
+//!     ..RetryPolicy::default()
+//! });
+//!
+//! // Lose one reply and damage the next: both reads still succeed
+//! battery.drop_replies(1);
+//! battery.corrupt_replies(1);
+//! let values = m18.read_registers(&[Register::CellVoltages, Register::Note], false)?;
+//! assert_eq!(values.len(), 2);
+//!
+//! let stats = m18.retry_stats();
+//! assert_eq!(stats.retries, 2);
+//! assert_eq!(stats.resyncs, 1);
+//! # Ok::<(), m18_protocol::M18Error>(())
+//! ```
+
+use crate::error::M18Error;
+use std::fmt;
+use std::time::Duration;
+
+/// What to do after a failed command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryAction {
+    /// Give up and return the error
+    Fail,
+    /// Send the command again
+    Retry,
+    /// Reset the battery, then send the command again
+    Resync,
+}
+
+/// How failed commands are retried.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries per command before giving up
+    pub max_retries: u32,
+    /// Wait before the first retry; doubled for every further retry
+    pub backoff: Duration,
+    /// Reset the battery before retrying once this many commands in a row
+    /// have failed, whatever the failure; `None` to never force a reset
+    pub resync_after: Option<u32>,
+    /// Action for timeouts and empty replies
+    pub on_timeout: RetryAction,
+    /// Action for checksum errors, short frames and malformed replies
+    pub on_checksum_error: RetryAction,
+}
+
+impl RetryPolicy {
+    /// Policy that never retries, returning the first error.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_retries: 0,
+            backoff: Duration::ZERO,
+            resync_after: None,
+            on_timeout: RetryAction::Fail,
+            on_checksum_error: RetryAction::Fail,
+        }
+    }
+
+    /// Action for a failed command.
+    pub fn action_for(&self, error: &M18Error) -> RetryAction {
+        match error {
+            M18Error::Timeout | M18Error::EmptyResponse => self.on_timeout,
+            M18Error::ChecksumMismatch { .. }
+            | M18Error::ShortFrame { .. }
+            | M18Error::InvalidResponse { .. } => self.on_checksum_error,
+            _ => RetryAction::Fail,
+        }
+    }
+
+    /// Wait before retry number `retry` (starting at 1).
+    pub fn backoff_for(&self, retry: u32) -> Duration {
+        self.backoff
+            .saturating_mul(1 << retry.saturating_sub(1).min(16))
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Two retries with a 100ms backoff, resetting on timeouts and after two
+    /// consecutive failures.
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 2,
+            backoff: Duration::from_millis(100),
+            resync_after: Some(2),
+            on_timeout: RetryAction::Resync,
+            on_checksum_error: RetryAction::Retry,
+        }
+    }
+}
+
+/// Retries made during one operation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetryStats {
+    /// Commands sent again after a failure
+    pub retries: u32,
+    /// Resets run to resynchronise the link
+    pub resyncs: u32,
+}
+
+impl fmt::Display for RetryStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} retries, {} resyncs", self.retries, self.resyncs)
+    }
+}
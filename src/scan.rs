@@ -0,0 +1,353 @@
+//! Address-space scanner for reverse engineering.
+//!
+//! Only the regions in the register map are understood. A [`Scan`] walks a
+//! range of addresses, trying one or more read lengths at each, and records
+//! whether the battery answered with data, rejected the read with a NACK or
+//! did not answer at all. The probes give a coverage map of the address space
+//! and a [`MemoryImage`] of everything that could be read.
+//!
+//! At 4800 baud a full scan takes hours, so [`ScanConfig`] rate-limits the
+//! reads and lets the battery rest with J2 idle at intervals. A scan can be
+//! saved part way and resumed: [`M18::scan`](crate::M18::scan) skips every
+//! probe already recorded.
+//!
+//! # Examples
+//! ```
+//! use m18_protocol::scan::{Coverage, Scan, ScanConfig};
+//! use m18_protocol::{emulator::VirtualBattery, M18};
+//! use std::time::Duration;
+//!
+//! let battery = VirtualBattery::new();
+//! let mut m18 = M18::with_transport(battery.transport());
+//!
+//! let mut scan = Scan::new(ScanConfig {
+//!     start: 0x0000,
+//!     end: 0x000F,
+//!     delay: Duration::ZERO,
+//!     ..ScanConfig::default()
+//! })?;
+//! m18.scan(&mut scan, |_| {})?;
+//! assert!(scan.is_complete());
+//!
+//! // The sample image has no bytes at 0x0009-0x000C
+//! let coverage = scan.coverage();
+//! assert_eq!((coverage[0].start, coverage[0].end), (0x0000, 0x0008));
+//! assert_eq!(coverage[0].coverage, Coverage::Data);
+//! assert_eq!((coverage[1].start, coverage[1].end), (0x0009, 0x000C));
+//! assert_eq!(coverage[1].coverage, Coverage::Nack);
+//!
+//! let image = scan.to_image();
+//! assert_eq!(image.read(0x0004, 5), battery.memory(0x0004, 5).as_deref());
+//! # Ok::<(), m18_protocol::M18Error>(())
+//! ```
+
+use crate::error::{M18Error, Result};
+use crate::memory_image::{ImageRegion, MemoryImage};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+/// What to scan and how fast.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanConfig {
+    /// First address to probe
+    pub start: u16,
+    /// Last address to probe (inclusive)
+    pub end: u16,
+    /// Distance between probed addresses
+    pub step: u16,
+    /// Read lengths to try at every address, in order
+    pub lengths: Vec<u8>,
+    /// Pause between reads
+    pub delay: Duration,
+    /// Rest with J2 idle after this many reads; `None` to never rest
+    pub rest_every: Option<u32>,
+    /// Length of each rest
+    pub rest: Duration,
+}
+
+impl Default for ScanConfig {
+    /// The whole address space, one byte at a time, 50ms between reads and a
+    /// 10 second rest every 500 reads.
+    fn default() -> Self {
+        ScanConfig {
+            start: 0x0000,
+            end: 0xFFFF,
+            step: 1,
+            lengths: vec![1],
+            delay: Duration::from_millis(50),
+            rest_every: Some(500),
+            rest: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Battery's answer to one read.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum ProbeResult {
+    /// Battery returned the requested bytes
+    Data {
+        /// Bytes read
+        #[serde(with = "crate::memory_image::hex_data")]
+        data: Vec<u8>,
+    },
+    /// Battery rejected the read
+    Nack {
+        /// Error code from the NACK reply
+        code: u8,
+    },
+    /// No reply arrived
+    Timeout,
+    /// Reply was damaged or did not answer the read
+    Error {
+        /// Description of the failure
+        message: String,
+    },
+}
+
+/// One read made by a scan.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanProbe {
+    /// Address read
+    pub address: u16,
+    /// Bytes requested
+    pub length: u8,
+    /// Battery's answer
+    #[serde(flatten)]
+    pub result: ProbeResult,
+}
+
+/// What is known about an address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Coverage {
+    /// Covered by a read that returned data
+    Data,
+    /// Every read starting here was rejected
+    Nack,
+    /// A read starting here went unanswered
+    Timeout,
+    /// A read starting here got a damaged or unexpected reply
+    Error,
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Coverage::Data => "data",
+            Coverage::Nack => "nack",
+            Coverage::Timeout => "timeout",
+            Coverage::Error => "error",
+        })
+    }
+}
+
+/// Run of consecutive addresses with the same coverage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoverageRange {
+    /// First address of the run
+    pub start: u16,
+    /// Last address of the run (inclusive)
+    pub end: u16,
+    /// Coverage of every address in the run
+    pub coverage: Coverage,
+}
+
+impl fmt::Display for CoverageRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "0x{:04X}-0x{:04X} {:5} bytes  {}",
+            self.start,
+            self.end,
+            self.end as u32 - self.start as u32 + 1,
+            self.coverage
+        )
+    }
+}
+
+/// Scan of a range of the address space, complete or in progress.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Scan {
+    /// What is being scanned
+    pub config: ScanConfig,
+    /// When the scan was first started
+    pub started_at: DateTime<Utc>,
+    /// Reads made so far, in the order they were made
+    pub probes: Vec<ScanProbe>,
+}
+
+impl Scan {
+    /// Create a scan with no probes made yet.
+    ///
+    /// # Errors
+    /// Returns `M18Error::InvalidScan` if the range is empty, the step is zero
+    /// or no non-zero read length is given.
+    pub fn new(config: ScanConfig) -> Result<Self> {
+        if config.start > config.end {
+            return Err(M18Error::InvalidScan(format!(
+                "start 0x{:04X} is after end 0x{:04X}",
+                config.start, config.end
+            )));
+        }
+        if config.step == 0 {
+            return Err(M18Error::InvalidScan("step must be at least 1".to_string()));
+        }
+        if config.lengths.is_empty() || config.lengths.contains(&0) {
+            return Err(M18Error::InvalidScan(
+                "read lengths must be between 1 and 255".to_string(),
+            ));
+        }
+        Ok(Scan {
+            config,
+            started_at: Utc::now(),
+            probes: Vec::new(),
+        })
+    }
+
+    /// Reads not made yet, as `(address, length)` pairs in scan order.
+    pub fn pending(&self) -> Vec<(u16, u8)> {
+        let done: HashSet<(u16, u8)> = self
+            .probes
+            .iter()
+            .map(|probe| (probe.address, probe.length))
+            .collect();
+        let config = &self.config;
+        (config.start..=config.end)
+            .step_by(config.step as usize)
+            .flat_map(|address| config.lengths.iter().map(move |&length| (address, length)))
+            .filter(|probe| !done.contains(probe))
+            .collect()
+    }
+
+    /// Whether every read has been made.
+    pub fn is_complete(&self) -> bool {
+        self.pending().is_empty()
+    }
+
+    /// Coverage of every probed address, as runs of consecutive addresses.
+    ///
+    /// An address counts as data if any read that returned data covered it,
+    /// even one that started at an earlier address. Addresses that were never
+    /// probed or covered are left out.
+    pub fn coverage(&self) -> Vec<CoverageRange> {
+        let mut coverage = BTreeMap::new();
+        for probe in &self.probes {
+            let found = match probe.result {
+                ProbeResult::Data { .. } => continue,
+                ProbeResult::Nack { .. } => Coverage::Nack,
+                ProbeResult::Error { .. } => Coverage::Error,
+                ProbeResult::Timeout => Coverage::Timeout,
+            };
+            // Keep the worst answer: a timeout outranks an error, which outranks a NACK
+            coverage
+                .entry(probe.address)
+                .and_modify(|current: &mut Coverage| {
+                    if severity(found) > severity(*current) {
+                        *current = found;
+                    }
+                })
+                .or_insert(found);
+        }
+        for address in self.data_bytes().into_keys() {
+            coverage.insert(address, Coverage::Data);
+        }
+
+        let mut ranges: Vec<CoverageRange> = Vec::new();
+        for (address, found) in coverage {
+            match ranges.last_mut() {
+                Some(last) if last.coverage == found && last.end as u32 + 1 == address as u32 => {
+                    last.end = address;
+                }
+                _ => ranges.push(CoverageRange {
+                    start: address,
+                    end: address,
+                    coverage: found,
+                }),
+            }
+        }
+        ranges
+    }
+
+    /// Everything read so far as a memory image.
+    ///
+    /// Bytes from overlapping reads are merged, and every run of consecutive
+    /// bytes becomes a region of up to 255 bytes.
+    pub fn to_image(&self) -> MemoryImage {
+        let mut regions: Vec<ImageRegion> = Vec::new();
+        for (address, byte) in self.data_bytes() {
+            match regions.last_mut() {
+                Some(ImageRegion {
+                    address: start,
+                    length,
+                    data: Some(data),
+                }) if *length < u8::MAX && *start as u32 + *length as u32 == address as u32 => {
+                    data.push(byte);
+                    *length += 1;
+                }
+                _ => regions.push(ImageRegion {
+                    address,
+                    length: 1,
+                    data: Some(vec![byte]),
+                }),
+            }
+        }
+        MemoryImage::from_regions(regions)
+    }
+
+    /// Serialize the scan as pretty-printed JSON.
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| M18Error::Parse(format!("Failed to serialize scan: {}", e)))
+    }
+
+    /// Parse a scan from JSON.
+    ///
+    /// # Errors
+    /// Returns `M18Error::Parse` if the JSON is invalid, or
+    /// `M18Error::InvalidScan` if its configuration is.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let scan: Scan = serde_json::from_str(json)
+            .map_err(|e| M18Error::Parse(format!("Invalid scan JSON: {}", e)))?;
+        Scan::new(scan.config.clone())?;
+        Ok(scan)
+    }
+
+    /// Save the scan as JSON, e.g. to resume it later.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    /// Load a scan saved with [`Scan::save`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// Every byte read so far, by address
+    fn data_bytes(&self) -> BTreeMap<u16, u8> {
+        let mut bytes = BTreeMap::new();
+        for probe in &self.probes {
+            if let ProbeResult::Data { data } = &probe.result {
+                for (address, &byte) in (probe.address..=u16::MAX).zip(data) {
+                    bytes.insert(address, byte);
+                }
+            }
+        }
+        bytes
+    }
+}
+
+/// Rank of a failed probe when several start at the same address
+fn severity(coverage: Coverage) -> u8 {
+    match coverage {
+        Coverage::Data => 0,
+        Coverage::Nack => 1,
+        Coverage::Error => 2,
+        Coverage::Timeout => 3,
+    }
+}
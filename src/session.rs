@@ -0,0 +1,166 @@
+//! Guard that keeps the battery awake for the length of a session.
+//!
+//! [`M18::reset`] leaves J2 high at about 20V so the battery keeps listening.
+//! If that is never undone, because a command failed with `?` or the program
+//! panicked, the pack stays awake and drains. [`M18::reset`] therefore returns
+//! a [`Session`] that puts J2 back to idle when it is dropped, including
+//! during unwinding. [`M18`] itself also idles J2 when dropped.
+//!
+//! With the `async` feature, `AsyncM18::reset` returns an `AsyncSession` that
+//! does the same for the async interface. It also idles J2 when the future
+//! holding it is dropped, so a cancelled operation does not leave the battery
+//! awake.
+//!
+//! The read, write and simulate methods of [`M18`] open their own session, so
+//! a session only needs to be held when sending individual commands. Sessions
+//! nest: those methods, or [`M18::reset`] itself, can be called through an
+//! open session, and J2 stays high until the outermost session ends.
+//!
+//! # Examples
+//! ```
+//! use m18_protocol::{emulator::VirtualBattery, M18};
+//!
+//! let battery = VirtualBattery::new();
+//! let mut m18 = M18::with_transport(battery.transport());
+//!
+//! let mut session = m18.reset()?;
+//! assert!(session.is_synced());
+//! session.get_snapchat()?;
+//! assert!(battery.is_awake());
+//!
+//! // Methods that open their own session leave J2 high inside this one
+//! session.read_registers(&[12], false)?;
+//! assert!(session.reset()?.is_synced());
+//! assert!(battery.is_awake());
+//!
+//! // Leaving the session returns J2 to idle
+//! drop(session);
+//! assert!(!battery.is_awake());
+//! # Ok::<(), m18_protocol::M18Error>(())
+//! ```
+
+#[cfg(feature = "async")]
+use crate::async_protocol::AsyncM18;
+use crate::protocol::M18;
+use std::ops::{Deref, DerefMut};
+
+/// Period with J2 held high, ended by returning J2 to idle on drop.
+///
+/// Dereferences to the [`M18`] it was opened on, so every command can be sent
+/// through it. If other sessions are open on the same interface, J2 is only
+/// returned to idle when the last of them is dropped.
+pub struct Session<'a> {
+    /// Interface the session was opened on
+    m18: &'a mut M18,
+    /// Whether the battery answered the sync byte
+    pub(crate) synced: bool,
+}
+
+impl<'a> Session<'a> {
+    /// Open a session on an interface that is about to raise J2
+    pub(crate) fn new(m18: &'a mut M18) -> Self {
+        m18.open_session();
+        Session { m18, synced: false }
+    }
+
+    /// Whether the battery echoed the sync byte when the session was opened.
+    pub fn is_synced(&self) -> bool {
+        self.synced
+    }
+}
+
+impl Deref for Session<'_> {
+    type Target = M18;
+
+    fn deref(&self) -> &M18 {
+        self.m18
+    }
+}
+
+impl DerefMut for Session<'_> {
+    fn deref_mut(&mut self) -> &mut M18 {
+        self.m18
+    }
+}
+
+impl Drop for Session<'_> {
+    fn drop(&mut self) {
+        self.m18.close_session();
+    }
+}
+
+/// Period with J2 held high on an [`AsyncM18`], ended by returning J2 to idle
+/// on drop.
+///
+/// Behaves like [`Session`], including nesting.
+///
+/// # Examples
+/// ```
+/// use m18_protocol::{emulator::VirtualBattery, AsyncM18, RetryPolicy};
+///
+/// # #[tokio::main]
+/// # async fn main() -> m18_protocol::Result<()> {
+/// let battery = VirtualBattery::new();
+/// let mut m18 = AsyncM18::with_transport(battery.transport());
+///
+/// let mut session = m18.reset().await?;
+/// assert!(session.is_synced());
+/// session.get_snapchat().await?;
+/// session.read_registers(&[12], false).await?;
+/// assert!(battery.is_awake());
+///
+/// drop(session);
+/// assert!(!battery.is_awake());
+///
+/// // A failed command still leaves J2 idle
+/// m18.set_retry_policy(RetryPolicy::none());
+/// battery.drop_replies(1);
+/// assert!(m18.write_message("hello").await.is_err());
+/// assert!(!battery.is_awake());
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "async")]
+pub struct AsyncSession<'a> {
+    /// Interface the session was opened on
+    m18: &'a mut AsyncM18,
+    /// Whether the battery answered the sync byte
+    pub(crate) synced: bool,
+}
+
+#[cfg(feature = "async")]
+impl<'a> AsyncSession<'a> {
+    /// Open a session on an interface that is about to raise J2
+    pub(crate) fn new(m18: &'a mut AsyncM18) -> Self {
+        m18.open_session();
+        AsyncSession { m18, synced: false }
+    }
+
+    /// Whether the battery echoed the sync byte when the session was opened.
+    pub fn is_synced(&self) -> bool {
+        self.synced
+    }
+}
+
+#[cfg(feature = "async")]
+impl Deref for AsyncSession<'_> {
+    type Target = AsyncM18;
+
+    fn deref(&self) -> &AsyncM18 {
+        self.m18
+    }
+}
+
+#[cfg(feature = "async")]
+impl DerefMut for AsyncSession<'_> {
+    fn deref_mut(&mut self) -> &mut AsyncM18 {
+        self.m18
+    }
+}
+
+#[cfg(feature = "async")]
+impl Drop for AsyncSession<'_> {
+    fn drop(&mut self) {
+        self.m18.close_session();
+    }
+}
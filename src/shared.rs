@@ -0,0 +1,347 @@
+//! Thread-safe connection that keeps a simulated charge running.
+//!
+//! Every [`M18`] method takes `&mut self`, so one caller cannot read registers
+//! while another keeps the battery awake. A [`SharedM18`] moves the [`M18`]
+//! onto a worker thread that announces a charger and then sends a keepalive
+//! every [`KEEPALIVE_INTERVAL_MS`](crate::constants::KEEPALIVE_INTERVAL_MS)
+//! (or the interval set with
+//! [`M18Builder::keepalive_interval`](crate::M18Builder::keepalive_interval)).
+//! Requests from any clone of the handle are queued and served one at a time
+//! between keepalives, on the same link and ACC sequence, so the battery never
+//! sees a reset or an out-of-order command.
+//!
+//! A long request delays the next keepalive until it completes.
+//!
+//! # Examples
+//! ```
+//! use m18_protocol::{emulator::VirtualBattery, ChargerProfile, Register, SharedM18, M18};
+//! use std::thread;
+//!
+//! let battery = VirtualBattery::new();
+//! let m18 = M18::with_transport(battery.transport());
+//! let shared = SharedM18::start(m18, ChargerProfile::standard())?;
+//!
+//! // Read from another thread while the charge keeps running
+//! let reader = shared.clone();
+//! let values = thread::spawn(move || reader.read_registers(&[Register::CellVoltages], false))
+//!     .join()
+//!     .unwrap()?;
+//! assert_eq!(values.len(), 1);
+//!
+//! let snapshot = shared.snapshot()?;
+//! assert_eq!(snapshot.temperature, 25.0);
+//! assert!(shared.is_running());
+//!
+//! shared.stop();
+//! assert!(!battery.is_awake());
+//! # Ok::<(), m18_protocol::M18Error>(())
+//! ```
+
+use crate::charge_session::ChargeEvent;
+use crate::error::{M18Error, Result};
+use crate::protocol::{successful_values, M18};
+use crate::register::RegisterSelector;
+use crate::types::{
+    ChargerProfile, KeepaliveResponse, RegisterResult, RegisterValue, SnapshotResponse,
+};
+use log::warn;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Request run on the worker thread
+type Job = Box<dyn FnOnce(&mut M18) + Send>;
+
+/// Message to the worker thread
+enum Message {
+    /// Run a request between keepalives
+    Job(Job),
+    /// End the charge and stop the thread
+    Stop,
+}
+
+/// Cloneable handle to a connection owned by a worker thread.
+///
+/// The worker stops when [`SharedM18::stop`] is called, when the link to the
+/// battery is lost, or when the last handle is dropped. J2 is returned to
+/// idle when it stops, and requests made afterwards fail with
+/// [`M18Error::WorkerStopped`].
+///
+/// # Examples
+/// ```
+/// use m18_protocol::{emulator::VirtualBattery, ChargerProfile, M18Builder, SharedM18};
+///
+/// let battery = VirtualBattery::new();
+/// let m18 = M18Builder::from_transport(battery.transport()).build()?;
+/// let shared = SharedM18::start(m18, ChargerProfile::standard())?;
+/// let other = shared.clone();
+/// shared.snapshot()?;
+///
+/// // The charge runs until the last handle is gone
+/// drop(shared);
+/// assert!(battery.is_awake());
+/// drop(other);
+/// assert!(!battery.is_awake());
+/// # Ok::<(), m18_protocol::M18Error>(())
+/// ```
+#[derive(Clone)]
+pub struct SharedM18 {
+    /// Queue of requests for the worker thread
+    requests: Sender<Message>,
+    /// State shared by every handle
+    worker: Arc<Worker>,
+}
+
+/// Worker thread and the state it reports
+struct Worker {
+    /// Worker thread, until it has been joined
+    handle: Mutex<Option<JoinHandle<()>>>,
+    /// Most recent keepalive reply
+    latest_keepalive: Arc<Mutex<Option<KeepaliveResponse>>>,
+}
+
+impl SharedM18 {
+    /// Announce a charger and start sending keepalives on a worker thread.
+    ///
+    /// # Arguments
+    /// * `m18` - Connection to the battery
+    /// * `profile` - Charger parameters to announce
+    ///
+    /// # Errors
+    /// Returns `M18Error::ChargerProfileOutOfRange` if the profile is invalid.
+    pub fn start(mut m18: M18, profile: ChargerProfile) -> Result<Self> {
+        profile.validate()?;
+        let (request_tx, request_rx) = mpsc::channel();
+        let latest_keepalive = Arc::new(Mutex::new(None));
+
+        let keepalive_slot = latest_keepalive.clone();
+        let handle = thread::spawn(move || {
+            let result = m18.run_charger(
+                &profile,
+                |m18, delay| serve(m18, &request_rx, delay),
+                |event| {
+                    if let ChargeEvent::Keepalive(keepalive) = event {
+                        *lock(&keepalive_slot) = Some(keepalive);
+                    }
+                },
+            );
+            if let Err(e) = result {
+                warn!("Shared connection lost: {}", e);
+            }
+        });
+
+        Ok(SharedM18 {
+            requests: request_tx,
+            worker: Arc::new(Worker {
+                handle: Mutex::new(Some(handle)),
+                latest_keepalive,
+            }),
+        })
+    }
+
+    /// Read specific registers between keepalives.
+    ///
+    /// Behaves like [`M18::read_registers`], except that the battery is not
+    /// reset first.
+    ///
+    /// # Arguments
+    /// * `registers` - Registers to read, as IDs (0-183) or [`Register`](crate::Register)s
+    /// * `force_refresh` - If true, reads every memory region once
+    pub fn read_registers<R>(
+        &self,
+        registers: &[R],
+        force_refresh: bool,
+    ) -> Result<Vec<(usize, RegisterValue)>>
+    where
+        R: RegisterSelector + Clone + Send + 'static,
+    {
+        Ok(successful_values(
+            self.read_registers_detailed(registers, force_refresh)?,
+        ))
+    }
+
+    /// Read specific registers between keepalives, reporting the outcome of
+    /// each one.
+    ///
+    /// See [`M18::read_registers_detailed`].
+    pub fn read_registers_detailed<R>(
+        &self,
+        registers: &[R],
+        force_refresh: bool,
+    ) -> Result<Vec<(usize, RegisterResult)>>
+    where
+        R: RegisterSelector + Clone + Send + 'static,
+    {
+        let registers = registers.to_vec();
+        self.call(move |m18| m18.read_planned(&registers, force_refresh))
+    }
+
+    /// Request a snapshot between keepalives.
+    ///
+    /// See [`M18::get_snapchat`]. The snapshot advances the ACC sequence the
+    /// keepalives use, so the next keepalive carries the following value.
+    ///
+    /// # Examples
+    /// ```
+    /// use m18_protocol::{emulator::VirtualBattery, ChargerProfile, M18Builder, SharedM18};
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// let battery = VirtualBattery::new();
+    /// let m18 = M18Builder::from_transport(battery.transport())
+    ///     .keepalive_interval(Duration::from_millis(20))
+    ///     .build()?;
+    /// let shared = SharedM18::start(m18, ChargerProfile::standard())?;
+    ///
+    /// // The battery NACKs a keepalive with the wrong ACC value, which would
+    /// // stop the worker
+    /// for _ in 0..3 {
+    ///     shared.snapshot()?;
+    ///     let count = battery.keepalive_count();
+    ///     while battery.keepalive_count() == count && shared.is_running() {
+    ///         thread::sleep(Duration::from_millis(5));
+    ///     }
+    /// }
+    /// assert!(shared.is_running());
+    /// shared.stop();
+    /// # Ok::<(), m18_protocol::M18Error>(())
+    /// ```
+    pub fn snapshot(&self) -> Result<SnapshotResponse> {
+        self.call(|m18| m18.retrying(M18::get_snapchat))
+    }
+
+    /// Most recent keepalive reply, if one has arrived.
+    pub fn latest_keepalive(&self) -> Option<KeepaliveResponse> {
+        lock(&self.worker.latest_keepalive).clone()
+    }
+
+    /// Whether the worker thread is still running.
+    ///
+    /// Returns `false` once the connection has stopped, including after a
+    /// lost link.
+    ///
+    /// # Examples
+    /// ```
+    /// use m18_protocol::emulator::VirtualBattery;
+    /// use m18_protocol::{ChargerProfile, M18Builder, M18Error, RetryPolicy, SharedM18};
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// let battery = VirtualBattery::new();
+    /// let m18 = M18Builder::from_transport(battery.transport())
+    ///     .keepalive_interval(Duration::from_millis(20))
+    ///     .retry_policy(RetryPolicy::none())
+    ///     .build()?;
+    /// let shared = SharedM18::start(m18, ChargerProfile::standard())?;
+    /// shared.snapshot()?;
+    ///
+    /// // Lose every reply from now on
+    /// battery.drop_replies(u32::MAX);
+    /// while shared.is_running() {
+    ///     thread::sleep(Duration::from_millis(5));
+    /// }
+    /// assert!(!battery.is_awake());
+    /// assert!(matches!(shared.snapshot(), Err(M18Error::WorkerStopped)));
+    /// # Ok::<(), M18Error>(())
+    /// ```
+    pub fn is_running(&self) -> bool {
+        lock(&self.worker.handle)
+            .as_ref()
+            .is_some_and(|handle| !handle.is_finished())
+    }
+
+    /// Stop the worker and wait for it to return J2 to idle.
+    ///
+    /// Stops the connection for every handle. Called on the worker thread
+    /// itself, from inside a request, it cannot wait for its own thread: the
+    /// stop is queued and the worker stops once the request returns.
+    ///
+    /// # Examples
+    /// ```
+    /// use m18_protocol::{emulator::VirtualBattery, ChargerProfile, M18Error, SharedM18, M18};
+    ///
+    /// let battery = VirtualBattery::new();
+    /// let m18 = M18::with_transport(battery.transport());
+    /// let shared = SharedM18::start(m18, ChargerProfile::standard())?;
+    ///
+    /// shared.stop();
+    /// assert!(!shared.is_running());
+    /// assert!(!battery.is_awake());
+    /// assert!(matches!(shared.snapshot(), Err(M18Error::WorkerStopped)));
+    /// # Ok::<(), M18Error>(())
+    /// ```
+    pub fn stop(&self) {
+        let _ = self.requests.send(Message::Stop);
+        self.worker.join();
+    }
+
+    /// Queue a request and wait for its result
+    fn call<T: Send + 'static>(
+        &self,
+        request: impl FnOnce(&mut M18) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let (reply_tx, reply_rx) = mpsc::channel();
+        let job: Job = Box::new(move |m18| {
+            // The caller may have given up waiting
+            let _ = reply_tx.send(request(m18));
+        });
+        self.requests
+            .send(Message::Job(job))
+            .map_err(|_| M18Error::WorkerStopped)?;
+        reply_rx.recv().map_err(|_| M18Error::WorkerStopped)?
+    }
+}
+
+impl Worker {
+    /// Wait for the worker thread, if it has not been joined yet.
+    ///
+    /// Does nothing on the worker thread itself, which would wait forever.
+    fn join(&self) {
+        let mut slot = lock(&self.handle);
+        if slot
+            .as_ref()
+            .is_some_and(|handle| handle.thread().id() == thread::current().id())
+        {
+            return;
+        }
+        let handle = slot.take();
+        drop(slot);
+        if let Some(handle) = handle {
+            if handle.join().is_err() {
+                warn!("Shared connection worker panicked");
+            }
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            self.join();
+        }
+    }
+}
+
+/// Run queued requests until `delay` has passed.
+///
+/// Returns `false` once the worker should stop.
+fn serve(m18: &mut M18, requests: &Receiver<Message>, delay: Duration) -> bool {
+    let deadline = Instant::now() + delay;
+    loop {
+        let remaining = match deadline.checked_duration_since(Instant::now()) {
+            Some(remaining) => remaining,
+            None => return true,
+        };
+        match requests.recv_timeout(remaining) {
+            Ok(Message::Job(job)) => job(m18),
+            Ok(Message::Stop) | Err(RecvTimeoutError::Disconnected) => return false,
+            Err(RecvTimeoutError::Timeout) => return true,
+        }
+    }
+}
+
+/// Lock a mutex, ignoring poisoning
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
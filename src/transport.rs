@@ -0,0 +1,294 @@
+//! Byte transport abstraction for M18 communication.
+//!
+//! The protocol logic in [`M18`](crate::M18) only needs a way to move bytes and
+//! to drive the J2 line. This module defines that contract as the [`Transport`]
+//! trait, along with [`SerialTransport`], the implementation backed by a real
+//! serial port.
+
+use crate::constants::*;
+use crate::error::{M18Error, Result};
+use crate::exchange;
+use serialport::SerialPort;
+use std::io::{self, ErrorKind, Read, Write};
+use std::time::Duration;
+
+/// Byte-level link to a battery, including J2 line control.
+///
+/// Implementations carry raw (bit-reversed) bytes exactly as they appear on the
+/// wire; framing, checksums and bit ordering are handled by the protocol layer.
+pub trait Transport: Send {
+    /// Write all bytes to the link.
+    fn write_all(&mut self, data: &[u8]) -> Result<()>;
+
+    /// Read exactly `buf.len()` bytes from the link.
+    ///
+    /// # Errors
+    /// Returns `M18Error::Timeout` if the bytes do not arrive in time.
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()>;
+
+    /// Discard any bytes waiting in the receive buffer.
+    fn clear_input(&mut self) -> Result<()>;
+
+    /// Assert (`true`) or release (`false`) the break condition on TX.
+    fn set_break(&mut self, enabled: bool) -> Result<()>;
+
+    /// Drive the DTR line to the given level.
+    fn set_dtr(&mut self, level: bool) -> Result<()>;
+
+    /// Drive the RTS line to the given level.
+    ///
+    /// Only needed for adapters wired to drive J2 from RTS (see
+    /// [`LineControl`](crate::LineControl)).
+    ///
+    /// # Errors
+    /// The default implementation returns an `Unsupported` I/O error.
+    fn set_rts(&mut self, _level: bool) -> Result<()> {
+        Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "RTS is not supported by this transport",
+        )
+        .into())
+    }
+
+    /// Set how long [`Transport::read_exact`] may wait for bytes.
+    ///
+    /// The default implementation does nothing, for transports that never
+    /// wait or manage their own timeouts.
+    fn set_read_timeout(&mut self, _timeout: Duration) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn write_all(&mut self, data: &[u8]) -> Result<()> {
+        (**self).write_all(data)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        (**self).read_exact(buf)
+    }
+
+    fn clear_input(&mut self) -> Result<()> {
+        (**self).clear_input()
+    }
+
+    fn set_break(&mut self, enabled: bool) -> Result<()> {
+        (**self).set_break(enabled)
+    }
+
+    fn set_dtr(&mut self, level: bool) -> Result<()> {
+        (**self).set_dtr(level)
+    }
+
+    fn set_rts(&mut self, level: bool) -> Result<()> {
+        (**self).set_rts(level)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> Result<()> {
+        (**self).set_read_timeout(timeout)
+    }
+}
+
+/// Transport backed by a `serialport` UART.
+pub struct SerialTransport {
+    /// Underlying serial port
+    port: Box<dyn SerialPort>,
+    /// Whether the port supports break and modem-control lines
+    line_control: bool,
+}
+
+impl SerialTransport {
+    /// Open a serial port configured for M18 communication (4800 baud, 2 stop bits).
+    ///
+    /// Pseudo-terminals (such as the one served by
+    /// [`VirtualBattery`](crate::emulator::VirtualBattery)) have no break or
+    /// DTR line, so J2 line control is skipped when one is opened.
+    ///
+    /// # Arguments
+    /// * `port_name` - Serial port name (e.g., "COM3" on Windows, "/dev/ttyUSB0" on Linux)
+    ///
+    /// # Errors
+    /// Returns error if serial port cannot be opened or configured.
+    pub fn open(port_name: &str) -> Result<Self> {
+        Self::open_with_timeout(port_name, Duration::from_millis(TIMEOUT_MS))
+    }
+
+    /// Open a serial port like [`SerialTransport::open`], with a custom read timeout.
+    ///
+    /// # Arguments
+    /// * `port_name` - Serial port name
+    /// * `timeout` - How long a read may wait for bytes
+    ///
+    /// # Errors
+    /// Returns error if serial port cannot be opened or configured.
+    pub fn open_with_timeout(port_name: &str, timeout: Duration) -> Result<Self> {
+        let port = serialport::new(port_name, BAUD_RATE)
+            .timeout(timeout)
+            .stop_bits(STOP_BITS)
+            .open()?;
+        Ok(SerialTransport {
+            port,
+            line_control: !is_pseudo_terminal(port_name),
+        })
+    }
+
+    /// Wrap an already-opened serial port.
+    ///
+    /// The port is used as-is; the caller is responsible for its baud rate,
+    /// stop bits and timeout.
+    pub fn new(port: Box<dyn SerialPort>) -> Self {
+        SerialTransport {
+            port,
+            line_control: true,
+        }
+    }
+
+    /// Consume the transport and return the underlying serial port.
+    pub fn into_inner(self) -> Box<dyn SerialPort> {
+        self.port
+    }
+}
+
+impl Transport for SerialTransport {
+    fn write_all(&mut self, data: &[u8]) -> Result<()> {
+        self.port.write_all(data)?;
+        Ok(())
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        match self.port.read_exact(buf) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::TimedOut => Err(M18Error::Timeout),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.port.set_timeout(timeout)?;
+        Ok(())
+    }
+
+    fn clear_input(&mut self) -> Result<()> {
+        self.port.clear(serialport::ClearBuffer::Input)?;
+        Ok(())
+    }
+
+    fn set_break(&mut self, enabled: bool) -> Result<()> {
+        if !self.line_control {
+            return Ok(());
+        }
+        if enabled {
+            self.port.set_break()?;
+        } else {
+            self.port.clear_break()?;
+        }
+        Ok(())
+    }
+
+    fn set_dtr(&mut self, level: bool) -> Result<()> {
+        if !self.line_control {
+            return Ok(());
+        }
+        self.port.write_data_terminal_ready(level)?;
+        Ok(())
+    }
+
+    fn set_rts(&mut self, level: bool) -> Result<()> {
+        if !self.line_control {
+            return Ok(());
+        }
+        self.port.write_request_to_send(level)?;
+        Ok(())
+    }
+}
+
+/// Transport for single-wire links, where every transmitted byte is echoed.
+///
+/// Adapters that tie TX and RX together read back everything they send. After
+/// each write this transport reads the echo and checks it against the bytes
+/// sent, so reads only see the battery's reply.
+///
+/// # Examples
+/// ```
+/// use m18_protocol::{emulator::VirtualBattery, transport::EchoCancellingTransport, M18Error, M18};
+///
+/// let battery = VirtualBattery::new();
+/// battery.set_echo(true);
+/// let mut m18 = M18::with_transport(EchoCancellingTransport::new(battery.transport()));
+/// assert_eq!(m18.read_registers(&[12], false)?.len(), 1);
+///
+/// // Without the echo the sync reply is taken for the echo, and the next
+/// // command shows the wiring is faulty
+/// battery.set_echo(false);
+/// let mut session = m18.reset()?;
+/// assert!(!session.is_synced());
+/// assert!(matches!(session.get_snapchat(), Err(M18Error::WiringFault { .. })));
+/// # Ok::<(), M18Error>(())
+/// ```
+pub struct EchoCancellingTransport<T> {
+    /// Link that echoes transmitted bytes
+    inner: T,
+}
+
+impl<T: Transport> EchoCancellingTransport<T> {
+    /// Wrap a transport whose receive line also carries its own transmissions.
+    pub fn new(inner: T) -> Self {
+        EchoCancellingTransport { inner }
+    }
+
+    /// Consume the wrapper and return the inner transport.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Transport> Transport for EchoCancellingTransport<T> {
+    /// Write all bytes, then read and verify their echo.
+    ///
+    /// # Errors
+    /// Returns `M18Error::WiringFault` if the echo differs from the bytes sent
+    /// or does not arrive.
+    fn write_all(&mut self, data: &[u8]) -> Result<()> {
+        self.inner.write_all(data)?;
+
+        let mut echoed = vec![0u8; data.len()];
+        let echoed = self.inner.read_exact(&mut echoed).map(|()| echoed);
+        exchange::check_echo(data, echoed)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.inner.read_exact(buf)
+    }
+
+    fn clear_input(&mut self) -> Result<()> {
+        self.inner.clear_input()
+    }
+
+    fn set_break(&mut self, enabled: bool) -> Result<()> {
+        self.inner.set_break(enabled)
+    }
+
+    fn set_dtr(&mut self, level: bool) -> Result<()> {
+        self.inner.set_dtr(level)
+    }
+
+    fn set_rts(&mut self, level: bool) -> Result<()> {
+        self.inner.set_rts(level)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+}
+
+/// Whether a port name refers to a pseudo-terminal: `/dev/ttys*` on macOS,
+/// `/dev/pts/*` on other Unix systems
+pub(crate) fn is_pseudo_terminal(port_name: &str) -> bool {
+    if cfg!(target_os = "macos") {
+        port_name
+            .strip_prefix("/dev/ttys")
+            .is_some_and(|number| !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()))
+    } else {
+        cfg!(unix) && port_name.starts_with("/dev/pts/")
+    }
+}
@@ -3,9 +3,17 @@
 //! This module contains all the data structures used for representing battery data,
 //! including register definitions, health reports, and various data types.
 
+use crate::constants::*;
+use crate::data::RegisterMap;
+use crate::error::ReadFailure;
+use crate::frame::ResponseFrame;
+use crate::histogram::Histogram;
+use crate::protocol::{
+    build_health_report, format_duration, health_report_registers, parse_register_data,
+};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, str::FromStr};
+use std::{collections::HashMap, fmt, str::FromStr};
 
 /// Data types for register interpretation.
 ///
@@ -60,7 +68,7 @@ impl FromStr for DataType {
 ///
 /// Represents a contiguous block of memory in the battery that can be read
 /// in a single command.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MemoryRegion {
     /// High byte of address
     pub address_high: u8,
@@ -70,6 +78,23 @@ pub struct MemoryRegion {
     pub length: u8,
 }
 
+impl MemoryRegion {
+    /// 16-bit start address of the region.
+    pub fn address(&self) -> u16 {
+        u16::from_be_bytes([self.address_high, self.address_low])
+    }
+}
+
+/// Battery family, for registers that only some packs implement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatteryFamily {
+    /// Regular packs (CP, XC, HO, HD)
+    Standard,
+    /// Forge packs with tabless cells
+    Forge,
+}
+
 /// Register definition with metadata.
 ///
 /// Describes how to read and interpret a specific battery register.
@@ -83,8 +108,24 @@ pub struct RegisterDef {
     pub data_type: DataType,
     /// Human-readable description
     pub label: String,
+    /// Name shared by related registers, such as the bins of one histogram
+    pub group: Option<String>,
+    /// Unit of the parsed value
+    pub units: Option<String>,
+    /// Family that implements this register, or `None` for all packs
+    pub family: Option<BatteryFamily>,
 }
 
+impl RegisterDef {
+    /// Whether packs of the given family implement this register.
+    pub fn applies_to(&self, family: BatteryFamily) -> bool {
+        self.family.is_none_or(|f| f == family)
+    }
+}
+
+/// Outcome of reading one register: its value, or why it could not be read.
+pub type RegisterResult = std::result::Result<RegisterValue, ReadFailure>;
+
 /// Parsed register value.
 ///
 /// Represents a battery register value after parsing from raw bytes.
@@ -129,12 +170,12 @@ pub struct HealthReport {
     pub electronic_serial: u32,
     /// When battery was manufactured
     pub manufacture_date: DateTime<Utc>,
-    /// Days since battery was first charged
-    pub days_since_first_charge: u16,
-    /// Days since battery was last used in a tool
-    pub days_since_last_tool_use: i64,
-    /// Days since battery was last charged
-    pub days_since_last_charge: i64,
+    /// Days since battery was first charged, if known
+    pub days_since_first_charge: Option<u16>,
+    /// Days since battery was last used in a tool, by the battery clock
+    pub days_since_last_tool_use: Option<i64>,
+    /// Days since battery was last charged, by the battery clock
+    pub days_since_last_charge: Option<i64>,
     /// Total pack voltage in volts
     pub pack_voltage: f64,
     /// Individual cell voltages in millivolts
@@ -147,60 +188,341 @@ pub struct HealthReport {
     pub charging_stats: ChargingStats,
     /// Tool usage statistics
     pub usage_stats: UsageStats,
-    /// Histogram of discharge current over battery lifetime
-    pub discharge_histogram: Vec<DischargeHistogramEntry>,
+    /// Time spent at each discharge current over battery lifetime, in 10A bins
+    pub discharge_histogram: Histogram,
+}
+
+impl HealthReport {
+    /// Build a health report from raw memory, without a battery connection.
+    ///
+    /// Produces the same report as [`M18::health_report`](crate::M18::health_report)
+    /// for the same memory contents, so saved captures and fixtures can be
+    /// turned into reports offline. Fields whose registers are not covered by
+    /// `raw` are reported as unknown.
+    ///
+    /// # Arguments
+    /// * `raw` - Memory chunks as `(address, data)`, as returned by
+    ///   [`M18::read_all_raw`](crate::M18::read_all_raw)
+    /// * `now` - Report timestamp
+    /// * `register_map` - Map the memory was read with
+    ///
+    /// # Errors
+    /// Returns error if registers required by the report are missing.
+    ///
+    /// # Examples
+    /// ```
+    /// use chrono::{TimeZone, Utc};
+    /// use m18_protocol::{emulator::VirtualBattery, HealthReport, RegisterMap};
+    ///
+    /// let battery = VirtualBattery::new();
+    /// let map = RegisterMap::embedded();
+    /// let raw: Vec<(u16, Vec<u8>)> = map
+    ///     .regions()
+    ///     .iter()
+    ///     .filter_map(|region| {
+    ///         let data = battery.memory(region.address(), region.length as u16)?;
+    ///         Some((region.address(), data))
+    ///     })
+    ///     .collect();
+    ///
+    /// let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    /// let report = HealthReport::from_raw(&raw, now, map)?;
+    /// assert_eq!(report.timestamp, now);
+    /// assert_eq!(report.electronic_serial, 1234567);
+    ///
+    /// // Without the lifetime counters those fields are unknown, not zero
+    /// let partial: Vec<_> = raw.into_iter().filter(|(address, _)| *address < 0x9000).collect();
+    /// let report = HealthReport::from_raw(&partial, now, map)?;
+    /// assert_eq!(report.charging_stats.total_charge_count, None);
+    /// # Ok::<(), m18_protocol::M18Error>(())
+    /// ```
+    pub fn from_raw(
+        raw: &[(u16, Vec<u8>)],
+        now: DateTime<Utc>,
+        register_map: &RegisterMap,
+    ) -> crate::Result<Self> {
+        let results: HashMap<usize, RegisterResult> = health_report_registers()
+            .into_iter()
+            .filter_map(|selector| {
+                let id = register_map.resolve(&selector)?;
+                let register = &register_map.registers()[id];
+                let length = register.length as usize;
+                let data = raw.iter().find_map(|(address, data)| {
+                    let offset = register.address.checked_sub(*address)? as usize;
+                    data.get(offset..offset + length)
+                });
+                let result = match data {
+                    Some(data) => parse_register_data(register, data).map_err(ReadFailure::from),
+                    None => Err(ReadFailure::Unavailable),
+                };
+                Some((id, result))
+            })
+            .collect();
+
+        build_health_report(&results, register_map, &create_battery_lookup(), now)
+    }
+}
+
+impl fmt::Display for HealthReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Type: {} [{}]",
+            self.battery_type, self.battery_description
+        )?;
+        writeln!(
+            f,
+            "E-serial: {} (does NOT match case serial)",
+            self.electronic_serial
+        )?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "Manufacture date: {}",
+            self.manufacture_date.format("%Y-%m-%d")
+        )?;
+        writeln!(
+            f,
+            "Days since 1st charge: {}",
+            or_unknown(&self.days_since_first_charge)
+        )?;
+        writeln!(
+            f,
+            "Days since last tool use: {}",
+            or_unknown(&self.days_since_last_tool_use)
+        )?;
+        writeln!(
+            f,
+            "Days since last charge: {}",
+            or_unknown(&self.days_since_last_charge)
+        )?;
+        writeln!(f, "Pack voltage: {:.2}V", self.pack_voltage)?;
+        writeln!(f, "Cell Voltages (mV): {:?}", self.cell_voltages)?;
+        writeln!(f, "Cell Imbalance (mV): {}", self.cell_imbalance)?;
+
+        if let Some(temp) = self.temperature {
+            writeln!(f, "Temperature (deg C): {:.2}", temp)?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "CHARGING STATS:")?;
+        writeln!(
+            f,
+            "Charge count [Redlink, dumb, (total)]: {}, {}, ({})",
+            or_unknown(&self.charging_stats.redlink_charge_count),
+            or_unknown(&self.charging_stats.dumb_charge_count),
+            or_unknown(&self.charging_stats.total_charge_count)
+        )?;
+        writeln!(
+            f,
+            "Total charge time: {}",
+            or_unknown(&self.charging_stats.total_charge_time)
+        )?;
+        writeln!(
+            f,
+            "Time idling on charger: {}",
+            or_unknown(&self.charging_stats.time_idling_on_charger)
+        )?;
+        writeln!(
+            f,
+            "Low-voltage charges (any cell <2.5V): {}",
+            or_unknown(&self.charging_stats.low_voltage_charges)
+        )?;
+
+        writeln!(f)?;
+        writeln!(f, "TOOL USE STATS:")?;
+        writeln!(
+            f,
+            "Total discharge (Ah): {}",
+            or_unknown(
+                &self
+                    .usage_stats
+                    .total_discharge_ah
+                    .map(|ah| format!("{:.2}", ah))
+            )
+        )?;
+        writeln!(
+            f,
+            "Total discharge cycles: {}",
+            or_unknown(
+                &self
+                    .usage_stats
+                    .total_discharge_cycles
+                    .map(|cycles| format!("{:.2}", cycles))
+            )
+        )?;
+        writeln!(
+            f,
+            "Times discharged to empty: {}",
+            or_unknown(&self.usage_stats.times_discharged_to_empty)
+        )?;
+        writeln!(
+            f,
+            "Times overheated: {}",
+            or_unknown(&self.usage_stats.times_overheated)
+        )?;
+        writeln!(
+            f,
+            "Overcurrent events: {}",
+            or_unknown(&self.usage_stats.overcurrent_events)
+        )?;
+        writeln!(
+            f,
+            "Low-voltage events: {}",
+            or_unknown(&self.usage_stats.low_voltage_events)
+        )?;
+        writeln!(
+            f,
+            "Low-voltage bounce/stutter: {}",
+            or_unknown(&self.usage_stats.low_voltage_bounce)
+        )?;
+        writeln!(
+            f,
+            "Total time on tool (>10A): {}",
+            or_unknown(&self.usage_stats.total_time_on_tool)
+        )?;
+
+        writeln!(f)?;
+        writeln!(f, "DISCHARGE HISTOGRAM:")?;
+        let histogram = &self.discharge_histogram;
+        for (bin, percentage) in histogram.bins.iter().zip(histogram.percentages()) {
+            let percentage = percentage.map(|p| p.round() as u8);
+            let bar = "X".repeat(percentage.unwrap_or(0) as usize);
+            writeln!(
+                f,
+                "Time @ {:>8}: {} {:>2}% {}",
+                bin.label(&histogram.units),
+                or_unknown(&bin.seconds.map(format_duration)),
+                or_unknown(&percentage),
+                bar
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Value for display, or "unknown" if it could not be read
+fn or_unknown<T: fmt::Display>(value: &Option<T>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => "unknown".to_string(),
+    }
 }
 
 /// Battery charging statistics.
+///
+/// Each field is `None` if its register could not be read.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChargingStats {
     /// Number of charges using Redlink (UART) protocol
-    pub redlink_charge_count: u16,
+    pub redlink_charge_count: Option<u16>,
     /// Number of "dumb" charges (voltage-based only)
-    pub dumb_charge_count: u16,
+    pub dumb_charge_count: Option<u16>,
     /// Total number of charge cycles
-    pub total_charge_count: u16,
+    pub total_charge_count: Option<u16>,
     /// Total time spent charging (HH:MM:SS)
-    pub total_charge_time: String,
+    pub total_charge_time: Option<String>,
     /// Time spent on charger after reaching full charge (HH:MM:SS)
-    pub time_idling_on_charger: String,
+    pub time_idling_on_charger: Option<String>,
     /// Number of times charged when any cell was below 2.5V
-    pub low_voltage_charges: u16,
+    pub low_voltage_charges: Option<u16>,
 }
 
 /// Battery usage statistics.
+///
+/// Each field is `None` if its registers could not be read.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UsageStats {
     /// Total amp-hours discharged over battery lifetime
-    pub total_discharge_ah: f64,
+    pub total_discharge_ah: Option<f64>,
     /// Equivalent full discharge cycles (total_discharge_ah / capacity)
-    pub total_discharge_cycles: f64,
+    pub total_discharge_cycles: Option<f64>,
     /// Number of times battery was completely drained
-    pub times_discharged_to_empty: u16,
+    pub times_discharged_to_empty: Option<u16>,
     /// Number of overheat events during tool use
-    pub times_overheated: u16,
+    pub times_overheated: Option<u16>,
     /// Number of overcurrent protection events
-    pub overcurrent_events: u16,
+    pub overcurrent_events: Option<u16>,
     /// Number of low-voltage protection events
-    pub low_voltage_events: u16,
+    pub low_voltage_events: Option<u16>,
     /// Number of low-voltage "bounce" events (4 flashing LEDs)
-    pub low_voltage_bounce: u16,
-    /// Total time on tool drawing >10A (HH:MM:SS)
-    pub total_time_on_tool: String,
+    pub low_voltage_bounce: Option<u16>,
+    /// Total time on tool drawing >10A (HH:MM:SS); unknown if any bin is
+    pub total_time_on_tool: Option<String>,
 }
 
-/// Single entry in discharge current histogram.
+/// Decoded reply to a `Snapshot` command.
 ///
-/// The battery tracks how much time it spent discharging at different
-/// current levels, creating a histogram of usage patterns.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DischargeHistogramEntry {
-    /// Current range (e.g., "10-20A", "> 200A")
-    pub current_range: String,
-    /// Time spent in this range (HH:MM:SS)
-    pub duration: String,
-    /// Percentage of total tool use time
-    pub percentage: u8,
+/// The snapshot payload is only partly understood. `raw` is the authoritative
+/// record of the reply. The decoded fields are provisional: their offsets are
+/// a guess that has not been checked against a real pack, and may change.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotResponse {
+    /// Battery state flags (byte 0, provisional)
+    pub state_flags: u8,
+    /// Pack temperature in Celsius (byte 1, provisional)
+    pub temperature: f64,
+    /// Full payload as received; the authoritative record of the reply
+    pub raw: Vec<u8>,
+}
+
+impl SnapshotResponse {
+    /// Decode a snapshot reply frame.
+    ///
+    /// # Errors
+    /// Returns `M18Error::InvalidResponse` if the payload is shorter than 3 bytes.
+    pub fn decode(frame: &ResponseFrame) -> Result<Self, crate::M18Error> {
+        let payload = frame.payload();
+        if payload.len() < 3 {
+            return Err(crate::M18Error::InvalidResponse {
+                expected: "snapshot payload of 3 bytes".to_string(),
+                actual: format!("{:02X?}", frame.encode()),
+            });
+        }
+
+        Ok(SnapshotResponse {
+            state_flags: payload[0],
+            temperature: payload[1] as f64,
+            raw: payload.to_vec(),
+        })
+    }
+}
+
+/// Decoded reply to a `Keepalive` command.
+///
+/// As with [`SnapshotResponse`], `raw` is authoritative and the decoded
+/// fields are provisional guesses at the layout.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeepaliveResponse {
+    /// Charge current requested by the battery in milliamps (bytes 0-1, provisional)
+    pub requested_current: u16,
+    /// Total pack voltage in volts (bytes 2-3 as millivolts, provisional)
+    pub pack_voltage: f64,
+    /// Full payload as received; the authoritative record of the reply
+    pub raw: Vec<u8>,
+}
+
+impl KeepaliveResponse {
+    /// Decode a keepalive reply frame.
+    ///
+    /// # Errors
+    /// Returns `M18Error::InvalidResponse` if the payload is shorter than 4 bytes.
+    pub fn decode(frame: &ResponseFrame) -> Result<Self, crate::M18Error> {
+        let payload = frame.payload();
+        if payload.len() < 4 {
+            return Err(crate::M18Error::InvalidResponse {
+                expected: "keepalive payload of 4 bytes".to_string(),
+                actual: format!("{:02X?}", frame.encode()),
+            });
+        }
+
+        Ok(KeepaliveResponse {
+            requested_current: u16::from_be_bytes([payload[0], payload[1]]),
+            pack_voltage: u16::from_be_bytes([payload[2], payload[3]]) as f64 / 1000.0,
+            raw: payload.to_vec(),
+        })
+    }
 }
 
 /// Battery type information.
@@ -266,6 +588,136 @@ impl From<ChargeState> for u8 {
     }
 }
 
+/// Charger parameters sent with the `Configure` command.
+///
+/// The configure payload is `[cutoff(2), max(2), max_secondary(2), state, trailer]`.
+/// The purpose of the second current limit and the trailing byte is unknown;
+/// chargers seen so far send the same value in both current fields and 13 as
+/// the trailer. The `8` that precedes the payload on the wire is its length
+/// and is filled in by the frame codec.
+///
+/// # Examples
+/// ```
+/// use m18_protocol::ChargerProfile;
+///
+/// let profile = ChargerProfile::super_charger();
+/// assert!(profile.validate().is_ok());
+///
+/// let unsafe_profile = ChargerProfile {
+///     max_current: 20000,
+///     ..ChargerProfile::standard()
+/// };
+/// assert!(unsafe_profile.validate().is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChargerProfile {
+    /// Current at which charging stops, in milliamps
+    pub cutoff_current: u16,
+    /// Maximum charge current, in milliamps
+    pub max_current: u16,
+    /// Second maximum current field, in milliamps
+    pub max_current_secondary: u16,
+    /// Unknown trailing byte
+    pub trailer: u8,
+}
+
+impl ChargerProfile {
+    /// Profile the library has always sent (300 mA cutoff, 6 A maximum).
+    pub fn standard() -> Self {
+        ChargerProfile {
+            cutoff_current: CUTOFF_CURRENT,
+            max_current: MAX_CURRENT,
+            max_current_secondary: MAX_CURRENT,
+            trailer: 13,
+        }
+    }
+
+    /// Compact multi-voltage charger (nominal 3 A output).
+    pub fn compact() -> Self {
+        Self::with_max_current(3000)
+    }
+
+    /// Rapid charger (nominal 8 A output).
+    pub fn rapid() -> Self {
+        Self::with_max_current(8000)
+    }
+
+    /// Super charger (nominal 12 A output).
+    pub fn super_charger() -> Self {
+        Self::with_max_current(12000)
+    }
+
+    /// Standard profile with both current limits set to `max_current`
+    fn with_max_current(max_current: u16) -> Self {
+        ChargerProfile {
+            max_current,
+            max_current_secondary: max_current,
+            ..Self::standard()
+        }
+    }
+
+    /// Check that every current is inside the range considered safe to send.
+    ///
+    /// # Errors
+    /// Returns `M18Error::ChargerProfileOutOfRange` naming the first field that
+    /// is out of range. The cutoff current must also be below both maximums.
+    pub fn validate(&self) -> Result<(), crate::M18Error> {
+        let check = |field: &'static str, value: u16, min: u16, max: u16| {
+            if (min..=max).contains(&value) {
+                Ok(())
+            } else {
+                Err(crate::M18Error::ChargerProfileOutOfRange {
+                    field,
+                    value,
+                    min,
+                    max,
+                })
+            }
+        };
+
+        check(
+            "max_current",
+            self.max_current,
+            MIN_CHARGE_CURRENT,
+            MAX_CHARGE_CURRENT,
+        )?;
+        check(
+            "max_current_secondary",
+            self.max_current_secondary,
+            MIN_CHARGE_CURRENT,
+            MAX_CHARGE_CURRENT,
+        )?;
+        // The cutoff must also sit below the lower of the two maximums
+        let max_current = self.max_current.min(self.max_current_secondary);
+        check(
+            "cutoff_current",
+            self.cutoff_current,
+            MIN_CUTOFF_CURRENT,
+            MAX_CUTOFF_CURRENT.min(max_current - 1),
+        )
+    }
+
+    /// Build the `Configure` payload for this profile.
+    ///
+    /// # Arguments
+    /// * `state` - Charging state to request
+    pub fn encode(&self, state: ChargeState) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(8);
+        payload.extend_from_slice(&self.cutoff_current.to_be_bytes());
+        payload.extend_from_slice(&self.max_current.to_be_bytes());
+        payload.extend_from_slice(&self.max_current_secondary.to_be_bytes());
+        payload.push(state.into());
+        payload.push(self.trailer);
+        payload
+    }
+}
+
+impl Default for ChargerProfile {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
 /// Output format for printing register data.
 #[derive(Debug, Clone, Copy)]
 pub enum OutputFormat {